@@ -0,0 +1,50 @@
+use std::hint::black_box;
+
+use calculator::{
+    ast::{environment::Environment, parse_expression, value::Value},
+    vm::compile_expression,
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+const EXPRESSION: &str = "x^2 + 3*x*y - sin(y) / (1 + x)";
+
+fn tree(c: &mut Criterion) {
+    let expr = parse_expression(EXPRESSION).unwrap();
+    let mut env = Environment::new();
+    c.bench_function("tree", |b| {
+        b.iter(|| {
+            env.set("x", Value::Number(black_box(1.5)));
+            env.set("y", Value::Number(black_box(2.5)));
+            expr.calculate(&env).unwrap()
+        })
+    });
+}
+
+fn compiled(c: &mut Criterion) {
+    let compiled = compile_expression(EXPRESSION, &["x", "y"], &Environment::new()).unwrap();
+    c.bench_function("compiled", |b| {
+        b.iter(|| compiled.eval(black_box(&[1.5, 2.5])).unwrap())
+    });
+}
+
+fn batch(c: &mut Criterion) {
+    let compiled = compile_expression(EXPRESSION, &["x", "y"], &Environment::new()).unwrap();
+    let xs: Vec<f64> = (0..100_000).map(|i| f64::from(i) / 1000.0).collect();
+    let ys: Vec<f64> = xs.iter().map(|x| x * 0.5).collect();
+    let mut group = c.benchmark_group("100000 rows");
+    group.bench_function("eval", |b| {
+        b.iter(|| {
+            xs.iter()
+                .zip(&ys)
+                .map(|(x, y)| compiled.eval(&[*x, *y]).unwrap())
+                .collect::<Vec<f64>>()
+        })
+    });
+    group.bench_function("eval_batch", |b| {
+        b.iter(|| compiled.eval_batch(black_box(&[&xs, &ys])).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tree, compiled, batch);
+criterion_main!(benches);
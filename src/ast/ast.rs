@@ -1,42 +1,888 @@
-use crate::utils::operators::BinaryOperator;
+use std::convert::Infallible;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::{
+    ast::{
+        closure::{self, Closure},
+        derivative,
+        environment::Environment,
+        equation, functions, linear,
+        matrix::{self, Matrix},
+        numeric, statistics,
+        trace::Trace,
+        value::Value,
+        visit::{Visitor, visit},
+    },
+    datetime::DateTime,
+    units::{self, quantity::Quantity, registry},
+    utils::{error::CalcError, operators::BinaryOperator, span::Span},
+};
+
+#[derive(Debug)]
 pub enum Node {
     Operand {
         value: f64,
     },
+    Variable {
+        name: String,
+        span: Span,
+    },
+    Date {
+        value: DateTime,
+    },
     BinaryExpr {
         operation: BinaryOperator,
         lhs: Box<Node>,
         rhs: Box<Node>,
     },
+    /// The operand with its sign flipped, written `-x`.
+    Negation {
+        operand: Box<Node>,
+    },
+    Function {
+        name: String,
+        args: Vec<Node>,
+        span: Span,
+    },
+    /// Items written between braces, such as numbers to summarise or the equations and unknowns given to `solve`.
+    List {
+        items: Vec<Node>,
+        span: Span,
+    },
+    /// Rows of entries written between square brackets, like `[1, 2; 3, 4]`.
+    Matrix {
+        rows: Vec<Vec<Node>>,
+        span: Span,
+    },
+    /// A function of its parameters, like `x -> x^2` or `(a, b) -> a + b`.
+    Lambda {
+        params: Vec<String>,
+        body: Box<Node>,
+        span: Span,
+    },
+}
+
+/// Where a [`Node`] and each of its children came from in the input, in the same shape as the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    pub fn leaf(span: Span) -> SpanTree {
+        SpanTree {
+            span,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Takes a tree apart one node at a time, so that dropping a deeply nested expression cannot
+/// overflow the stack.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_branches(&mut pending);
+        while let Some(mut node) = pending.pop() {
+            node.take_branches(&mut pending);
+        }
+    }
+}
+
+/// Copies a tree one node at a time, for the same reason.
+impl Clone for Node {
+    fn clone(&self) -> Node {
+        struct Copier;
+        impl Visitor<'_> for Copier {
+            type Output = Node;
+            type Error = Infallible;
+
+            fn visit_node(&mut self, node: &Node, outputs: Vec<Node>) -> Result<Node, Infallible> {
+                let mut children = outputs.into_iter();
+                Ok(match node {
+                    Node::Operand { value } => Node::Operand { value: *value },
+                    Node::Variable { name, span } => Node::Variable {
+                        name: name.clone(),
+                        span: *span,
+                    },
+                    Node::Date { value } => Node::Date { value: *value },
+                    Node::BinaryExpr { operation, .. } => {
+                        let lhs = children.next().unwrap();
+                        Node::binary(*operation, lhs, children.next().unwrap())
+                    }
+                    Node::Negation { .. } => Node::negation(children.next().unwrap()),
+                    Node::Function { name, span, .. } => Node::Function {
+                        name: name.clone(),
+                        args: children.collect(),
+                        span: *span,
+                    },
+                    Node::List { span, .. } => Node::List {
+                        items: children.collect(),
+                        span: *span,
+                    },
+                    Node::Matrix { rows, span } => Node::Matrix {
+                        rows: rows
+                            .iter()
+                            .map(|row| children.by_ref().take(row.len()).collect())
+                            .collect(),
+                        span: *span,
+                    },
+                    Node::Lambda { params, span, .. } => Node::Lambda {
+                        params: params.clone(),
+                        body: Box::new(children.next().unwrap()),
+                        span: *span,
+                    },
+                })
+            }
+        }
+        let Ok(node) = visit(&mut Copier, self);
+        node
+    }
+}
+
+/// Compares two trees one pair of nodes at a time, for the same reason.
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let same = match pair {
+                (Node::Operand { value: a }, Node::Operand { value: b }) => a == b,
+                (
+                    Node::Variable {
+                        name: a,
+                        span: a_span,
+                    },
+                    Node::Variable {
+                        name: b,
+                        span: b_span,
+                    },
+                ) => a == b && a_span == b_span,
+                (Node::Date { value: a }, Node::Date { value: b }) => a == b,
+                (
+                    Node::BinaryExpr {
+                        operation: a,
+                        lhs: a_lhs,
+                        rhs: a_rhs,
+                    },
+                    Node::BinaryExpr {
+                        operation: b,
+                        lhs: b_lhs,
+                        rhs: b_rhs,
+                    },
+                ) => {
+                    pending.extend([(&**a_lhs, &**b_lhs), (&**a_rhs, &**b_rhs)]);
+                    a == b
+                }
+                (Node::Negation { operand: a }, Node::Negation { operand: b }) => {
+                    pending.push((a, b));
+                    true
+                }
+                (
+                    Node::Function {
+                        name: a,
+                        args: a_args,
+                        span: a_span,
+                    },
+                    Node::Function {
+                        name: b,
+                        args: b_args,
+                        span: b_span,
+                    },
+                ) => {
+                    pending.extend(a_args.iter().zip(b_args));
+                    a == b && a_args.len() == b_args.len() && a_span == b_span
+                }
+                (
+                    Node::List {
+                        items: a,
+                        span: a_span,
+                    },
+                    Node::List {
+                        items: b,
+                        span: b_span,
+                    },
+                ) => {
+                    pending.extend(a.iter().zip(b));
+                    a.len() == b.len() && a_span == b_span
+                }
+                (
+                    Node::Matrix {
+                        rows: a,
+                        span: a_span,
+                    },
+                    Node::Matrix {
+                        rows: b,
+                        span: b_span,
+                    },
+                ) => {
+                    pending.extend(a.iter().flatten().zip(b.iter().flatten()));
+                    a.len() == b.len()
+                        && a.iter().zip(b).all(|(a, b)| a.len() == b.len())
+                        && a_span == b_span
+                }
+                (
+                    Node::Lambda {
+                        params: a,
+                        body: a_body,
+                        span: a_span,
+                    },
+                    Node::Lambda {
+                        params: b,
+                        body: b_body,
+                        span: b_span,
+                    },
+                ) => {
+                    pending.push((a_body, b_body));
+                    a == b && a_span == b_span
+                }
+                _ => false,
+            };
+            if !same {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Drop for SpanTree {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut tree) = pending.pop() {
+            pending.append(&mut tree.children);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Assignment { name: String, expr: Node },
+    Expression(Node),
 }
 
 impl Node {
-    pub fn calculate(&self) -> Result<f64, String> {
+    pub fn binary(operation: BinaryOperator, lhs: Node, rhs: Node) -> Node {
+        Node::BinaryExpr {
+            operation,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    pub fn negation(operand: Node) -> Node {
+        Node::Negation {
+            operand: Box::new(operand),
+        }
+    }
+
+    /// The operands of a binary expression, taken out of it.
+    pub fn into_operands(mut self) -> (Node, Node) {
+        let leaf = || Box::new(Node::Operand { value: 0.0 });
+        match &mut self {
+            Node::BinaryExpr { lhs, rhs, .. } => (
+                *std::mem::replace(lhs, leaf()),
+                *std::mem::replace(rhs, leaf()),
+            ),
+            _ => panic!("{} is not a binary expression", self),
+        }
+    }
+
+    /// A call to `name`, as built by code rather than parsed, so with an empty span.
+    pub fn call(name: &str, args: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            args,
+            span: Span::default(),
+        }
+    }
+
+    /// Where the expression came from in the input, as far as its names and brackets tell.
+    /// Numbers and dates do not keep their place, so an expression of only those has none.
+    /// An operation runs from the start of the first part of it with a span to the end of the last.
+    pub fn span(&self) -> Option<Span> {
+        let (mut first, mut last) = (None, None);
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            let span = match node {
+                Node::Variable { span, .. }
+                | Node::Function { span, .. }
+                | Node::List { span, .. }
+                | Node::Matrix { span, .. }
+                | Node::Lambda { span, .. } => *span,
+                Node::BinaryExpr { lhs, rhs, .. } => {
+                    pending.push(rhs);
+                    pending.push(lhs);
+                    continue;
+                }
+                Node::Negation { operand } => {
+                    pending.push(operand);
+                    continue;
+                }
+                Node::Operand { .. } | Node::Date { .. } => continue,
+            };
+            first.get_or_insert(span);
+            last = Some(span);
+        }
+        Some(Span::new(first?.start, last?.end))
+    }
+
+    /// How many levels of operations, calls, lists and closures the tree has, so that a number
+    /// is none deep and `1 + 2 * 3` is two.
+    pub fn depth(&self) -> usize {
+        struct Depth;
+        impl Visitor<'_> for Depth {
+            type Output = usize;
+            type Error = Infallible;
+
+            fn visit_node(
+                &mut self,
+                _node: &Node,
+                outputs: Vec<usize>,
+            ) -> Result<usize, Self::Error> {
+                Ok(outputs.into_iter().max().map_or(0, |deepest| deepest + 1))
+            }
+        }
+        let Ok(depth) = visit(&mut Depth, self);
+        depth
+    }
+
+    /// How many nodes the tree has.
+    pub fn size(&self) -> usize {
+        struct Size;
+        impl Visitor<'_> for Size {
+            type Output = usize;
+            type Error = Infallible;
+
+            fn visit_node(
+                &mut self,
+                _node: &Node,
+                outputs: Vec<usize>,
+            ) -> Result<usize, Self::Error> {
+                Ok(outputs.into_iter().sum::<usize>() + 1)
+            }
+        }
+        let Ok(size) = visit(&mut Size, self);
+        size
+    }
+
+    /// Moves the children that have children of their own into `pending`, leaving numbers in
+    /// their place, so that dropping this node only drops leaves.
+    fn take_branches(&mut self, pending: &mut Vec<Node>) {
+        let mut take = |child: &mut Node| {
+            let is_leaf = match child {
+                Node::BinaryExpr { .. } | Node::Negation { .. } | Node::Lambda { .. } => false,
+                Node::Function { args: items, .. } | Node::List { items, .. } => items.is_empty(),
+                Node::Matrix { rows, .. } => rows.is_empty(),
+                _ => true,
+            };
+            if !is_leaf {
+                pending.push(std::mem::replace(child, Node::Operand { value: 0.0 }));
+            }
+        };
         match self {
-            Node::Operand { value } => Ok(*value),
+            Node::BinaryExpr { lhs, rhs, .. } => {
+                take(lhs);
+                take(rhs);
+            }
+            Node::Negation { operand: body } | Node::Lambda { body, .. } => take(body),
+            Node::Function { args: items, .. } | Node::List { items, .. } => {
+                items.iter_mut().for_each(take)
+            }
+            Node::Matrix { rows, .. } => rows.iter_mut().flatten().for_each(take),
+            _ => {}
+        }
+    }
+
+    pub fn calculate(&self, env: &Environment) -> Result<Value, CalcError> {
+        self.evaluate(env, None)
+    }
+
+    /// Like [`Node::calculate`], but records each reduction in `trace` as it happens.
+    pub fn calculate_traced(
+        &self,
+        env: &Environment,
+        trace: &mut Trace,
+    ) -> Result<Value, CalcError> {
+        self.evaluate(env, Some(trace))
+    }
+
+    fn evaluate(&self, env: &Environment, trace: Option<&mut Trace>) -> Result<Value, CalcError> {
+        let mut evaluator = Evaluator {
+            env,
+            trace,
+            path: Vec::new(),
+        };
+        let result = visit(&mut evaluator, self);
+        // leave the children the trace was inside when the error came
+        if result.is_err()
+            && let Some(trace) = evaluator.trace
+        {
+            for _ in evaluator.path.iter().filter(|(_, entered)| *entered) {
+                trace.leave();
+            }
+        }
+        result
+    }
+
+    fn is_conversion(&self) -> bool {
+        matches!(
+            self,
             Node::BinaryExpr {
-                operation,
-                lhs,
-                rhs,
-            } => {
-                let lhs = match lhs.calculate() {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
-                let rhs = match rhs.calculate() {
-                    Ok(value) => value,
-                    Err(e) => return Err(e),
-                };
-                match operation {
-                    BinaryOperator::Addition => Ok(lhs + rhs),
-                    BinaryOperator::Subtraction => Ok(lhs - rhs),
-                    BinaryOperator::Multiplication => Ok(lhs * rhs),
-                    BinaryOperator::Division => Ok(lhs / rhs),
-                    BinaryOperator::Index => Ok(lhs.powf(rhs)),
+                operation: BinaryOperator::Conversion,
+                ..
+            }
+        )
+    }
+
+    /// The value of an entry of a matrix or item of a list, which must be a plain number.
+    fn entry(&self, value: Value, container: &str) -> Result<f64, CalcError> {
+        match value {
+            Value::Number(value) => Ok(value),
+            other => Err(CalcError {
+                message: format!("A {} holds plain numbers, found {}", container, other),
+                span: self.span(),
+                limit: None,
+            }),
+        }
+    }
+
+    /// Whether every name in the expression has a value in `env`.
+    pub fn is_closed(&self, env: &Environment) -> bool {
+        // the scopes made for the parameters of closures, which each node is in one of, if any
+        let mut scopes: Vec<Environment> = Vec::new();
+        let mut pending = vec![(self, None)];
+        while let Some((node, scope)) = pending.pop() {
+            let in_scope = scope.map_or(env, |scope| &scopes[scope]);
+            match node {
+                Node::Operand { .. } | Node::Date { .. } => {}
+                Node::Variable { name, span } => {
+                    if resolve(name, *span, in_scope).is_err() {
+                        return false;
+                    }
+                }
+                Node::BinaryExpr { lhs, rhs, .. } => {
+                    pending.extend([(&**lhs, scope), (&**rhs, scope)]);
+                }
+                Node::Negation { operand } => pending.push((operand, scope)),
+                Node::Function { args, .. } | Node::List { items: args, .. } => {
+                    pending.extend(args.iter().map(|arg| (arg, scope)));
+                }
+                Node::Matrix { rows, .. } => {
+                    pending.extend(rows.iter().flatten().map(|entry| (entry, scope)));
+                }
+                Node::Lambda { params, body, .. } => {
+                    let mut inner = in_scope.clone();
+                    for param in params {
+                        inner.set(param.as_str(), Value::Number(0.0));
+                    }
+                    scopes.push(inner);
+                    pending.push((body, Some(scopes.len() - 1)));
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Works out the value of a tree as a [`Visitor`], recording each reduction in the trace if there
+/// is one.
+struct Evaluator<'e, 't> {
+    env: &'e Environment,
+    trace: Option<&'t mut Trace>,
+    /// For each node entered but not yet left, whether its steps are recorded in the trace and
+    /// whether the trace entered it.
+    path: Vec<(bool, bool)>,
+}
+
+impl Evaluator<'_, '_> {
+    /// Whether the node being visited records its steps in the trace.
+    fn traced(&self) -> bool {
+        self.path.last().is_some_and(|(traced, _)| *traced)
+    }
+
+    /// Counts a node against the budget, if there is one.
+    fn step(&self) -> Result<(), CalcError> {
+        match self.env.budget() {
+            Some(budget) => budget.step().map_err(CalcError::from),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts each entry of a list or matrix against the budget, as the work done with them grows
+    /// with their size.
+    fn charge(&self, value: &Value) -> Result<(), CalcError> {
+        let entries = match value {
+            Value::List(items) => items.len(),
+            Value::Matrix(matrix) => matrix.entries().len(),
+            _ => return Ok(()),
+        };
+        match self.env.budget() {
+            Some(budget) => budget.charge(entries as u64).map_err(CalcError::from),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, value: &Value) {
+        if self.traced()
+            && let Some(trace) = self.trace.as_deref_mut()
+        {
+            trace.record(value.clone());
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Evaluator<'_, '_> {
+    type Output = Value;
+    type Error = CalcError;
+
+    fn enter(&mut self, _node: &'a Node, parent: Option<(&'a Node, usize)>) {
+        let traced = match parent {
+            None => true,
+            // list and matrix entries and conversion targets are not steps of their own
+            Some((parent, index)) => {
+                self.traced()
+                    && matches!(
+                        parent,
+                        Node::BinaryExpr { .. } | Node::Negation { .. } | Node::Function { .. }
+                    )
+                    && !(index == 1 && parent.is_conversion())
+            }
+        };
+        let entered = match (parent, self.trace.as_deref_mut()) {
+            (Some((_, index)), Some(trace)) if traced => {
+                trace.enter(index);
+                true
+            }
+            _ => false,
+        };
+        self.path.push((traced, entered));
+    }
+
+    fn leave(&mut self, _node: &'a Node) {
+        if let Some((_, true)) = self.path.pop()
+            && let Some(trace) = self.trace.as_deref_mut()
+        {
+            trace.leave();
+        }
+    }
+
+    /// Equations work out their own sides.
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        match operation {
+            BinaryOperator::Equation => vec![],
+            _ => vec![lhs, rhs],
+        }
+    }
+
+    /// Calls that bind a variable work out their own arguments, and unknown functions have none
+    /// worth working out.
+    fn walk_function(&mut self, name: &'a str, args: &'a [Node]) -> Vec<&'a Node> {
+        if functions::is_special_form(name) {
+            vec![]
+        } else if functions::is_matrix_function(name)
+            || closure::is_higher_order(name)
+            || statistics::is_statistic(name)
+        {
+            args.iter().collect()
+        } else if functions::builtin(name).is_some() {
+            args[..1].iter().collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// The body is worked out each time the closure is called.
+    fn walk_lambda(&mut self, _params: &'a [String], _body: &'a Node) -> Vec<&'a Node> {
+        vec![]
+    }
+
+    /// Every kind of node has its own `visit_*`, so this is only reached by a kind added later.
+    fn visit_node(&mut self, node: &'a Node, _outputs: Vec<Value>) -> Result<Value, CalcError> {
+        Err(CalcError {
+            span: node.span(),
+            ..CalcError::new("Unsupported node")
+        })
+    }
+
+    fn visit_operand(&mut self, _node: &'a Node, value: f64) -> Result<Value, CalcError> {
+        self.step()?;
+        Ok(Value::Number(value))
+    }
+
+    fn visit_variable(
+        &mut self,
+        _node: &'a Node,
+        name: &'a str,
+        span: Span,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        let value = resolve(name, span, self.env)?;
+        // units are left as written, only substituted values count as a step
+        if self.env.get(name).is_some() || !matches!(value, Value::Quantity(_)) {
+            self.record(&value);
+        }
+        Ok(value)
+    }
+
+    fn visit_date(&mut self, _node: &'a Node, value: &'a DateTime) -> Result<Value, CalcError> {
+        self.step()?;
+        Ok(Value::DateTime(value.in_mode(self.env.time_mode())))
+    }
+
+    fn visit_binary(
+        &mut self,
+        node: &'a Node,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+        outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        let mut operands = outputs.into_iter();
+        let result = match operation {
+            BinaryOperator::Equation => equation::solve_equation(lhs, rhs, self.env)?,
+            BinaryOperator::Conversion => {
+                let (value, target) = (operands.next().unwrap(), operands.next().unwrap());
+                value.convert(target, Some(units::label(rhs)))?
+            }
+            _ => {
+                let (lhs_value, rhs_value) = (operands.next().unwrap(), operands.next().unwrap());
+                if let (BinaryOperator::Index, Some(budget)) = (operation, self.env.budget()) {
+                    let exponent = match &rhs_value {
+                        Value::Number(value) => Some(*value),
+                        Value::Quantity(quantity) => Some(quantity.value),
+                        _ => None,
+                    };
+                    if let Some(exponent) = exponent {
+                        budget.check_exponent(exponent).map_err(|limit| CalcError {
+                            span: node.span(),
+                            ..limit.into()
+                        })?;
+                    }
                 }
+                // a mismatch points at both operands
+                Value::apply(operation, lhs_value, rhs_value).map_err(|message| CalcError {
+                    span: node.span(),
+                    ..CalcError::new(message)
+                })?
+            }
+        };
+        self.charge(&result)?;
+        self.record(&result);
+        Ok(result)
+    }
+
+    fn visit_negation(
+        &mut self,
+        node: &'a Node,
+        _operand: &'a Node,
+        outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        let value = outputs.into_iter().next().unwrap();
+        let result = value.negate().map_err(|message| CalcError {
+            span: node.span(),
+            ..CalcError::new(message)
+        })?;
+        self.charge(&result)?;
+        self.record(&result);
+        Ok(result)
+    }
+
+    fn visit_function(
+        &mut self,
+        _node: &'a Node,
+        name: &'a str,
+        args: &'a [Node],
+        span: Span,
+        outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        for output in &outputs {
+            self.charge(output)?;
+        }
+        let result = if functions::is_special_form(name) {
+            special_form(name, args, span, self.env)?
+        } else if outputs.is_empty() {
+            return Err(CalcError::at(format!("Unknown function: {}", name), span));
+        } else {
+            match functions::builtin(name) {
+                _ if functions::is_matrix_function(name) => {
+                    matrix::call(name, outputs).map_err(|message| CalcError::at(message, span))?
+                }
+                _ if closure::is_higher_order(name) => {
+                    closure::call(name, outputs).map_err(|error| match error.span {
+                        Some(_) => error,
+                        None => CalcError {
+                            span: Some(span),
+                            ..error
+                        },
+                    })?
+                }
+                _ if statistics::is_statistic(name) => statistics::call(name, outputs)
+                    .map_err(|message| CalcError::at(message, span))?,
+                Some(builtin) => functions::apply(builtin, outputs.into_iter().next().unwrap())
+                    .map_err(|message| CalcError::at(message, span))?,
+                None => return Err(CalcError::at(format!("Unknown function: {}", name), span)),
+            }
+        };
+        self.record(&result);
+        Ok(result)
+    }
+
+    fn visit_list(
+        &mut self,
+        node: &'a Node,
+        items: &'a [Node],
+        outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        let result = Value::List(
+            items
+                .iter()
+                .zip(outputs)
+                .map(|(item, value)| item.entry(value, "list"))
+                .collect::<Result<Vec<f64>, CalcError>>()?,
+        );
+        // a list of plain numbers is already its own value
+        if result.to_string() != node.to_string() {
+            self.record(&result);
+        }
+        Ok(result)
+    }
+
+    fn visit_matrix(
+        &mut self,
+        node: &'a Node,
+        rows: &'a [Vec<Node>],
+        outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        let mut entries = rows.iter().flatten().zip(outputs);
+        let rows = rows
+            .iter()
+            .map(|row| {
+                entries
+                    .by_ref()
+                    .take(row.len())
+                    .map(|(entry, value)| entry.entry(value, "matrix"))
+                    .collect()
+            })
+            .collect::<Result<Vec<Vec<f64>>, CalcError>>()?;
+        let result = Value::Matrix(Matrix::from_rows(rows));
+        if result.to_string() != node.to_string() {
+            self.record(&result);
+        }
+        Ok(result)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _node: &'a Node,
+        params: &'a [String],
+        body: &'a Node,
+        _outputs: Vec<Value>,
+    ) -> Result<Value, CalcError> {
+        self.step()?;
+        Ok(Value::Closure(Closure {
+            params: params.to_vec(),
+            body: body.clone(),
+            scope: self.env.clone(),
+        }))
+    }
+}
+
+/// Evaluates a call that binds its own variable, which is always the second argument,
+/// or for `solve` with two arguments the list of unknowns of a system of equations.
+fn special_form(
+    name: &str,
+    args: &[Node],
+    span: Span,
+    env: &Environment,
+) -> Result<Value, CalcError> {
+    // failures to converge belong to the call rather than to anything inside it
+    let at_call = |error: CalcError| match error.span {
+        Some(_) => error,
+        None => CalcError {
+            span: Some(span),
+            ..error
+        },
+    };
+    if let ("solve", [equations, unknowns]) = (name, args) {
+        return linear::solve_system(equations, unknowns, env).map_err(at_call);
+    }
+    let Node::Variable { name: by, .. } = &args[1] else {
+        return Err(CalcError::at(
+            format!(
+                "{} expects a variable as its second argument, found {}",
+                name, args[1]
+            ),
+            span,
+        ));
+    };
+    let number = |arg: &Node| match arg.calculate(env)? {
+        Value::Number(value) => Ok(value),
+        other => Err(CalcError::at(
+            format!("{} expects a number, found {}", name, other),
+            span,
+        )),
+    };
+
+    let result = match name {
+        "diff" => {
+            let derivative =
+                derivative::differentiate(&args[0], by, env.budget()).map_err(at_call)?;
+            // with every name known the derivative has a value, otherwise it is the answer itself
+            return match derivative.is_closed(env) {
+                true => derivative.calculate(env),
+                false => Ok(Value::Expression(derivative)),
+            };
+        }
+        "integrate" => numeric::integrate(&args[0], by, number(&args[2])?, number(&args[3])?, env),
+        "solve" => numeric::solve(&args[0], by, number(&args[2])?, env),
+        "nsum" => numeric::nsum(&args[0], by, number(&args[2])?, number(&args[3])?, env),
+        _ => unreachable!(),
+    };
+    result.map(Value::Number).map_err(at_call)
+}
+
+fn resolve(name: &str, span: Span, env: &Environment) -> Result<Value, CalcError> {
+    if let Some(value) = env.get(name) {
+        return Ok(value.clone());
+    }
+    match name {
+        "today" => return Ok(Value::DateTime(DateTime::today(env.time_mode()))),
+        "now" => return Ok(Value::DateTime(DateTime::now(env.time_mode()))),
+        _ => {}
+    }
+    if let Some(currency) = env.rates().and_then(|rates| rates.lookup(name)) {
+        let unit = currency.map_err(|message| CalcError::at(message, span))?;
+        return Ok(Value::Quantity(Quantity::from_unit(name, unit)));
+    }
+    match registry::lookup(name) {
+        Some(unit) => Ok(Value::Quantity(Quantity::from_unit(name, unit))),
+        None => Err(CalcError::at(
+            format!("Unknown variable or unit: {}", name),
+            span,
+        )),
+    }
+}
+
+impl Statement {
+    /// Runs the statement, binding the result in `env` if it is an assignment.
+    pub fn execute(&self, env: &mut Environment) -> Result<Value, CalcError> {
+        match self {
+            Statement::Assignment { name, expr } => {
+                let value = expr.calculate(env)?;
+                env.set(name.clone(), value.clone());
+                Ok(value)
             }
+            Statement::Expression(expr) => expr.calculate(env),
         }
     }
 }
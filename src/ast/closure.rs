@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::{
+    ast::{ast::Node, environment::Environment, value::Value},
+    utils::error::CalcError,
+};
+
+/// Functions that take a function and a list, with the fewest and most arguments each takes.
+pub const FUNCTIONS: &[(&str, usize, usize)] = &[("map", 2, 2), ("filter", 2, 2), ("reduce", 2, 3)];
+
+/// A function written as `x -> x^2`, with the variables it could see when it was made.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub params: Vec<String>,
+    pub body: Node,
+    pub scope: Environment,
+}
+
+/// Two closures are the same function if they are written the same, whatever they captured.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        self.params == other.params && self.body == other.body
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.params[..] {
+            [param] => write!(f, "{} -> {}", param, self.body),
+            params => write!(f, "({}) -> {}", params.join(", "), self.body),
+        }
+    }
+}
+
+impl Closure {
+    /// Calls the closure with `args` bound to its parameters in `scope`, which starts out as a
+    /// copy of the closure's own so that one copy serves every call over a list.
+    fn call(&self, scope: &mut Environment, args: &[f64]) -> Result<f64, CalcError> {
+        for (param, arg) in self.params.iter().zip(args) {
+            scope.set(param.as_str(), Value::Number(*arg));
+        }
+        match self.body.calculate(scope)? {
+            Value::Number(value) => Ok(value),
+            other => Err(CalcError {
+                message: format!("{} must give a number, found {}", self, other),
+                span: self.body.span(),
+                limit: None,
+            }),
+        }
+    }
+}
+
+pub fn is_higher_order(name: &str) -> bool {
+    FUNCTIONS.iter().any(|(function, _, _)| *function == name)
+}
+
+/// Calls `name` with evaluated arguments: a closure, a list and for `reduce` a starting value.
+/// `filter` keeps the items for which the closure gives anything but zero.
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value, CalcError> {
+    let mut args = args.into_iter();
+    let params = if name == "reduce" { 2 } else { 1 };
+    let closure = match args.next() {
+        Some(Value::Closure(closure)) if closure.params.len() == params => closure,
+        other => {
+            return Err(CalcError::new(format!(
+                "{} expects a function of {} as its first argument, found {}",
+                name,
+                if params == 1 { "x" } else { "(a, b)" },
+                other.map_or("nothing".to_string(), |other| other.to_string())
+            )));
+        }
+    };
+    let items = match args.next() {
+        Some(Value::List(items)) => items,
+        Some(Value::Number(item)) => vec![item],
+        other => {
+            return Err(CalcError::new(format!(
+                "{} expects a list as its second argument, found {}",
+                name,
+                other.map_or("nothing".to_string(), |other| other.to_string())
+            )));
+        }
+    };
+
+    let mut scope = closure.scope.clone();
+    match name {
+        "map" => items
+            .iter()
+            .map(|item| closure.call(&mut scope, &[*item]))
+            .collect::<Result<Vec<f64>, CalcError>>()
+            .map(Value::List),
+        "filter" => {
+            let mut kept = Vec::new();
+            for item in items {
+                if closure.call(&mut scope, &[item])? != 0.0 {
+                    kept.push(item);
+                }
+            }
+            Ok(Value::List(kept))
+        }
+        "reduce" => {
+            let mut items = items.into_iter();
+            let initial = match args.next() {
+                Some(Value::Number(initial)) => Some(initial),
+                Some(other) => {
+                    return Err(CalcError::new(format!(
+                        "reduce expects a number to start from, found {}",
+                        other
+                    )));
+                }
+                None => items.next(),
+            };
+            let Some(mut total) = initial else {
+                return Err(CalcError::new(
+                    "reduce of an empty list needs a value to start from",
+                ));
+            };
+            for item in items {
+                total = closure.call(&mut scope, &[total, item])?;
+            }
+            Ok(Value::Number(total))
+        }
+        _ => Err(CalcError::new(format!("Unknown function: {}", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::evaluate;
+    use pretty_assertions::assert_eq;
+
+    fn calculated(input: &str) -> String {
+        match evaluate(input, &Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_map_filter_reduce() {
+        assert_eq!(calculated("map(x -> x^2, 1..5)"), "{1, 4, 9, 16, 25}");
+        assert_eq!(calculated("filter(x -> x - 3, {1, 3, 5})"), "{1, 5}");
+        assert_eq!(calculated("reduce((a, b) -> a*b, 1..5)"), "120");
+        assert_eq!(calculated("reduce((a, b) -> a + b, {}, 10)"), "10");
+        assert_eq!(calculated("x -> 2x"), "x -> 2*x");
+    }
+
+    #[test]
+    fn test_generators() {
+        assert_eq!(calculated("sum(k^2 for k in 1..10)"), "385");
+        assert_eq!(calculated("max(map(x -> -x, 1..3))"), "-1");
+    }
+
+    #[test]
+    fn test_closures_capture_their_scope() {
+        let mut env = Environment::new();
+        env.set("n", Value::Number(2.0));
+        let times_n = evaluate("x -> x*n", &env).unwrap();
+        env.set("n", Value::Number(3.0));
+        env.set("f", times_n);
+        assert_eq!(
+            evaluate("map(f, {1, 2})", &env),
+            Ok(Value::List(vec![2.0, 4.0]))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            calculated("map(x -> {x}, 1..2)"),
+            "Error: x -> {x} must give a number, found {1}"
+        );
+        assert_eq!(
+            calculated("reduce(x -> x, 1..2)"),
+            "Error: reduce expects a function of (a, b) as its first argument, found x -> x"
+        );
+        assert_eq!(
+            calculated("reduce((a, b) -> a, {})"),
+            "Error: reduce of an empty list needs a value to start from"
+        );
+    }
+}
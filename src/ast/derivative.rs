@@ -0,0 +1,425 @@
+use crate::{
+    ast::{
+        ast::Node,
+        simplify::simplify_within,
+        visit::{Visitor, visit},
+    },
+    utils::{error::CalcError, limits::Budget, operators::BinaryOperator, span::Span},
+};
+
+/// The derivative of `expr` with respect to the variable `by`, simplified.
+/// Every other name is held constant.
+///
+/// Derivatives can be much larger than what they are taken of, so each node built, including
+/// the copies the product and chain rules make, is counted against `budget`.
+pub fn differentiate(expr: &Node, by: &str, budget: Option<&Budget>) -> Result<Node, CalcError> {
+    Ok(simplify_within(&derivative(expr, by, budget)?, budget)?)
+}
+
+fn derivative(expr: &Node, by: &str, budget: Option<&Budget>) -> Result<Node, CalcError> {
+    visit(&mut Differentiator { by, budget }, expr)
+}
+
+/// Differentiates each node from the derivatives of its operands, with a stack rather than by
+/// recursion so that deep expressions cannot overflow it.
+struct Differentiator<'b> {
+    by: &'b str,
+    budget: Option<&'b Budget>,
+}
+
+impl Differentiator<'_> {
+    /// A copy of an operand for a rule to use, counted against the budget as it may be large.
+    fn copy(&self, node: &Node) -> Result<Node, CalcError> {
+        charge(self.budget, node.size())?;
+        Ok(node.clone())
+    }
+}
+
+impl<'a> Visitor<'a> for Differentiator<'_> {
+    type Output = Node;
+    type Error = CalcError;
+
+    /// Only the operands a rule needs the derivative of are differentiated.
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        use BinaryOperator::*;
+
+        match operation {
+            Index if !contains(rhs, self.by) => vec![lhs],
+            Index if !contains(lhs, self.by) => vec![rhs],
+            Conversion => vec![lhs],
+            Equation | Range => Vec::new(),
+            _ => vec![lhs, rhs],
+        }
+    }
+
+    /// A nested `diff` is worked out before it is differentiated again.
+    fn walk_function(&mut self, name: &'a str, args: &'a [Node]) -> Vec<&'a Node> {
+        match name {
+            "diff" => Vec::new(),
+            _ => vec![&args[0]],
+        }
+    }
+
+    fn walk_list(&mut self, _items: &'a [Node]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_lambda(&mut self, _params: &'a [String], _body: &'a Node) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn visit_node(&mut self, node: &'a Node, outputs: Vec<Node>) -> Result<Node, CalcError> {
+        charge(self.budget, 1)?;
+        let mut outputs = outputs.into_iter();
+        Ok(match node {
+            Node::Operand { .. } => number(0.0),
+            Node::Variable { name, .. } => number(if name == self.by { 1.0 } else { 0.0 }),
+            Node::Date { value } => {
+                return Err(CalcError::new(format!(
+                    "Cannot differentiate the date {}",
+                    value
+                )));
+            }
+            Node::List { .. } => {
+                return Err(CalcError::new(format!(
+                    "Cannot differentiate the list {}",
+                    node
+                )));
+            }
+            Node::Lambda { .. } => {
+                return Err(CalcError::new(format!(
+                    "Cannot differentiate the function {}",
+                    node
+                )));
+            }
+            Node::Matrix { rows, span } => Node::Matrix {
+                rows: rows
+                    .iter()
+                    .map(|row| outputs.by_ref().take(row.len()).collect())
+                    .collect(),
+                span: *span,
+            },
+            Node::BinaryExpr { .. } | Node::Negation { .. } | Node::Function { .. } => {
+                unreachable!()
+            }
+        })
+    }
+
+    fn visit_negation(
+        &mut self,
+        _node: &'a Node,
+        _operand: &'a Node,
+        outputs: Vec<Node>,
+    ) -> Result<Node, CalcError> {
+        charge(self.budget, 1)?;
+        Ok(negate(outputs.into_iter().next().unwrap()))
+    }
+
+    fn visit_binary(
+        &mut self,
+        node: &'a Node,
+        operation: BinaryOperator,
+        u: &'a Node,
+        v: &'a Node,
+        outputs: Vec<Node>,
+    ) -> Result<Node, CalcError> {
+        use BinaryOperator::*;
+
+        charge(self.budget, 1)?;
+        let mut outputs = outputs.into_iter();
+        let mut next = || outputs.next().unwrap();
+        Ok(match operation {
+            Addition | Subtraction => {
+                let du = next();
+                Node::binary(operation, du, next())
+            }
+            // (uv)' = u'v + uv'
+            Multiplication => {
+                let (du, dv) = (next(), next());
+                Node::binary(
+                    Addition,
+                    Node::binary(Multiplication, du, self.copy(v)?),
+                    Node::binary(Multiplication, self.copy(u)?, dv),
+                )
+            }
+            // (u/v)' = (u'v - uv')/v^2
+            Division => {
+                let (du, dv) = (next(), next());
+                Node::binary(
+                    Division,
+                    Node::binary(
+                        Subtraction,
+                        Node::binary(Multiplication, du, self.copy(v)?),
+                        Node::binary(Multiplication, self.copy(u)?, dv),
+                    ),
+                    Node::binary(Index, self.copy(v)?, number(2.0)),
+                )
+            }
+            // (u^n)' = n u^(n - 1) u'
+            Index if !contains(v, self.by) => Node::binary(
+                Multiplication,
+                Node::binary(
+                    Multiplication,
+                    self.copy(v)?,
+                    Node::binary(
+                        Index,
+                        self.copy(u)?,
+                        Node::binary(Subtraction, self.copy(v)?, number(1.0)),
+                    ),
+                ),
+                next(),
+            ),
+            // (a^v)' = a^v ln(a) v'
+            Index if !contains(u, self.by) => Node::binary(
+                Multiplication,
+                Node::binary(Multiplication, self.copy(node)?, call("ln", self.copy(u)?)),
+                next(),
+            ),
+            // (u^v)' = u^v (v' ln(u) + v u'/u)
+            Index => {
+                let (du, dv) = (next(), next());
+                Node::binary(
+                    Multiplication,
+                    self.copy(node)?,
+                    Node::binary(
+                        Addition,
+                        Node::binary(Multiplication, dv, call("ln", self.copy(u)?)),
+                        Node::binary(
+                            Division,
+                            Node::binary(Multiplication, self.copy(v)?, du),
+                            self.copy(u)?,
+                        ),
+                    ),
+                )
+            }
+            Conversion => Node::binary(Conversion, next(), self.copy(v)?),
+            Equation => {
+                return Err(CalcError::new(format!(
+                    "Cannot differentiate the equation {}",
+                    node
+                )));
+            }
+            Range => {
+                return Err(CalcError::new(format!(
+                    "Cannot differentiate the range {}",
+                    node
+                )));
+            }
+        })
+    }
+
+    fn visit_function(
+        &mut self,
+        _node: &'a Node,
+        name: &'a str,
+        args: &'a [Node],
+        span: Span,
+        outputs: Vec<Node>,
+    ) -> Result<Node, CalcError> {
+        use BinaryOperator::*;
+
+        charge(self.budget, 1)?;
+        if name == "diff" {
+            let inner = match &args[1] {
+                Node::Variable { name, .. } => differentiate(&args[0], name, self.budget)?,
+                other => {
+                    return Err(CalcError::new(format!(
+                        "diff expects a variable to differentiate by, found {}",
+                        other
+                    )));
+                }
+            };
+            return derivative(&inner, self.by, self.budget);
+        }
+        // the chain rule: f(u)' = f'(u) u'
+        let u = || self.copy(&args[0]);
+        let outer = match name {
+            "sin" => call("cos", u()?),
+            "cos" => negate(call("sin", u()?)),
+            "tan" => reciprocal(Node::binary(Index, call("cos", u()?), number(2.0))),
+            "asin" => reciprocal(call("sqrt", one_minus_square(u()?))),
+            "acos" => negate(reciprocal(call("sqrt", one_minus_square(u()?)))),
+            "atan" => reciprocal(Node::binary(
+                Addition,
+                number(1.0),
+                Node::binary(Index, u()?, number(2.0)),
+            )),
+            "sinh" => call("cosh", u()?),
+            "cosh" => call("sinh", u()?),
+            "tanh" => reciprocal(Node::binary(Index, call("cosh", u()?), number(2.0))),
+            "exp" => call("exp", u()?),
+            "ln" => reciprocal(u()?),
+            "log" => reciprocal(Node::binary(Multiplication, u()?, call("ln", number(10.0)))),
+            "sqrt" => reciprocal(Node::binary(
+                Multiplication,
+                number(2.0),
+                call("sqrt", u()?),
+            )),
+            "abs" => Node::binary(Division, u()?, call("abs", u()?)),
+            _ => {
+                return Err(CalcError::at(
+                    format!("Cannot differentiate {}", name),
+                    span,
+                ));
+            }
+        };
+        Ok(Node::binary(
+            Multiplication,
+            outer,
+            outputs.into_iter().next().unwrap(),
+        ))
+    }
+}
+
+/// Whether the variable `name` appears anywhere in `expr`.
+pub(crate) fn contains(expr: &Node, name: &str) -> bool {
+    let mut pending = vec![expr];
+    while let Some(node) = pending.pop() {
+        match node {
+            Node::Variable { name: other, .. } if other == name => return true,
+            Node::BinaryExpr { lhs, rhs, .. } => pending.extend([&**lhs, &**rhs]),
+            Node::Negation { operand } => pending.push(operand),
+            Node::Function { args, .. } | Node::List { items: args, .. } => pending.extend(args),
+            Node::Matrix { rows, .. } => pending.extend(rows.iter().flatten()),
+            // a parameter of the same name hides the variable
+            Node::Lambda { params, body, .. } => {
+                if !params.iter().any(|param| param == name) {
+                    pending.push(body);
+                }
+            }
+            Node::Variable { .. } | Node::Operand { .. } | Node::Date { .. } => {}
+        }
+    }
+    false
+}
+
+fn number(value: f64) -> Node {
+    Node::Operand { value }
+}
+
+fn call(name: &str, argument: Node) -> Node {
+    Node::call(name, vec![argument])
+}
+
+fn negate(node: Node) -> Node {
+    Node::negation(node)
+}
+
+fn reciprocal(node: Node) -> Node {
+    Node::binary(BinaryOperator::Division, number(1.0), node)
+}
+
+fn one_minus_square(u: Node) -> Node {
+    Node::binary(
+        BinaryOperator::Subtraction,
+        number(1.0),
+        Node::binary(BinaryOperator::Index, u, number(2.0)),
+    )
+}
+
+fn charge(budget: Option<&Budget>, nodes: usize) -> Result<(), CalcError> {
+    match budget {
+        Some(budget) => budget.charge(nodes as u64).map_err(CalcError::from),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{environment::Environment, parse_expression, value::Value};
+    use pretty_assertions::assert_eq;
+
+    fn derived(input: &str) -> String {
+        differentiate(&parse_expression(input).unwrap(), "x", None)
+            .unwrap()
+            .to_string()
+    }
+
+    fn at(expr: &Node, x: f64) -> f64 {
+        let mut env = Environment::new();
+        env.set("x".to_string(), x.into());
+        match expr.calculate(&env) {
+            Ok(Value::Number(value)) => value,
+            other => panic!("{} at x = {} gave {:?}", expr, x, other),
+        }
+    }
+
+    /// Checks the symbolic derivative against a central difference at a few points.
+    fn check_numerically(input: &str, points: &[f64]) {
+        let expr = parse_expression(input).unwrap();
+        let derivative = differentiate(&expr, "x", None).unwrap();
+        for &x in points {
+            let h = 1e-6;
+            let expected = (at(&expr, x + h) - at(&expr, x - h)) / (2.0 * h);
+            let actual = at(&derivative, x);
+            assert!(
+                (actual - expected).abs() <= 1e-5 * expected.abs().max(1.0),
+                "d/dx {} at {}: {} = {}, but the difference quotient is {}",
+                input,
+                x,
+                derivative,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_product_rule() {
+        assert_eq!(derived("x^2 * sin(x)"), "2*x*sin(x) + x^2*cos(x)");
+    }
+
+    #[test]
+    fn test_other_names_are_constant() {
+        assert_eq!(derived("3*a*x + b"), "3*a");
+        assert_eq!(derived("y"), "0");
+    }
+
+    #[test]
+    fn test_negative_derivative() {
+        assert_eq!(derived("cos(x)"), "-sin(x)");
+    }
+
+    #[test]
+    fn test_operators_numerically() {
+        let points = [0.3, 1.0, 2.5];
+        check_numerically("3*x^2 - 2*x + 1", &points);
+        check_numerically("(x + 1)/(x^2 + 1)", &points);
+        check_numerically("2^x", &points);
+        check_numerically("x^x", &points);
+        check_numerically("x^(x/2 + 1) - x^-2", &points);
+    }
+
+    #[test]
+    fn test_functions_numerically() {
+        let points = [0.2, 0.5, 0.9];
+        for function in [
+            "sin", "cos", "tan", "asin", "acos", "atan", "sinh", "cosh", "tanh",
+        ] {
+            check_numerically(&format!("{}(x^2)", function), &points);
+        }
+        for function in ["exp", "ln", "log", "sqrt", "abs"] {
+            check_numerically(&format!("x*{}(3*x + 1)", function), &points);
+        }
+        check_numerically("abs(x - 1)", &points);
+    }
+
+    #[test]
+    fn test_nested_diff() {
+        assert_eq!(derived("diff(x^3, x)"), "6*x");
+    }
+
+    #[test]
+    fn test_dates_cannot_be_differentiated() {
+        assert_eq!(
+            differentiate(&parse_expression("2026-01-01 + x").unwrap(), "x", None),
+            Err(CalcError::new("Cannot differentiate the date 2026-01-01"))
+        );
+    }
+}
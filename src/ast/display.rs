@@ -0,0 +1,275 @@
+use std::{borrow::Cow, fmt};
+
+use crate::{
+    ast::ast::{Node, Statement},
+    utils::operators::{Associativity, BinaryOperator, NEGATION, OperatorInfo, get_operator_info},
+};
+
+pub(crate) fn operator_info(operation: BinaryOperator) -> &'static OperatorInfo {
+    get_operator_info(operation.symbol()).unwrap()
+}
+
+/// How tightly a leading `-` binds, which is as loosely as subtraction.
+pub(crate) fn negation_info() -> &'static OperatorInfo {
+    get_operator_info(NEGATION).unwrap()
+}
+
+/// Whether `child` must be wrapped in parentheses to keep its place under an operator with `parent` info.
+pub(crate) fn needs_parentheses(child: &Node, parent: &OperatorInfo, is_rhs: bool) -> bool {
+    match child {
+        // a leading minus would otherwise be read as a unary operator on everything after it,
+        // except in an exponent where the sign sticks to the number
+        Node::Operand { value } => {
+            value.is_sign_negative() && !(is_rhs && parent.operation == BinaryOperator::Index)
+        }
+        // a sign in an exponent applies to as much as `^` itself would take
+        Node::Negation { operand } if is_rhs && parent.operation == BinaryOperator::Index => {
+            match &**operand {
+                Node::BinaryExpr { operation, .. } => {
+                    operator_info(*operation).precedence < parent.precedence
+                }
+                Node::Negation { .. } => true,
+                _ => false,
+            }
+        }
+        Node::BinaryExpr { .. } | Node::Negation { .. } => {
+            let child = match child {
+                Node::BinaryExpr { operation, .. } => operator_info(*operation),
+                _ => negation_info(),
+            };
+            child.precedence < parent.precedence
+                || (child.precedence == parent.precedence
+                    && match parent.associativity {
+                        Associativity::Left => is_rhs,
+                        Associativity::Right => !is_rhs,
+                    })
+        }
+        // the body of a function runs to the end of whatever it is in
+        Node::Lambda { .. } => true,
+        Node::Variable { .. }
+        | Node::Date { .. }
+        | Node::Function { .. }
+        | Node::List { .. }
+        | Node::Matrix { .. } => false,
+    }
+}
+
+/// A piece of an expression being written out: text as it is, or a node to write in its place.
+pub(crate) enum Part<'a> {
+    Text(Cow<'a, str>),
+    Node(&'a Node),
+}
+
+impl<'a> Part<'a> {
+    pub(crate) fn text(text: impl Into<Cow<'a, str>>) -> Part<'a> {
+        Part::Text(text.into())
+    }
+}
+
+/// Writes `node` out as the parts `expand` splits each node into, with a stack rather than by
+/// recursion so that however deeply the tree nests it cannot overflow.
+pub(crate) fn write_parts<'a>(
+    out: &mut impl fmt::Write,
+    node: &'a Node,
+    mut expand: impl FnMut(&'a Node) -> Vec<Part<'a>>,
+) -> fmt::Result {
+    let mut pending = vec![Part::Node(node)];
+    while let Some(part) = pending.pop() {
+        match part {
+            Part::Text(text) => out.write_str(&text)?,
+            Part::Node(node) => pending.extend(expand(node).into_iter().rev()),
+        }
+    }
+    Ok(())
+}
+
+/// `nodes` with `separator` between each of them.
+pub(crate) fn separated<'a>(
+    nodes: impl IntoIterator<Item = &'a Node>,
+    separator: &'a str,
+) -> Vec<Part<'a>> {
+    let mut parts = Vec::new();
+    for (i, node) in nodes.into_iter().enumerate() {
+        if i > 0 {
+            parts.push(Part::text(separator));
+        }
+        parts.push(Part::Node(node));
+    }
+    parts
+}
+
+fn child<'a>(child: &'a Node, parent: &OperatorInfo, is_rhs: bool) -> Vec<Part<'a>> {
+    match needs_parentheses(child, parent, is_rhs) {
+        true => vec![Part::text("("), Part::Node(child), Part::text(")")],
+        false => vec![Part::Node(child)],
+    }
+}
+
+fn parts(node: &Node) -> Vec<Part<'_>> {
+    match node {
+        Node::Operand { value } => vec![Part::text(value.to_string())],
+        Node::Variable { name, .. } => vec![Part::text(name.as_str())],
+        Node::Date { value } => vec![Part::text(value.to_string())],
+        Node::Negation { operand } => {
+            let mut parts = vec![Part::text("-")];
+            parts.extend(child(operand, negation_info(), true));
+            parts
+        }
+        Node::BinaryExpr {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let info = operator_info(*operation);
+            // loosely binding operators get spaces so the structure stands out
+            let symbol = match operation {
+                BinaryOperator::Addition
+                | BinaryOperator::Subtraction
+                | BinaryOperator::Conversion
+                | BinaryOperator::Equation => Part::text(format!(" {} ", operation.symbol())),
+                _ => Part::text(operation.symbol()),
+            };
+            let mut parts = child(lhs, info, false);
+            parts.push(symbol);
+            parts.extend(child(rhs, info, true));
+            parts
+        }
+        Node::Function { name, args, .. } => {
+            let mut parts = vec![Part::text(name.as_str()), Part::text("(")];
+            parts.extend(separated(args, ", "));
+            parts.push(Part::text(")"));
+            parts
+        }
+        Node::List { items, .. } => {
+            let mut parts = vec![Part::text("{")];
+            parts.extend(separated(items, ", "));
+            parts.push(Part::text("}"));
+            parts
+        }
+        Node::Matrix { rows, .. } => {
+            let mut parts = vec![Part::text("[")];
+            for (i, row) in rows.iter().enumerate() {
+                if i > 0 {
+                    parts.push(Part::text("; "));
+                }
+                parts.extend(separated(row, ", "));
+            }
+            parts.push(Part::text("]"));
+            parts
+        }
+        Node::Lambda { params, body, .. } => {
+            let params = match &params[..] {
+                [param] => Part::text(format!("{} -> ", param)),
+                params => Part::text(format!("({}) -> ", params.join(", "))),
+            };
+            vec![params, Part::Node(body)]
+        }
+    }
+}
+
+/// Prints the expression back in infix, with only the parentheses that precedence and associativity require.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_parts(f, self, parts)
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assignment { name, expr } => write!(f, "{} = {}", name, expr),
+            Statement::Expression(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::parse::infix_to_ast, utils::input::tokenize};
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn echo(input: &str) -> String {
+        infix_to_ast(tokenize(input).unwrap()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_right_associative_index() {
+        assert_eq!(echo("2^(3^2)"), "2^3^2");
+        assert_eq!(echo("(2^3)^2"), "(2^3)^2");
+    }
+
+    #[test]
+    fn test_left_associative_subtraction() {
+        assert_eq!(echo("(1 - 2) - 3"), "1 - 2 - 3");
+        assert_eq!(echo("1 - (2 - 3)"), "1 - (2 - 3)");
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(echo("3 + 4 * 2 ÷ ( 1 - 5 ) ^ 2 ^ 3"), "3 + 4*2/(1 - 5)^2^3");
+        assert_eq!(echo("(4*2) + 1"), "4*2 + 1");
+    }
+
+    #[test]
+    fn test_units_and_conversion() {
+        assert_eq!(echo("3 m * 2 s^-1 to km/h"), "3*m*(2*s^-1) to km/h");
+        assert_eq!(
+            echo("2^-x^2 * 2^-(x*y) * 2^(-x)^2"),
+            "2^-x^2*2^(-x*y)*2^(-x)^2"
+        );
+    }
+
+    #[test]
+    fn test_functions_and_negation() {
+        assert_eq!(echo("-x^2 + 2*(-sin (x))"), "-x^2 + 2*(-sin(x))");
+        assert_eq!(echo("diff(x^2, x)"), "diff(x^2, x)");
+        assert_eq!(echo("-(2*3) - (-2)*3 - -2^2"), "-2*3 - (-2)*3 - (-2^2)");
+        assert_eq!(
+            echo("-(1 - 2) + 2^(-(-1)) + 2^-(1 + 2)"),
+            "-(1 - 2) + 2^(-(-1)) + 2^(-(1 + 2))"
+        );
+    }
+
+    #[test]
+    fn test_equation() {
+        assert_eq!(echo("x^2 - 5x + 6 = (0)"), "x^2 - 5*x + 6 = 0");
+    }
+
+    fn arbitrary_node() -> impl Strategy<Value = Node> {
+        let leaf = (0u32..100).prop_map(|value| Node::Operand {
+            value: f64::from(value),
+        });
+        leaf.prop_recursive(6, 64, 2, |inner| {
+            prop_oneof![
+                (
+                    prop_oneof![
+                        Just(BinaryOperator::Addition),
+                        Just(BinaryOperator::Subtraction),
+                        Just(BinaryOperator::Multiplication),
+                        Just(BinaryOperator::Division),
+                        Just(BinaryOperator::Index),
+                    ],
+                    inner.clone(),
+                    inner.clone(),
+                )
+                    .prop_map(|(operation, lhs, rhs)| Node::BinaryExpr {
+                        operation,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    }),
+                inner.prop_map(Node::negation),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn printing_round_trips(node in arbitrary_node()) {
+            let printed = node.to_string();
+            prop_assert_eq!(infix_to_ast(tokenize(&printed).unwrap()), Ok(node));
+        }
+    }
+}
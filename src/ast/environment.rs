@@ -0,0 +1,59 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    ast::value::Value,
+    datetime::TimeMode,
+    units::currency::CurrencyRates,
+    utils::limits::{Budget, EvalLimits},
+};
+
+/// Variable bindings, exchange rates, the time mode and any limits visible while evaluating an
+/// expression.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    variables: HashMap<String, Value>,
+    rates: Option<CurrencyRates>,
+    time_mode: TimeMode,
+    budget: Option<Arc<Budget>>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: Value) {
+        self.variables.insert(name.into(), value);
+    }
+
+    pub fn rates(&self) -> Option<&CurrencyRates> {
+        self.rates.as_ref()
+    }
+
+    pub fn set_rates(&mut self, rates: CurrencyRates) {
+        self.rates = Some(rates);
+    }
+
+    pub fn time_mode(&self) -> TimeMode {
+        self.time_mode
+    }
+
+    pub fn set_time_mode(&mut self, time_mode: TimeMode) {
+        self.time_mode = time_mode;
+    }
+
+    /// The budget evaluation in this environment counts against, shared with the scopes made
+    /// from it.
+    pub fn budget(&self) -> Option<&Budget> {
+        self.budget.as_deref()
+    }
+
+    /// Limits evaluation in this environment from now on, starting the clock for any timeout.
+    pub fn set_limits(&mut self, limits: EvalLimits) {
+        self.budget = Some(Arc::new(Budget::new(limits)));
+    }
+}
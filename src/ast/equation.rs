@@ -0,0 +1,546 @@
+use std::{convert::Infallible, fmt};
+
+use crate::{
+    ast::{
+        ast::Node,
+        environment::Environment,
+        functions, numeric,
+        value::Value,
+        visit::{Visitor, visit},
+    },
+    utils::{error::CalcError, operators::BinaryOperator, span::Span},
+};
+
+/// The highest power a polynomial may reach while its terms are multiplied out.
+pub const MAX_DEGREE: usize = 64;
+
+/// What solving one or more equations found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Solutions {
+    /// Each entry gives a value to every unknown, in the same order as the unknowns.
+    Values(Vec<Vec<f64>>),
+    /// The equations contradict each other, or have no real solution.
+    None,
+    /// The equations hold whatever values the unknowns take.
+    Infinite,
+}
+
+/// The unknowns of a set of equations and the values that solve them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolutionSet {
+    pub unknowns: Vec<String>,
+    pub solutions: Solutions,
+}
+
+impl fmt::Display for SolutionSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.solutions {
+            Solutions::Values(values) => {
+                let assignments: Vec<String> = values
+                    .iter()
+                    .flat_map(|values| {
+                        self.unknowns
+                            .iter()
+                            .zip(values)
+                            .map(|(unknown, value)| format!("{} = {}", unknown, value))
+                    })
+                    .collect();
+                write!(f, "{}", assignments.join(", "))
+            }
+            Solutions::None => write!(f, "no solution"),
+            Solutions::Infinite => write!(f, "infinitely many solutions"),
+        }
+    }
+}
+
+/// Solves `lhs = rhs` for its one unknown, the only name in it without a value.
+///
+/// When both sides are polynomials in the unknown, up to degree 2 is solved in closed form and
+/// higher degrees by bracketing every real root. Anything else is solved numerically from 0.
+pub fn solve_equation(lhs: &Node, rhs: &Node, env: &Environment) -> Result<Value, CalcError> {
+    let equation = Node::binary(BinaryOperator::Equation, lhs.clone(), rhs.clone());
+    let mut names = Vec::new();
+    unknowns(&equation, env, &mut names);
+    let unknown = match &names[..] {
+        [unknown] => unknown.clone(),
+        [] => {
+            return Err(CalcError::new(format!(
+                "{} has no unknown to solve for",
+                equation
+            )));
+        }
+        _ => {
+            return Err(CalcError::new(format!(
+                "Cannot solve one equation for {} unknowns: {}",
+                names.len(),
+                names.join(", ")
+            )));
+        }
+    };
+
+    let difference = Node::binary(BinaryOperator::Subtraction, lhs.clone(), rhs.clone());
+    let solutions = match polynomial(&difference, &unknown, env) {
+        Some(polynomial) => polynomial_roots(polynomial.trimmed()),
+        None => {
+            let root = numeric::solve(&difference, &unknown, 0.0, env)?;
+            Solutions::Values(vec![vec![root]])
+        }
+    };
+    Ok(Value::Solutions(SolutionSet {
+        unknowns: vec![unknown],
+        solutions,
+    }))
+}
+
+/// Collects the names in `expr` that have no value, in the order they first appear.
+/// The variables that calls such as `integrate` bind themselves do not count.
+pub fn unknowns(expr: &Node, env: &Environment, names: &mut Vec<String>) {
+    let Ok(found) = visit(&mut Unknowns { env }, expr);
+    merge(names, found);
+}
+
+/// Finds the unknowns of each node from those of its children, leaving out the names a call or
+/// closure binds.
+struct Unknowns<'e> {
+    env: &'e Environment,
+}
+
+impl<'a> Visitor<'a> for Unknowns<'_> {
+    type Output = Vec<String>;
+    type Error = Infallible;
+
+    /// The target of a conversion is a unit rather than an unknown.
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        match operation {
+            BinaryOperator::Conversion => vec![lhs],
+            _ => vec![lhs, rhs],
+        }
+    }
+
+    /// The second argument of a special form is what it binds.
+    fn walk_function(&mut self, name: &'a str, args: &'a [Node]) -> Vec<&'a Node> {
+        match functions::is_special_form(name) {
+            true => args[..1].iter().chain(&args[2..]).collect(),
+            false => args.iter().collect(),
+        }
+    }
+
+    fn visit_node(
+        &mut self,
+        _node: &'a Node,
+        outputs: Vec<Vec<String>>,
+    ) -> Result<Vec<String>, Infallible> {
+        let mut names = Vec::new();
+        for found in outputs {
+            merge(&mut names, found);
+        }
+        Ok(names)
+    }
+
+    fn visit_variable(
+        &mut self,
+        node: &'a Node,
+        name: &'a str,
+        _span: Span,
+    ) -> Result<Vec<String>, Infallible> {
+        Ok(match node.is_closed(self.env) {
+            true => Vec::new(),
+            false => vec![name.to_string()],
+        })
+    }
+
+    fn visit_function(
+        &mut self,
+        node: &'a Node,
+        name: &'a str,
+        args: &'a [Node],
+        _span: Span,
+        mut outputs: Vec<Vec<String>>,
+    ) -> Result<Vec<String>, Infallible> {
+        if functions::is_special_form(name) {
+            let bound: Vec<String> = match &args[1] {
+                Node::List { items, .. } => items.iter().map(|item| item.to_string()).collect(),
+                other => vec![other.to_string()],
+            };
+            outputs[0].retain(|name| !bound.contains(name));
+        }
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        _node: &'a Node,
+        params: &'a [String],
+        _body: &'a Node,
+        outputs: Vec<Vec<String>>,
+    ) -> Result<Vec<String>, Infallible> {
+        let mut names = outputs.into_iter().next().unwrap();
+        names.retain(|name| !params.contains(name));
+        Ok(names)
+    }
+}
+
+/// Adds the names of `found` that `names` does not have yet.
+fn merge(names: &mut Vec<String>, found: Vec<String>) {
+    for name in found {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+}
+
+/// A polynomial in one unknown as it is collected from an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    /// The coefficient of each power of the unknown, lowest power first.
+    pub coefficients: Vec<f64>,
+    /// How large the numbers that were added up into each coefficient were, so that a
+    /// coefficient that has cancelled down to rounding error can be told from a small one.
+    sizes: Vec<f64>,
+}
+
+impl Polynomial {
+    fn constant(value: f64) -> Polynomial {
+        Polynomial {
+            coefficients: vec![value],
+            sizes: vec![value.abs()],
+        }
+    }
+
+    fn add(&self, other: &Polynomial, sign: f64) -> Polynomial {
+        let at = |terms: &[f64], i: usize| terms.get(i).copied().unwrap_or(0.0);
+        let len = self.coefficients.len().max(other.coefficients.len());
+        Polynomial {
+            coefficients: (0..len)
+                .map(|i| at(&self.coefficients, i) + sign * at(&other.coefficients, i))
+                .collect(),
+            sizes: (0..len)
+                .map(|i| at(&self.sizes, i) + at(&other.sizes, i))
+                .collect(),
+        }
+    }
+
+    fn multiply(&self, other: &Polynomial) -> Option<Polynomial> {
+        let len = self.coefficients.len() + other.coefficients.len() - 1;
+        if len > MAX_DEGREE + 1 {
+            return None;
+        }
+        let mut product = Polynomial {
+            coefficients: vec![0.0; len],
+            sizes: vec![0.0; len],
+        };
+        for (i, (a, a_size)) in self.coefficients.iter().zip(&self.sizes).enumerate() {
+            for (j, (b, b_size)) in other.coefficients.iter().zip(&other.sizes).enumerate() {
+                product.coefficients[i + j] += a * b;
+                product.sizes[i + j] += a_size * b_size;
+            }
+        }
+        Some(product)
+    }
+
+    /// The coefficients with those that are only rounding error, like that of
+    /// `0.1x + 0.2x - 0.3x`, set to zero and the zero highest powers dropped.
+    pub fn trimmed(mut self) -> Vec<f64> {
+        for (c, size) in self.coefficients.iter_mut().zip(&self.sizes) {
+            if c.abs() <= 1e-12 * size {
+                *c = 0.0;
+            }
+        }
+        while self.coefficients.last() == Some(&0.0) {
+            self.coefficients.pop();
+        }
+        self.coefficients
+    }
+}
+
+/// `expr` as a polynomial in `unknown`, or `None` if it is not one.
+pub fn polynomial(expr: &Node, unknown: &str, env: &Environment) -> Option<Polynomial> {
+    visit(&mut Collector { unknown, env }, expr).ok()
+}
+
+/// Collects the polynomial of each node from those of its operands. Calls and other names have
+/// to be numbers that do not depend on the unknown, and are worked out rather than walked.
+struct Collector<'e> {
+    unknown: &'e str,
+    env: &'e Environment,
+}
+
+/// What [`Collector`] fails with when an expression is not a polynomial.
+struct NotPolynomial;
+
+impl<'a> Visitor<'a> for Collector<'_> {
+    type Output = Polynomial;
+    type Error = NotPolynomial;
+
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        match operation {
+            BinaryOperator::Conversion | BinaryOperator::Range | BinaryOperator::Equation => {
+                Vec::new()
+            }
+            _ => vec![lhs, rhs],
+        }
+    }
+
+    fn walk_function(&mut self, _name: &'a str, _args: &'a [Node]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_list(&mut self, _items: &'a [Node]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_matrix(&mut self, _rows: &'a [Vec<Node>]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_lambda(&mut self, _params: &'a [String], _body: &'a Node) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn visit_node(
+        &mut self,
+        node: &'a Node,
+        _outputs: Vec<Polynomial>,
+    ) -> Result<Polynomial, NotPolynomial> {
+        match node {
+            Node::Operand { value } => Ok(Polynomial::constant(*value)),
+            Node::Variable { name, .. } if name == self.unknown => Ok(Polynomial {
+                coefficients: vec![0.0, 1.0],
+                sizes: vec![0.0, 1.0],
+            }),
+            Node::List { .. } | Node::Matrix { .. } | Node::Lambda { .. } => Err(NotPolynomial),
+            // anything else has to be a number that does not depend on the unknown
+            _ => match node.calculate(self.env) {
+                Ok(Value::Number(value)) => Ok(Polynomial::constant(value)),
+                _ => Err(NotPolynomial),
+            },
+        }
+    }
+
+    fn visit_binary(
+        &mut self,
+        _node: &'a Node,
+        operation: BinaryOperator,
+        _lhs: &'a Node,
+        _rhs: &'a Node,
+        outputs: Vec<Polynomial>,
+    ) -> Result<Polynomial, NotPolynomial> {
+        let mut outputs = outputs.into_iter();
+        // conversions, ranges and equations have no operands walked
+        let (Some(lhs), Some(rhs)) = (outputs.next(), outputs.next()) else {
+            return Err(NotPolynomial);
+        };
+        match operation {
+            BinaryOperator::Addition => Some(lhs.add(&rhs, 1.0)),
+            BinaryOperator::Subtraction => Some(lhs.add(&rhs, -1.0)),
+            BinaryOperator::Multiplication => lhs.multiply(&rhs),
+            BinaryOperator::Division => match rhs.coefficients[..] {
+                [divisor] if divisor != 0.0 => lhs.multiply(&Polynomial::constant(1.0 / divisor)),
+                _ => None,
+            },
+            BinaryOperator::Index => match rhs.coefficients[..] {
+                [exponent]
+                    if exponent.fract() == 0.0
+                        && exponent >= 0.0
+                        && exponent <= MAX_DEGREE as f64 =>
+                {
+                    (0..exponent as usize)
+                        .try_fold(Polynomial::constant(1.0), |power, _| power.multiply(&lhs))
+                }
+                _ => None,
+            },
+            BinaryOperator::Conversion | BinaryOperator::Range | BinaryOperator::Equation => None,
+        }
+        .ok_or(NotPolynomial)
+    }
+
+    fn visit_negation(
+        &mut self,
+        _node: &'a Node,
+        _operand: &'a Node,
+        outputs: Vec<Polynomial>,
+    ) -> Result<Polynomial, NotPolynomial> {
+        Ok(Polynomial::constant(0.0).add(&outputs[0], -1.0))
+    }
+}
+
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c)
+}
+
+/// Every real root of the polynomial with these [trimmed](Polynomial::trimmed) coefficients,
+/// in increasing order.
+pub fn polynomial_roots(coefficients: Vec<f64>) -> Solutions {
+    let mut roots = match coefficients[..] {
+        [] => return Solutions::Infinite,
+        [_] => return Solutions::None,
+        [c, b] => vec![-c / b],
+        [c, b, a] => {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                return Solutions::None;
+            }
+            // the form that avoids subtracting nearly equal numbers
+            let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+            match q == 0.0 {
+                true => vec![0.0],
+                false => vec![q / a, c / q],
+            }
+        }
+        _ => real_roots(&coefficients),
+    };
+
+    // a root that is a whole number to within rounding is usually exactly that
+    for root in roots.iter_mut() {
+        if evaluate(&coefficients, root.round()) == 0.0 {
+            *root = root.round();
+        }
+    }
+    roots.sort_by(f64::total_cmp);
+    roots.dedup();
+    match roots.is_empty() {
+        true => Solutions::None,
+        false => Solutions::Values(roots.into_iter().map(|root| vec![root]).collect()),
+    }
+}
+
+/// Finds the real roots of a polynomial of any degree: between two neighbouring turning points,
+/// which are the roots of the derivative, there is at most one root.
+fn real_roots(coefficients: &[f64]) -> Vec<f64> {
+    if coefficients.len() == 2 {
+        return vec![-coefficients[0] / coefficients[1]];
+    }
+    let derivative: Vec<f64> = coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(power, c)| power as f64 * c)
+        .collect();
+    let leading = coefficients.last().unwrap();
+    // every root lies within this bound
+    let bound = 1.0
+        + coefficients[..coefficients.len() - 1]
+            .iter()
+            .fold(0.0_f64, |max, c| max.max((c / leading).abs()));
+
+    let mut points = vec![-bound];
+    points.extend(
+        real_roots(&derivative)
+            .into_iter()
+            .filter(|x| x.abs() < bound),
+    );
+    points.push(bound);
+
+    let mut roots = Vec::new();
+    for pair in points.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (evaluate(coefficients, a), evaluate(coefficients, b));
+        if fa == 0.0 {
+            roots.push(a);
+        }
+        if fa.signum() == fb.signum() || fb == 0.0 {
+            continue;
+        }
+        for _ in 0..200 {
+            let m = (a + b) / 2.0;
+            if m == a || m == b {
+                break;
+            }
+            match evaluate(coefficients, m).signum() == fa.signum() {
+                true => a = m,
+                false => b = m,
+            }
+        }
+        roots.push((a + b) / 2.0);
+    }
+    if evaluate(coefficients, bound) == 0.0 {
+        roots.push(bound);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::evaluate;
+    use pretty_assertions::assert_eq;
+
+    fn solved(input: &str) -> String {
+        match evaluate(input, &Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_linear() {
+        assert_eq!(solved("3x + 5 = 20"), "x = 5");
+        assert_eq!(solved("2(y - 1) = y/2"), "y = 1.3333333333333333");
+    }
+
+    #[test]
+    fn test_quadratic() {
+        assert_eq!(solved("x^2 - 5x + 6 = 0"), "x = 2, x = 3");
+        assert_eq!(solved("(x - 1)^2 = 0"), "x = 1");
+        assert_eq!(solved("x^2 = -1"), "no solution");
+    }
+
+    #[test]
+    fn test_no_or_infinite_solutions() {
+        assert_eq!(solved("x + 1 = x + 2"), "no solution");
+        assert_eq!(solved("2(x + 1) = 2x + 2"), "infinitely many solutions");
+        assert_eq!(solved("0.1x + 0.2x = 0.3x"), "infinitely many solutions");
+    }
+
+    #[test]
+    fn test_higher_degree() {
+        assert_eq!(
+            solved("(x - 1)(x - 2)(x + 3)(x - 4) = 0"),
+            "x = -3, x = 1, x = 2, x = 4"
+        );
+        assert_eq!(solved("x^3 = 8"), "x = 2");
+        assert_eq!(solved("x^4 + 1 = 0"), "no solution");
+    }
+
+    #[test]
+    fn test_not_a_polynomial() {
+        assert_eq!(solved("2^x = 8"), "x = 3");
+        // solving starts from 0, where these are only defined on one side
+        assert_eq!(solved("sqrt(x) = 3"), "x = 9");
+        assert_eq!(solved("x^0.5 = 2"), "x = 4");
+        assert_eq!(solved("x^(1/3) + 1 = 3"), "x = 8");
+    }
+
+    #[test]
+    fn test_known_names_are_constants() {
+        let mut env = Environment::new();
+        env.set("a", 2.0.into());
+        assert_eq!(
+            evaluate("a*x = 6", &env).map(|value| value.to_string()),
+            Ok("x = 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unknown_count() {
+        assert_eq!(
+            solved("2 + 2 = 4"),
+            "Error: 2 + 2 = 4 has no unknown to solve for"
+        );
+        assert_eq!(
+            solved("x + y = 1"),
+            "Error: Cannot solve one equation for 2 unknowns: x, y"
+        );
+        assert_eq!(solved("integrate(1, t, 0, x) = 2"), "x = 2");
+    }
+}
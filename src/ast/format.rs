@@ -0,0 +1,47 @@
+use crate::{
+    ast::{environment::Environment, value::Value},
+    datetime::format_duration,
+    units::dimension::Dimension,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    /// `2026-10-18T14:30:00`
+    #[default]
+    Iso,
+    /// `Sunday 18 October 2026 14:30:00`
+    Long,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DurationFormat {
+    /// In the unit the duration was written or converted in, e.g. `13.3333333333 h`.
+    #[default]
+    Decimal,
+    /// Split into days, hours, minutes and seconds, e.g. `13h 20min`.
+    Compound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputFormat {
+    pub dates: DateFormat,
+    pub durations: DurationFormat,
+}
+
+/// Formats a result for printing, noting the date of the exchange rates when money is involved.
+pub fn describe(value: &Value, env: &Environment, format: &OutputFormat) -> String {
+    match value {
+        Value::DateTime(date) if format.dates == DateFormat::Long => date.to_long_string(),
+        Value::Quantity(quantity)
+            if quantity.dimension == Dimension::TIME
+                && format.durations == DurationFormat::Compound =>
+        {
+            format_duration(quantity.value)
+        }
+        Value::Quantity(quantity) if quantity.dimension.involves_currency() => match env.rates() {
+            Some(rates) => format!("{} (rates as of {})", value, rates.as_of()),
+            None => value.to_string(),
+        },
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    ast::{closure, matrix, statistics, value::Value},
+    units::quantity::Quantity,
+    utils::operators::BinaryOperator,
+};
+
+/// A function of one number that evaluates its argument like any other value.
+pub struct Builtin {
+    pub name: &'static str,
+    pub apply: fn(f64) -> f64,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "sin",
+        apply: f64::sin,
+    },
+    Builtin {
+        name: "cos",
+        apply: f64::cos,
+    },
+    Builtin {
+        name: "tan",
+        apply: f64::tan,
+    },
+    Builtin {
+        name: "asin",
+        apply: f64::asin,
+    },
+    Builtin {
+        name: "acos",
+        apply: f64::acos,
+    },
+    Builtin {
+        name: "atan",
+        apply: f64::atan,
+    },
+    Builtin {
+        name: "sinh",
+        apply: f64::sinh,
+    },
+    Builtin {
+        name: "cosh",
+        apply: f64::cosh,
+    },
+    Builtin {
+        name: "tanh",
+        apply: f64::tanh,
+    },
+    Builtin {
+        name: "exp",
+        apply: f64::exp,
+    },
+    Builtin {
+        name: "ln",
+        apply: f64::ln,
+    },
+    Builtin {
+        name: "log",
+        apply: f64::log10,
+    },
+    Builtin {
+        name: "sqrt",
+        apply: f64::sqrt,
+    },
+    Builtin {
+        name: "abs",
+        apply: f64::abs,
+    },
+];
+
+/// Functions that work on the expressions they are given rather than on their values,
+/// with the fewest and most arguments each takes.
+pub const SPECIAL_FORMS: &[(&str, usize, usize)] = &[
+    ("diff", 2, 2),
+    ("integrate", 4, 4),
+    ("solve", 2, 3),
+    ("nsum", 4, 4),
+];
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+pub fn is_special_form(name: &str) -> bool {
+    SPECIAL_FORMS.iter().any(|(form, _, _)| *form == name)
+}
+
+/// Whether `name` followed by `(` is a call rather than a value multiplied by a bracket.
+pub fn is_function(name: &str) -> bool {
+    builtin(name).is_some()
+        || is_special_form(name)
+        || is_matrix_function(name)
+        || statistics::is_statistic(name)
+        || closure::is_higher_order(name)
+}
+
+pub fn is_matrix_function(name: &str) -> bool {
+    matrix::FUNCTIONS
+        .iter()
+        .any(|(function, _)| *function == name)
+}
+
+/// The fewest and most arguments a call to `name` takes, with no most for a statistic.
+fn arity(name: &str) -> (usize, Option<usize>) {
+    if let Some((_, fewest, most)) = SPECIAL_FORMS
+        .iter()
+        .chain(closure::FUNCTIONS)
+        .find(|(form, _, _)| *form == name)
+    {
+        return (*fewest, Some(*most));
+    }
+    if let Some((_, arity)) = matrix::FUNCTIONS
+        .iter()
+        .find(|(function, _)| *function == name)
+    {
+        return (*arity, Some(*arity));
+    }
+    match statistics::FUNCTIONS
+        .iter()
+        .find(|(function, _)| *function == name)
+    {
+        Some((_, fewest)) => (*fewest, None),
+        None => (1, Some(1)),
+    }
+}
+
+/// Checks that a call to `name` has the right number of arguments.
+pub fn check_arity(name: &str, count: usize) -> Result<(), String> {
+    let (fewest, most) = arity(name);
+    if count < fewest || most.is_some_and(|most| count > most) {
+        let expected = match most {
+            None => format!("at least {}", fewest),
+            Some(most) if most == fewest => fewest.to_string(),
+            Some(most) => format!("{} or {}", fewest, most),
+        };
+        return Err(format!(
+            "{} expects {} argument{}, found {}",
+            name,
+            expected,
+            if most.unwrap_or(fewest) == 1 { "" } else { "s" },
+            count
+        ));
+    }
+    Ok(())
+}
+
+/// Applies a builtin to an evaluated argument. `sqrt` and `abs` also work on quantities,
+/// and every builtin works on each entry of a matrix or item of a list.
+pub fn apply(builtin: &Builtin, argument: Value) -> Result<Value, String> {
+    match (builtin.name, argument) {
+        (_, Value::Number(value)) => Ok(Value::Number((builtin.apply)(value))),
+        (_, Value::Matrix(matrix)) => Ok(Value::Matrix(matrix.map(builtin.apply))),
+        (_, Value::List(items)) => Ok(Value::List(items.into_iter().map(builtin.apply).collect())),
+        ("sqrt", argument @ Value::Quantity(_)) => {
+            Value::apply(BinaryOperator::Index, argument, Value::Number(0.5))
+        }
+        ("abs", Value::Quantity(quantity)) => Ok(Value::Quantity(Quantity {
+            value: quantity.value.abs(),
+            ..quantity
+        })),
+        (name, argument) => Err(format!("{} expects a number, found {}", name, argument)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_arity() {
+        assert_eq!(check_arity("sin", 1), Ok(()));
+        assert_eq!(
+            check_arity("diff", 1),
+            Err("diff expects 2 arguments, found 1".to_string())
+        );
+        assert_eq!(
+            check_arity("solve", 4),
+            Err("solve expects 2 or 3 arguments, found 4".to_string())
+        );
+        assert_eq!(check_arity("mean", 12), Ok(()));
+        assert_eq!(
+            check_arity("max", 0),
+            Err("max expects at least 1 argument, found 0".to_string())
+        );
+        assert_eq!(
+            check_arity("percentile", 1),
+            Err("percentile expects at least 2 arguments, found 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(
+            apply(builtin("sqrt").unwrap(), Value::Number(9.0)),
+            Ok(Value::Number(3.0))
+        );
+    }
+}
@@ -0,0 +1,648 @@
+use std::{cmp::Ordering, marker::PhantomData};
+
+use crate::{
+    ast::{
+        ast::Node,
+        derivative::contains,
+        environment::Environment,
+        equation::{SolutionSet, Solutions},
+        value::Value,
+        visit::{Visitor, visit},
+    },
+    utils::{error::CalcError, operators::BinaryOperator},
+};
+
+/// A number that Gaussian elimination can work with. Each operation gives `None` when its result
+/// cannot be represented, so that an exact calculation can start again with floats.
+pub trait Scalar: Copy + PartialEq {
+    fn from_f64(value: f64) -> Option<Self>;
+    fn to_f64(self) -> f64;
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn add(self, other: Self) -> Option<Self>;
+    fn sub(self, other: Self) -> Option<Self>;
+    fn mul(self, other: Self) -> Option<Self>;
+    fn div(self, other: Self) -> Option<Self>;
+    /// Whether the value is zero, or so small next to `scale` that it can only be rounding error.
+    fn is_negligible(self, scale: f64) -> bool;
+}
+
+impl Scalar for f64 {
+    fn from_f64(value: f64) -> Option<f64> {
+        Some(value)
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn zero() -> f64 {
+        0.0
+    }
+
+    fn one() -> f64 {
+        1.0
+    }
+
+    fn add(self, other: f64) -> Option<f64> {
+        Some(self + other)
+    }
+
+    fn sub(self, other: f64) -> Option<f64> {
+        Some(self - other)
+    }
+
+    fn mul(self, other: f64) -> Option<f64> {
+        Some(self * other)
+    }
+
+    fn div(self, other: f64) -> Option<f64> {
+        Some(self / other)
+    }
+
+    fn is_negligible(self, scale: f64) -> bool {
+        self.abs() <= 1e-12 * scale
+    }
+}
+
+/// An exact fraction, always in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ratio {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Ratio {
+    fn new(numerator: i128, denominator: i128) -> Option<Ratio> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator.unsigned_abs(), denominator.unsigned_abs()).max(1) as i128;
+        let sign = denominator.signum();
+        Some(Ratio {
+            numerator: numerator.checked_div(divisor)?.checked_mul(sign)?,
+            denominator: denominator.checked_div(divisor)?.checked_mul(sign)?,
+        })
+    }
+}
+
+impl Scalar for Ratio {
+    /// The fraction for a number as it prints, so that `0.1` is exactly a tenth.
+    fn from_f64(value: f64) -> Option<Ratio> {
+        if !value.is_finite() {
+            return None;
+        }
+        let text = value.to_string();
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let numerator: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        let denominator = 10i128.checked_pow(fraction.len() as u32)?;
+        Ratio::new(numerator, denominator)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    fn zero() -> Ratio {
+        Ratio {
+            numerator: 0,
+            denominator: 1,
+        }
+    }
+
+    fn one() -> Ratio {
+        Ratio {
+            numerator: 1,
+            denominator: 1,
+        }
+    }
+
+    fn add(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.numerator
+                .checked_mul(other.denominator)?
+                .checked_add(other.numerator.checked_mul(self.denominator)?)?,
+            self.denominator.checked_mul(other.denominator)?,
+        )
+    }
+
+    fn sub(self, other: Ratio) -> Option<Ratio> {
+        self.add(Ratio {
+            numerator: other.numerator.checked_neg()?,
+            ..other
+        })
+    }
+
+    fn mul(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.numerator.checked_mul(other.numerator)?,
+            self.denominator.checked_mul(other.denominator)?,
+        )
+    }
+
+    fn div(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.numerator.checked_mul(other.denominator)?,
+            self.denominator.checked_mul(other.numerator)?,
+        )
+    }
+
+    fn is_negligible(self, _scale: f64) -> bool {
+        self.numerator == 0
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+/// What elimination makes of a system of linear equations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Elimination<S> {
+    Unique(Vec<S>),
+    /// Some combination of the equations reduces to `0 = b` for a `b` that is not zero.
+    Inconsistent,
+    /// Fewer independent equations than unknowns.
+    Underdetermined,
+}
+
+/// Solves the system whose augmented matrix is `rows`, each row being the coefficients of the
+/// unknowns followed by the right-hand side, by Gaussian elimination with partial pivoting.
+/// Gives `None` if a number along the way cannot be represented.
+pub fn eliminate<S: Scalar>(mut rows: Vec<Vec<S>>, unknowns: usize) -> Option<Elimination<S>> {
+    let scale = largest(&rows);
+    let mut rank = 0;
+    for column in 0..unknowns {
+        if let Pivot::Found { .. } = reduce_column(&mut rows, rank, column, scale)? {
+            rank += 1;
+        }
+    }
+
+    if rows[rank..]
+        .iter()
+        .any(|row| !row[unknowns].is_negligible(scale))
+    {
+        return Some(Elimination::Inconsistent);
+    }
+    if rank < unknowns {
+        return Some(Elimination::Underdetermined);
+    }
+
+    // with a pivot in every column, row `i` is the one that gives unknown `i`
+    let mut solution = vec![S::zero(); unknowns];
+    for row in (0..unknowns).rev() {
+        let mut sum = rows[row][unknowns];
+        for column in row + 1..unknowns {
+            sum = sum.sub(rows[row][column].mul(solution[column])?)?;
+        }
+        solution[row] = sum.div(rows[row][row])?;
+    }
+    Some(Elimination::Unique(solution))
+}
+
+/// The determinant of the square matrix `rows`, the product of the pivots that elimination
+/// finds with a change of sign for every swap of rows.
+pub fn determinant<S: Scalar>(mut rows: Vec<Vec<S>>) -> Option<S> {
+    let scale = largest(&rows);
+    let mut determinant = S::one();
+    for column in 0..rows.len() {
+        match reduce_column(&mut rows, column, column, scale)? {
+            Pivot::Missing => return Some(S::zero()),
+            Pivot::Found { swapped } => {
+                if swapped {
+                    determinant = S::zero().sub(determinant)?;
+                }
+                determinant = determinant.mul(rows[column][column])?;
+            }
+        }
+    }
+    Some(determinant)
+}
+
+fn largest<S: Scalar>(rows: &[Vec<S>]) -> f64 {
+    rows.iter()
+        .flatten()
+        .fold(0.0_f64, |max, value| max.max(value.to_f64().abs()))
+}
+
+enum Pivot {
+    Missing,
+    Found { swapped: bool },
+}
+
+/// Moves the row with the largest entry in `column`, from `rank` down, up to `rank` and clears
+/// the entries below it. The largest entry makes the best pivot, as it keeps the multipliers
+/// below one.
+fn reduce_column<S: Scalar>(
+    rows: &mut [Vec<S>],
+    rank: usize,
+    column: usize,
+    scale: f64,
+) -> Option<Pivot> {
+    let magnitude = |value: S| value.to_f64().abs();
+    let pivot = (rank..rows.len())
+        .filter(|&row| !rows[row][column].is_negligible(scale))
+        .max_by(|&a, &b| {
+            magnitude(rows[a][column])
+                .partial_cmp(&magnitude(rows[b][column]))
+                .unwrap_or(Ordering::Equal)
+        });
+    let Some(pivot) = pivot else {
+        return Some(Pivot::Missing);
+    };
+    rows.swap(rank, pivot);
+    let (upper, lower) = rows.split_at_mut(rank + 1);
+    let pivot_row = &upper[rank];
+    for row in lower {
+        let factor = row[column].div(pivot_row[column])?;
+        row[column] = S::zero();
+        for (entry, above) in row[column + 1..].iter_mut().zip(&pivot_row[column + 1..]) {
+            *entry = entry.sub(factor.mul(*above)?)?;
+        }
+    }
+    Some(Pivot::Found {
+        swapped: pivot != rank,
+    })
+}
+
+/// Why the coefficients of an equation could not be collected.
+enum Failure {
+    NotLinear,
+    /// A number could not be represented exactly.
+    Inexact,
+    Error(CalcError),
+}
+
+impl From<CalcError> for Failure {
+    fn from(error: CalcError) -> Failure {
+        Failure::Error(error)
+    }
+}
+
+/// Solves `solve({equations}, {unknowns})`, a system of equations that are each linear in the
+/// unknowns. The elimination is done in exact fractions where the numbers allow it.
+pub fn solve_system(
+    equations: &Node,
+    unknowns: &Node,
+    env: &Environment,
+) -> Result<Value, CalcError> {
+    let equations = match equations {
+        Node::List { items, .. }
+            if items.iter().all(|item| {
+                matches!(
+                    item,
+                    Node::BinaryExpr {
+                        operation: BinaryOperator::Equation,
+                        ..
+                    }
+                )
+            }) =>
+        {
+            items
+        }
+        other => {
+            return Err(CalcError::new(format!(
+                "solve expects a list of equations, found {}",
+                other
+            )));
+        }
+    };
+    let mut names: Vec<String> = Vec::new();
+    match unknowns {
+        Node::List { items, .. } => {
+            for item in items {
+                match item {
+                    Node::Variable { name, .. } if !names.contains(name) => {
+                        names.push(name.clone())
+                    }
+                    _ => {
+                        return Err(CalcError::new(format!(
+                            "solve expects a list of different unknowns, found {}",
+                            unknowns
+                        )));
+                    }
+                }
+            }
+        }
+        other => {
+            return Err(CalcError::new(format!(
+                "solve expects a list of unknowns, found {}",
+                other
+            )));
+        }
+    }
+
+    let solution = match system::<Ratio>(equations, &names, env) {
+        Ok(rows) => eliminate(rows, names.len())
+            .map(|solution| solution.map(Ratio::to_f64))
+            .map(Ok),
+        Err(Failure::Inexact) => None,
+        Err(Failure::NotLinear) => Some(Err(Failure::NotLinear)),
+        Err(Failure::Error(error)) => return Err(error),
+    };
+    let solution = match solution {
+        Some(solution) => solution,
+        None => system::<f64>(equations, &names, env).map(|rows| {
+            eliminate(rows, names.len()).expect("float arithmetic always has a result")
+        }),
+    };
+    let solutions = match solution {
+        Ok(Elimination::Unique(values)) => Solutions::Values(vec![values]),
+        Ok(Elimination::Inconsistent) => Solutions::None,
+        Ok(Elimination::Underdetermined) => Solutions::Infinite,
+        Err(Failure::NotLinear) => {
+            return Err(CalcError::new(format!(
+                "solve can only solve equations that are linear in {}",
+                names.join(", ")
+            )));
+        }
+        Err(Failure::Error(error)) => return Err(error),
+        Err(Failure::Inexact) => unreachable!(),
+    };
+    Ok(Value::Solutions(SolutionSet {
+        unknowns: names,
+        solutions,
+    }))
+}
+
+impl<S> Elimination<S> {
+    fn map<T>(self, f: impl Fn(S) -> T) -> Elimination<T> {
+        match self {
+            Elimination::Unique(values) => Elimination::Unique(values.into_iter().map(f).collect()),
+            Elimination::Inconsistent => Elimination::Inconsistent,
+            Elimination::Underdetermined => Elimination::Underdetermined,
+        }
+    }
+}
+
+/// The augmented matrix of the equations.
+fn system<S: Scalar>(
+    equations: &[Node],
+    unknowns: &[String],
+    env: &Environment,
+) -> Result<Vec<Vec<S>>, Failure> {
+    equations
+        .iter()
+        .map(|equation| {
+            let Node::BinaryExpr { lhs, rhs, .. } = equation else {
+                unreachable!()
+            };
+            let lhs = linear::<S>(lhs, unknowns, env)?;
+            let rhs = linear::<S>(rhs, unknowns, env)?;
+            // a·x + c = b·x + d becomes (a - b)·x = d - c
+            let mut row = combine(&lhs, &rhs, S::sub)?;
+            let last = row.len() - 1;
+            row[last] = S::zero().sub(row[last]).ok_or(Failure::Inexact)?;
+            Ok(row)
+        })
+        .collect()
+}
+
+fn combine<S: Scalar>(
+    lhs: &[S],
+    rhs: &[S],
+    operation: fn(S, S) -> Option<S>,
+) -> Result<Vec<S>, Failure> {
+    lhs.iter()
+        .zip(rhs)
+        .map(|(a, b)| operation(*a, *b).ok_or(Failure::Inexact))
+        .collect()
+}
+
+/// The coefficient of each unknown in `expr`, followed by its constant term.
+fn linear<S: Scalar>(
+    expr: &Node,
+    unknowns: &[String],
+    env: &Environment,
+) -> Result<Vec<S>, Failure> {
+    visit(
+        &mut Coefficients {
+            unknowns,
+            env,
+            scalar: PhantomData,
+        },
+        expr,
+    )
+}
+
+/// Collects the coefficients of each node from those of its operands. Anything other than an
+/// operation has to be a constant, and is worked out rather than walked.
+struct Coefficients<'e, S> {
+    unknowns: &'e [String],
+    env: &'e Environment,
+    scalar: PhantomData<S>,
+}
+
+impl<S: Scalar> Coefficients<'_, S> {
+    fn constant(&self, value: S) -> Vec<S> {
+        let mut terms = vec![S::zero(); self.unknowns.len() + 1];
+        terms[self.unknowns.len()] = value;
+        terms
+    }
+}
+
+impl<'a, S: Scalar> Visitor<'a> for Coefficients<'_, S> {
+    type Output = Vec<S>;
+    type Error = Failure;
+
+    fn walk_function(&mut self, _name: &'a str, _args: &'a [Node]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_list(&mut self, _items: &'a [Node]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_matrix(&mut self, _rows: &'a [Vec<Node>]) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn walk_lambda(&mut self, _params: &'a [String], _body: &'a Node) -> Vec<&'a Node> {
+        Vec::new()
+    }
+
+    fn visit_node(&mut self, node: &'a Node, _outputs: Vec<Vec<S>>) -> Result<Vec<S>, Failure> {
+        match node {
+            Node::Operand { value } => Ok(self.constant(number(*value)?)),
+            Node::Variable { name, .. } => {
+                match self.unknowns.iter().position(|unknown| unknown == name) {
+                    Some(index) => {
+                        let mut terms = self.constant(S::zero());
+                        terms[index] = S::one();
+                        Ok(terms)
+                    }
+                    None => Ok(self.constant(number(evaluate(node, self.env)?)?)),
+                }
+            }
+            _ => match self.unknowns.iter().any(|unknown| contains(node, unknown)) {
+                true => Err(Failure::NotLinear),
+                false => Ok(self.constant(number(evaluate(node, self.env)?)?)),
+            },
+        }
+    }
+
+    fn visit_binary(
+        &mut self,
+        node: &'a Node,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        _rhs: &'a Node,
+        outputs: Vec<Vec<S>>,
+    ) -> Result<Vec<S>, Failure> {
+        let mut outputs = outputs.into_iter();
+        let (lhs_terms, rhs_terms) = (outputs.next().unwrap(), outputs.next().unwrap());
+        let scale = |terms: &[S], factor: S| combine(terms, &vec![factor; terms.len()], S::mul);
+        let constant_term = |terms: &[S]| match mentions(terms) {
+            true => None,
+            false => terms.last().copied(),
+        };
+        match operation {
+            BinaryOperator::Addition => combine(&lhs_terms, &rhs_terms, S::add),
+            BinaryOperator::Subtraction => combine(&lhs_terms, &rhs_terms, S::sub),
+            BinaryOperator::Multiplication => {
+                match (constant_term(&lhs_terms), constant_term(&rhs_terms)) {
+                    (Some(factor), _) => scale(&rhs_terms, factor),
+                    (_, Some(factor)) => scale(&lhs_terms, factor),
+                    _ => Err(Failure::NotLinear),
+                }
+            }
+            BinaryOperator::Division => match constant_term(&rhs_terms) {
+                Some(divisor) if divisor != S::zero() => {
+                    scale(&lhs_terms, S::one().div(divisor).ok_or(Failure::Inexact)?)
+                }
+                Some(_) => Err(CalcError::new(format!("{} divides by zero", node)).into()),
+                None => Err(Failure::NotLinear),
+            },
+            BinaryOperator::Index => match constant_term(&rhs_terms).map(S::to_f64) {
+                Some(exponent) if constant_term(&lhs_terms).is_some() => {
+                    Ok(self.constant(number(evaluate(lhs, self.env)?.powf(exponent))?))
+                }
+                Some(1.0) => Ok(lhs_terms),
+                Some(0.0) => Ok(self.constant(S::one())),
+                _ => Err(Failure::NotLinear),
+            },
+            BinaryOperator::Conversion | BinaryOperator::Range | BinaryOperator::Equation => {
+                Err(Failure::NotLinear)
+            }
+        }
+    }
+
+    fn visit_negation(
+        &mut self,
+        _node: &'a Node,
+        _operand: &'a Node,
+        outputs: Vec<Vec<S>>,
+    ) -> Result<Vec<S>, Failure> {
+        combine(&self.constant(S::zero()), &outputs[0], S::sub)
+    }
+}
+
+fn number<S: Scalar>(value: f64) -> Result<S, Failure> {
+    S::from_f64(value).ok_or(Failure::Inexact)
+}
+
+/// Whether any unknown has a coefficient that is not zero.
+fn mentions<S: Scalar>(terms: &[S]) -> bool {
+    terms[..terms.len() - 1]
+        .iter()
+        .any(|term| *term != S::zero())
+}
+
+fn evaluate(expr: &Node, env: &Environment) -> Result<f64, CalcError> {
+    match expr.calculate(env)? {
+        Value::Number(value) => Ok(value),
+        other => Err(CalcError::new(format!(
+            "solve needs {} to be a number, found {}",
+            expr, other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::evaluate;
+    use pretty_assertions::assert_eq;
+
+    fn solved(input: &str) -> String {
+        match evaluate(input, &Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_two_equations() {
+        assert_eq!(
+            solved("solve({2x + y = 5, x - y = 1}, {x, y})"),
+            "x = 2, y = 1"
+        );
+    }
+
+    #[test]
+    fn test_exact_fractions() {
+        // in floats 0.1 and 0.2 do not add up to exactly 0.3
+        assert_eq!(
+            solved("solve({0.1a + 0.2b = 0.3, a - b = 0}, {a, b})"),
+            "a = 1, b = 1"
+        );
+        assert_eq!(solved("solve({3x = 1}, {x})"), "x = 0.3333333333333333");
+    }
+
+    #[test]
+    fn test_pivoting() {
+        // without a row swap the first pivot is zero
+        assert_eq!(
+            solved("solve({y + z = 3, x + y = 2, x + z = 3}, {x, y, z})"),
+            "x = 1, y = 1, z = 2"
+        );
+    }
+
+    #[test]
+    fn test_singular_and_underdetermined() {
+        assert_eq!(
+            solved("solve({x + y = 1, 2x + 2y = 3}, {x, y})"),
+            "no solution"
+        );
+        assert_eq!(
+            solved("solve({x + y = 1, 2x + 2y = 2}, {x, y})"),
+            "infinitely many solutions"
+        );
+        assert_eq!(
+            solved("solve({x + y + z = 1}, {x, y, z})"),
+            "infinitely many solutions"
+        );
+    }
+
+    #[test]
+    fn test_not_linear() {
+        assert_eq!(
+            solved("solve({x*y = 1, x = 1}, {x, y})"),
+            "Error: solve can only solve equations that are linear in x, y"
+        );
+        assert_eq!(
+            solved("solve({sin(x) = 1, y = 1}, {x, y})"),
+            "Error: solve can only solve equations that are linear in x, y"
+        );
+    }
+
+    #[test]
+    fn test_float_fallback() {
+        let big = 1e30;
+        let rows = vec![vec![big, 1.0, 1.0], vec![1.0, big, 2.0]];
+        let exact = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| Ratio::from_f64(*value).unwrap())
+                    .collect()
+            })
+            .collect();
+        // the fractions grow past what fits in an i128
+        assert_eq!(eliminate::<Ratio>(exact, 2), None);
+        assert!(matches!(eliminate(rows, 2), Some(Elimination::Unique(_))));
+    }
+}
@@ -0,0 +1,460 @@
+use std::fmt;
+
+use crate::{
+    ast::{
+        linear::{self, Elimination, Ratio, Scalar},
+        value::{Value, equation_error},
+    },
+    utils::operators::BinaryOperator,
+};
+
+/// A matrix of plain numbers. A vector is a matrix with one row or one column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    /// Row by row.
+    entries: Vec<f64>,
+}
+
+/// Functions of matrices and vectors, with how many arguments each takes.
+pub const FUNCTIONS: &[(&str, usize)] = &[
+    ("det", 1),
+    ("inv", 1),
+    ("transpose", 1),
+    ("dot", 2),
+    ("cross", 2),
+];
+
+impl Matrix {
+    /// A matrix from its rows, which must all be the same length.
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Matrix {
+        let columns = rows.first().map_or(0, Vec::len);
+        debug_assert!(rows.iter().all(|row| row.len() == columns));
+        Matrix {
+            rows: rows.len(),
+            columns,
+            entries: rows.into_iter().flatten().collect(),
+        }
+    }
+
+    fn identity(size: usize) -> Matrix {
+        Matrix::from_rows(
+            (0..size)
+                .map(|row| {
+                    (0..size)
+                        .map(|column| (row == column) as u8 as f64)
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.entries[row * self.columns + column]
+    }
+
+    pub fn entries(&self) -> &[f64] {
+        &self.entries
+    }
+
+    fn row(&self, row: usize) -> &[f64] {
+        &self.entries[row * self.columns..(row + 1) * self.columns]
+    }
+
+    fn to_rows(&self) -> Vec<Vec<f64>> {
+        (0..self.rows).map(|row| self.row(row).to_vec()).collect()
+    }
+
+    /// Its rows and columns, like `2×3`.
+    pub fn shape(&self) -> String {
+        format!("{}×{}", self.rows, self.columns)
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.rows == 1 || self.columns == 1
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Matrix {
+        Matrix {
+            entries: self.entries.iter().map(|entry| f(*entry)).collect(),
+            ..*self
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        Matrix::from_rows(
+            (0..self.columns)
+                .map(|column| (0..self.rows).map(|row| self.get(row, column)).collect())
+                .collect(),
+        )
+    }
+
+    /// Combines the entries in the same place in two matrices of the same shape.
+    fn zip(&self, other: &Matrix, f: impl Fn(f64, f64) -> f64) -> Matrix {
+        Matrix {
+            entries: self
+                .entries
+                .iter()
+                .zip(&other.entries)
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+            ..*self
+        }
+    }
+
+    fn product(&self, other: &Matrix) -> Matrix {
+        Matrix::from_rows(
+            (0..self.rows)
+                .map(|row| {
+                    (0..other.columns)
+                        .map(|column| {
+                            (0..self.columns)
+                                .map(|k| self.get(row, k) * other.get(k, column))
+                                .sum()
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    fn check_square(&self, what: &str) -> Result<(), String> {
+        match self.rows == self.columns {
+            true => Ok(()),
+            false => Err(format!(
+                "Only a square matrix has {}, found {} which is {}",
+                what,
+                self,
+                self.shape()
+            )),
+        }
+    }
+
+    /// Worked out in exact fractions when every entry fits, otherwise in floating point.
+    pub fn determinant(&self) -> Result<f64, String> {
+        self.check_square("a determinant")?;
+        Ok(match exact(&self.to_rows()).and_then(linear::determinant) {
+            Some(determinant) => determinant.to_f64(),
+            None => linear::determinant(self.to_rows()).unwrap(),
+        })
+    }
+
+    /// Worked out a column at a time by solving against the identity matrix, in exact fractions
+    /// when every entry fits, otherwise in floating point.
+    pub fn inverse(&self) -> Result<Matrix, String> {
+        self.check_square("an inverse")?;
+        let inverse = match exact(&self.to_rows()).and_then(|rows| inverse(&rows)) {
+            Some(inverse) => inverse.map(|columns| {
+                columns
+                    .into_iter()
+                    .map(|column| column.into_iter().map(Ratio::to_f64).collect())
+                    .collect()
+            }),
+            None => inverse(&self.to_rows()).unwrap(),
+        };
+        match inverse {
+            Some(columns) => Ok(Matrix::from_rows(columns).transpose()),
+            None => Err(format!("{} is singular and has no inverse", self)),
+        }
+    }
+
+    /// Raises a square matrix to a whole power by repeated squaring. A negative power is a
+    /// power of the inverse.
+    fn power(&self, exponent: f64) -> Result<Matrix, String> {
+        self.check_square("powers")?;
+        if exponent.fract() != 0.0 {
+            return Err(format!(
+                "A matrix can only be raised to a whole power, found {}",
+                exponent
+            ));
+        }
+        let mut base = match exponent < 0.0 {
+            true => self.inverse()?,
+            false => self.clone(),
+        };
+        let mut exponent = exponent.abs();
+        let mut result = Matrix::identity(self.rows);
+        while exponent > 0.0 {
+            if exponent % 2.0 == 1.0 {
+                result = result.product(&base);
+            }
+            base = base.product(&base);
+            exponent = (exponent / 2.0).floor();
+        }
+        Ok(result)
+    }
+
+    /// Applies an operator where at least one side is a matrix and the other a matrix or a number.
+    /// `+` and `-` work entry by entry, `*` and `/` are the matrix product and the product with
+    /// the inverse, and a number on either side of any operator applies to every entry.
+    pub fn apply(operation: BinaryOperator, lhs: Value, rhs: Value) -> Result<Value, String> {
+        use BinaryOperator::*;
+
+        let mismatch = |lhs: &Matrix, rhs: &Matrix| {
+            Err(format!(
+                "Shape mismatch: cannot {} {} which is {} and {} which is {}",
+                operation.verb(),
+                lhs,
+                lhs.shape(),
+                rhs,
+                rhs.shape()
+            ))
+        };
+        let result = match (operation, lhs, rhs) {
+            (Conversion, _, rhs) => return Err(format!("{} is not a unit", rhs)),
+            (Equation, _, _) => return Err(equation_error()),
+            (Addition | Subtraction, Value::Matrix(lhs), Value::Matrix(rhs)) => {
+                if lhs.rows != rhs.rows || lhs.columns != rhs.columns {
+                    return mismatch(&lhs, &rhs);
+                }
+                lhs.zip(&rhs, |a, b| match operation {
+                    Addition => a + b,
+                    _ => a - b,
+                })
+            }
+            (Multiplication, Value::Matrix(lhs), Value::Matrix(rhs)) => {
+                if lhs.columns != rhs.rows {
+                    return mismatch(&lhs, &rhs);
+                }
+                lhs.product(&rhs)
+            }
+            (Division, Value::Matrix(lhs), Value::Matrix(rhs)) => {
+                if lhs.columns != rhs.rows {
+                    return mismatch(&lhs, &rhs);
+                }
+                lhs.product(&rhs.inverse()?)
+            }
+            (Division, Value::Number(lhs), Value::Matrix(rhs)) => rhs.inverse()?.map(|b| lhs * b),
+            (Index, Value::Matrix(lhs), Value::Number(rhs)) => lhs.power(rhs)?,
+            (Index, lhs, rhs) => {
+                return Err(format!(
+                    "Cannot raise {} to the power of {}: only a square matrix to a whole power",
+                    lhs, rhs
+                ));
+            }
+            (_, Value::Matrix(lhs), Value::Number(rhs)) => lhs.map(|a| number(operation, a, rhs)),
+            (_, Value::Number(lhs), Value::Matrix(rhs)) => rhs.map(|b| number(operation, lhs, b)),
+            (_, lhs, rhs) => {
+                return Err(format!(
+                    "Cannot {} {} and {}: matrices hold plain numbers",
+                    operation.verb(),
+                    lhs,
+                    rhs
+                ));
+            }
+        };
+        Ok(Value::from_matrix(result))
+    }
+}
+
+fn number(operation: BinaryOperator, lhs: f64, rhs: f64) -> f64 {
+    match operation {
+        BinaryOperator::Addition => lhs + rhs,
+        BinaryOperator::Subtraction => lhs - rhs,
+        BinaryOperator::Multiplication => lhs * rhs,
+        BinaryOperator::Division => lhs / rhs,
+        _ => unreachable!(),
+    }
+}
+
+/// The entries as exact fractions, if every one of them fits.
+fn exact(rows: &[Vec<f64>]) -> Option<Vec<Vec<Ratio>>> {
+    rows.iter()
+        .map(|row| row.iter().map(|entry| Ratio::from_f64(*entry)).collect())
+        .collect()
+}
+
+/// The columns of the inverse, `None` inside if there is no inverse and outside if a number
+/// along the way cannot be represented.
+fn inverse<S: Scalar>(rows: &[Vec<S>]) -> Option<Option<Vec<Vec<S>>>> {
+    let size = rows.len();
+    let mut columns = Vec::with_capacity(size);
+    for column in 0..size {
+        let augmented = rows
+            .iter()
+            .enumerate()
+            .map(|(row, entries)| {
+                let mut entries = entries.clone();
+                entries.push(if row == column { S::one() } else { S::zero() });
+                entries
+            })
+            .collect();
+        match linear::eliminate(augmented, size)? {
+            Elimination::Unique(solution) => columns.push(solution),
+            _ => return Some(None),
+        }
+    }
+    Some(Some(columns))
+}
+
+/// Calls one of the [`FUNCTIONS`] on its evaluated arguments.
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value, String> {
+    let matrices = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::Matrix(matrix) => Ok(matrix),
+            other => Err(format!("{} expects a matrix, found {}", name, other)),
+        })
+        .collect::<Result<Vec<Matrix>, String>>()?;
+    match (name, &matrices[..]) {
+        ("det", [matrix]) => matrix.determinant().map(Value::Number),
+        ("inv", [matrix]) => matrix.inverse().map(Value::from_matrix),
+        ("transpose", [matrix]) => Ok(Value::Matrix(matrix.transpose())),
+        ("dot", [a, b]) => {
+            vectors(name, a, b)?;
+            Ok(Value::Number(
+                a.entries.iter().zip(&b.entries).map(|(a, b)| a * b).sum(),
+            ))
+        }
+        ("cross", [a, b]) => {
+            vectors(name, a, b)?;
+            if a.entries.len() != 3 {
+                return Err(format!(
+                    "cross expects vectors of 3 numbers, found {} and {}",
+                    a, b
+                ));
+            }
+            let (a, b, shape) = (&a.entries, &b.entries, a);
+            Ok(Value::Matrix(Matrix {
+                entries: vec![
+                    a[1] * b[2] - a[2] * b[1],
+                    a[2] * b[0] - a[0] * b[2],
+                    a[0] * b[1] - a[1] * b[0],
+                ],
+                ..*shape
+            }))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Checks that `a` and `b` are vectors of the same length, in either orientation.
+fn vectors(name: &str, a: &Matrix, b: &Matrix) -> Result<(), String> {
+    if !a.is_vector() || !b.is_vector() || a.entries.len() != b.entries.len() {
+        return Err(format!(
+            "{} expects two vectors of the same length, found {} which is {} and {} which is {}",
+            name,
+            a,
+            a.shape(),
+            b,
+            b.shape()
+        ));
+    }
+    Ok(())
+}
+
+/// Rows separated by `;` and entries by `,`, as they are written.
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows: Vec<String> = (0..self.rows)
+            .map(|row| {
+                let entries: Vec<String> = self.row(row).iter().map(f64::to_string).collect();
+                entries.join(", ")
+            })
+            .collect();
+        write!(f, "[{}]", rows.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{environment::Environment, evaluate},
+        utils::{error::CalcError, span::Span},
+    };
+    use pretty_assertions::assert_eq;
+
+    fn calculated(input: &str) -> String {
+        match evaluate(input, &Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_product() {
+        assert_eq!(calculated("[1, 2; 3, 4] * [5; 6]"), "[17; 39]");
+        assert_eq!(calculated("[1, 2, 3] * [4; 5; 6]"), "32");
+        assert_eq!(calculated("2[1, 2] * 3"), "[6, 12]");
+    }
+
+    #[test]
+    fn test_entry_by_entry() {
+        assert_eq!(calculated("[1, 2; 3, 4] - [1, 1; 1, 1]"), "[0, 1; 2, 3]");
+        assert_eq!(calculated("[2, 4] / 2 + 1"), "[2, 3]");
+        assert_eq!(calculated("sqrt([4, 9])"), "[2, 3]");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(calculated("det([1, 2; 3, 4])"), "-2");
+        assert_eq!(calculated("inv([1, 2; 3, 4])"), "[-2, 1; 1.5, -0.5]");
+        assert_eq!(calculated("transpose([1, 2, 3])"), "[1; 2; 3]");
+        assert_eq!(calculated("dot([1, 2, 3], [4; 5; 6])"), "32");
+        assert_eq!(calculated("cross([1, 0, 0], [0, 1, 0])"), "[0, 0, 1]");
+    }
+
+    #[test]
+    fn test_exact_determinant() {
+        // in floats the elimination leaves 0.30000000000000004 behind
+        assert_eq!(calculated("det([0.1, 0.2; 0.3, 0.9])"), "0.03");
+        assert_eq!(calculated("det([1, 2; 2, 4])"), "0");
+    }
+
+    #[test]
+    fn test_powers() {
+        assert_eq!(calculated("[1, 1; 1, 0]^10"), "[89, 55; 55, 34]");
+        assert_eq!(calculated("[2, 0; 0, 4]^-1"), "[0.5, 0; 0, 0.25]");
+        assert_eq!(calculated("[1, 2; 3, 4] / [1, 2; 3, 4]"), "[1, 0; 0, 1]");
+    }
+
+    #[test]
+    fn test_shape_mismatch_points_to_operands() {
+        assert_eq!(
+            evaluate("[1, 2] + [1; 2]", &Environment::new()),
+            Err(CalcError::at(
+                "Shape mismatch: cannot add [1, 2] which is 1×2 and [1; 2] which is 2×1",
+                Span::new(0, 15)
+            ))
+        );
+        let mut env = Environment::new();
+        env.set("a", Value::Matrix(Matrix::from_rows(vec![vec![1.0, 2.0]])));
+        assert_eq!(
+            evaluate("1 + a * a", &env),
+            Err(CalcError::at(
+                "Shape mismatch: cannot multiply [1, 2] which is 1×2 and [1, 2] which is 1×2",
+                Span::new(4, 9)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            calculated("inv([1, 2; 2, 4])"),
+            "Error: [1, 2; 2, 4] is singular and has no inverse"
+        );
+        assert_eq!(
+            calculated("det([1, 2])"),
+            "Error: Only a square matrix has a determinant, found [1, 2] which is 1×2"
+        );
+        assert_eq!(
+            calculated("cross([1, 2], [3, 4])"),
+            "Error: cross expects vectors of 3 numbers, found [1, 2] and [3, 4]"
+        );
+        assert_eq!(
+            calculated("[1, 2] * 3 m"),
+            "Error: Cannot multiply [1, 2] and 3 m: matrices hold plain numbers"
+        );
+    }
+}
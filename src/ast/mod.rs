@@ -1,23 +1,84 @@
-use crate::utils;
+use crate::{
+    ast::{environment::Environment, value::Value},
+    utils::{
+        self,
+        error::CalcError,
+        limits::{Budget, EvalLimits, LimitError},
+    },
+};
 
+#[allow(clippy::module_inception)]
 pub mod ast;
+pub mod closure;
+pub mod derivative;
+pub mod display;
+pub mod environment;
+pub mod equation;
+pub mod format;
+pub mod functions;
+pub mod linear;
+pub mod matrix;
+pub mod numeric;
 pub mod parse;
+pub mod simplify;
+pub mod statistics;
+pub mod trace;
+pub mod value;
+pub mod visit;
 
-pub fn calculate(input: String) -> Result<f64, String> {
-    let infix_input = match utils::input::get_normalized_input(&input) {
-        Ok(input) => input,
-        Err(e) => return Err(e),
-    };
-    let ast = match parse::infix_to_ast(infix_input) {
-        Ok(ast) => ast,
-        Err(e) => return Err(e),
-    };
-    ast.calculate()
+pub fn calculate(input: String) -> Result<Value, CalcError> {
+    evaluate(&input, &Environment::new())
+}
+
+pub fn evaluate(input: &str, env: &Environment) -> Result<Value, CalcError> {
+    parse_expression(input)?.calculate(env)
+}
+
+/// Like [`evaluate`], within `limits`. An error from going over one of them has its
+/// [`CalcError::limit`] set.
+pub fn evaluate_within(
+    input: &str,
+    env: &Environment,
+    limits: &EvalLimits,
+) -> Result<Value, CalcError> {
+    let infix_input = utils::input::tokenize_within(input, limits)?;
+    let expr = parse::infix_to_ast(infix_input)?;
+    // brackets were checked as the input was read, operations that need none are checked here
+    if let Some(limit) = limits.max_depth
+        && expr.depth() > limit
+    {
+        return Err(CalcError {
+            span: expr.span(),
+            ..LimitError::TooDeep { limit }.into()
+        });
+    }
+    let mut env = env.clone();
+    env.set_limits(limits.clone());
+    expr.calculate(&env)
+        .map_err(|error| match env.budget().and_then(Budget::exceeded) {
+            // the limit still counts when the error it caused was passed on as a message
+            Some(limit) if error.limit.is_none() => CalcError {
+                limit: Some(limit.clone()),
+                ..error
+            },
+            _ => error,
+        })
+}
+
+pub fn parse_expression(input: &str) -> Result<ast::Node, CalcError> {
+    let infix_input = utils::input::tokenize(input)?;
+    parse::infix_to_ast(infix_input)
+}
+
+pub fn parse_spanned_expression(input: &str) -> Result<(ast::Node, ast::SpanTree), CalcError> {
+    let infix_input = utils::input::tokenize(input)?;
+    parse::infix_to_spanned_ast(infix_input)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::units::currency::CurrencyRates;
     use pretty_assertions::assert_eq;
     use proptest::prelude::*;
 
@@ -26,13 +87,339 @@ mod tests {
         let input = String::from("3 + 4 * 2 ÷ ( 1 - 5 ) ^ 2 ^ 3");
         let expected =
             3.0_f64 + 4.0_f64 * 2.0_f64 / (1.0_f64 - 5.0_f64).powf(2.0_f64.powf(3.0_f64));
-        assert_eq!(calculate(input), Ok(expected));
+        assert_eq!(calculate(input), Ok(Value::Number(expected)));
     }
     #[test]
     fn test_another_example() {
         let input = String::from("4+5-2*5");
         let expected = 4.0_f64 + 5.0_f64 - 2.0_f64 * 5.0_f64;
-        assert_eq!(calculate(input), Ok(expected));
+        assert_eq!(calculate(input), Ok(Value::Number(expected)));
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        let input = String::from("3 m * 2 s^-1 to km/h");
+        assert_eq!(
+            calculate(input).map(|value| value.to_string()),
+            Ok("21.6 km/h".to_string())
+        );
+    }
+
+    #[test]
+    fn test_signed_exponent() {
+        let mut env = Environment::new();
+        env.set("x", Value::Number(2.0));
+        for (input, expected) in [
+            ("2^-x", 0.25),
+            ("2^-(1)", 0.5),
+            ("2^+x", 4.0),
+            ("2^-x^2", 1.0 / 16.0),
+            ("2^-x*3", 0.75),
+            ("2^-1.5^2", 2.0_f64.powf(-2.25)),
+        ] {
+            assert_eq!(
+                evaluate(input, &env),
+                Ok(Value::Number(expected)),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            evaluate("1 s^-1 to Hz", &env).map(|value| value.to_string()),
+            Ok("1 Hz".to_string())
+        );
+    }
+
+    #[test]
+    fn test_negative_quantities() {
+        for (input, expected) in [
+            ("-2 m", "-2 m"),
+            ("3 m + -2 m", "1 m"),
+            ("5 m - -2 m", "7 m"),
+            ("-3 m to km", "-0.003 km"),
+            ("2 * -3 m", "-6 m"),
+            ("-1 month", "-1 month"),
+            ("2026-10-18 + -1 month", "2026-09-18"),
+            ("-3 h 20 min to min", "-200 min"),
+            ("-0", "0"),
+        ] {
+            assert_eq!(
+                calculate(input.to_string()).map(|value| value.to_string()),
+                Ok(expected.to_string()),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            calculate("-2026-10-18".to_string()).map_err(|error| error.message),
+            Err("Cannot negate 2026-10-18".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let input = String::from("5 kg + 3 m");
+        assert_eq!(
+            calculate(input),
+            Err(CalcError::at(
+                "Dimension mismatch: cannot add 5 kg and 3 m",
+                utils::span::Span::new(2, 10)
+            ))
+        );
+        // only the operation that fails is pointed at
+        assert_eq!(
+            calculate("2 * (1 s - 1 kg) + 4".to_string()).map_err(|error| error.span),
+            Err(Some(utils::span::Span::new(7, 15)))
+        );
+    }
+
+    #[test]
+    fn test_currency_conversion() {
+        let path = utils::test::temp_file(
+            "csv",
+            "currency,rate,date\nUSD,1,2026-10-15\nEUR,0.8,2026-10-15\n",
+        );
+        let rates = CurrencyRates::load(&path, None);
+        std::fs::remove_file(path).unwrap();
+        let mut env = Environment::new();
+        env.set_rates(rates.unwrap());
+        let result = evaluate("120 USD to EUR", &env).unwrap();
+        assert_eq!(
+            format::describe(&result, &env, &format::OutputFormat::default()),
+            "96 EUR (rates as of 2026-10-15)"
+        );
+        // a result in no currency in particular reads back in the base currency
+        let result = evaluate("10 USD / 2 h", &env).unwrap();
+        assert_eq!(result.to_string(), "0.00138888888889 ¤/s");
+        assert_eq!(
+            evaluate(&format!("{} to EUR/s", result), &env).map(|value| value.to_string()),
+            Ok("0.00111111111111 EUR/s".to_string())
+        );
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        let input = String::from("2026-10-18 + 90 days");
+        assert_eq!(
+            calculate(input).map(|value| value.to_string()),
+            Ok("2027-01-16".to_string())
+        );
+    }
+
+    #[test]
+    fn test_days_between_dates() {
+        let input = String::from("(2026-12-25 - 2026-10-18) in days");
+        assert_eq!(
+            calculate(input).map(|value| value.to_string()),
+            Ok("68 days".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compound_duration() {
+        let result = calculate(String::from("3h 20min * 4")).unwrap();
+        let format = format::OutputFormat {
+            durations: format::DurationFormat::Compound,
+            ..format::OutputFormat::default()
+        };
+        assert_eq!(
+            format::describe(&result, &Environment::new(), &format),
+            "13h 20min"
+        );
+    }
+
+    #[test]
+    fn test_cannot_mix_utc_and_naive_times() {
+        let input = String::from("2026-10-18T12:00Z - 2026-10-18");
+        assert_eq!(
+            calculate(input),
+            Err(CalcError::new(
+                "Cannot mix timezone-less and UTC times: 2026-10-18T12:00:00Z and 2026-10-18"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_parentheses() {
+        let depth = 100_000;
+        let input = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(calculate(input), Ok(Value::Number(1.0)));
+
+        // each bracket holds a sum, so the tree is as deep as the brackets
+        let input = format!("{}1{}", "1+(".repeat(depth), ")".repeat(depth));
+        assert_eq!(calculate(input), Ok(Value::Number(100_001.0)));
+
+        let input = format!("{}0{}", "sin(".repeat(depth), ")".repeat(depth));
+        assert_eq!(calculate(input), Ok(Value::Number(0.0)));
+    }
+
+    #[test]
+    fn test_limits() {
+        use crate::utils::span::Span;
+        use std::time::Duration;
+
+        let limit = |input: &str, limits: EvalLimits| {
+            evaluate_within(input, &Environment::new(), &limits).map_err(|error| error.limit)
+        };
+        let tokens = EvalLimits {
+            max_tokens: Some(5),
+            ..EvalLimits::default()
+        };
+        assert_eq!(limit("1 + 2 + 3", tokens.clone()), Ok(Value::Number(6.0)));
+        assert_eq!(
+            limit("1 + 2 + 3 + 4", tokens),
+            Err(Some(LimitError::TooManyTokens { limit: 5 }))
+        );
+        assert_eq!(
+            limit(
+                "((1))",
+                EvalLimits {
+                    max_depth: Some(1),
+                    ..EvalLimits::default()
+                }
+            ),
+            Err(Some(LimitError::TooDeep { limit: 1 }))
+        );
+        // operations nest without brackets too
+        let depth = EvalLimits {
+            max_depth: Some(2),
+            ..EvalLimits::default()
+        };
+        assert_eq!(limit("(1 + 2) * 3", depth.clone()), Ok(Value::Number(9.0)));
+        assert_eq!(
+            evaluate_within("x - 1 + 2 + 3", &Environment::new(), &depth),
+            Err(CalcError {
+                message: "The expression nests deeper than the limit of 2".to_string(),
+                span: Some(Span::new(0, 1)),
+                limit: Some(LimitError::TooDeep { limit: 2 }),
+            })
+        );
+
+        let operations = EvalLimits {
+            max_operations: Some(1000),
+            ..EvalLimits::default()
+        };
+        assert_eq!(
+            limit("sum(k^2 for k in 1..10)", operations.clone()),
+            Ok(Value::Number(385.0))
+        );
+        for input in [
+            "sum(k^2 for k in 1..1000)",
+            "nsum(1/k^2, k, 1, 1000000)",
+            "map(x -> integrate(t^x, t, 0, 1), 1..100)",
+        ] {
+            assert_eq!(
+                limit(input, operations.clone()),
+                Err(Some(LimitError::TooManyOperations { limit: 1000 })),
+                "{}",
+                input
+            );
+        }
+        // derivatives grow with each nested diff, and building them counts too
+        let nested = format!(
+            "{}x^x*sin(x)*cos(x){}",
+            "diff(".repeat(10),
+            ", x)".repeat(10)
+        );
+        let timeout = Duration::from_millis(100);
+        let result = limit(
+            &nested,
+            EvalLimits {
+                max_tokens: Some(200),
+                max_depth: Some(50),
+                max_operations: Some(10_000),
+                timeout: Some(timeout),
+                ..EvalLimits::default()
+            },
+        );
+        assert!(
+            result == Err(Some(LimitError::TooManyOperations { limit: 10_000 }))
+                || result == Err(Some(LimitError::TimedOut { limit: timeout })),
+            "{:?}",
+            result
+        );
+
+        let exponent = EvalLimits {
+            max_exponent: Some(1000.0),
+            ..EvalLimits::default()
+        };
+        assert_eq!(
+            limit("[1, 1; 1, 0]^10", exponent.clone()),
+            calculate("[1, 1; 1, 0]^10".to_string()).map_err(|_| None)
+        );
+        assert_eq!(
+            evaluate_within(
+                "1 + [1, 1; 1, 0]^1000000000",
+                &Environment::new(),
+                &exponent
+            ),
+            Err(CalcError {
+                message: "The exponent 1000000000 is larger than the limit of 1000".to_string(),
+                span: Some(Span::new(4, 16)),
+                limit: Some(LimitError::ExponentTooLarge {
+                    limit: 1000.0,
+                    found: 1e9
+                }),
+            })
+        );
+
+        assert_eq!(
+            limit(
+                "1 + 1",
+                EvalLimits {
+                    timeout: Some(Duration::ZERO),
+                    ..EvalLimits::default()
+                }
+            ),
+            Err(Some(LimitError::TimedOut {
+                limit: Duration::ZERO
+            }))
+        );
+        // a short expression can still do a lot of work with a long list
+        let mut env = Environment::new();
+        env.set("xs", calculate("1..1000000".to_string()).unwrap());
+        for input in ["sum(1..1000000)", "median(xs)"] {
+            assert_eq!(
+                evaluate_within(input, &env, &operations).map_err(|error| error.limit),
+                Err(Some(LimitError::TooManyOperations { limit: 1000 })),
+                "{}",
+                input
+            );
+        }
+        let timeout = Duration::from_millis(1);
+        assert_eq!(
+            limit(
+                "median(1..1000000) + stdev(1..1000000) + mean(1..1000000) + sum(1..1000000)",
+                EvalLimits {
+                    timeout: Some(timeout),
+                    ..EvalLimits::default()
+                }
+            ),
+            Err(Some(LimitError::TimedOut { limit: timeout }))
+        );
+        assert_eq!(
+            evaluate_within("2^10 km", &Environment::new(), &EvalLimits::default()),
+            evaluate("2^10 km", &Environment::new())
+        );
+    }
+
+    #[test]
+    fn test_million_terms() {
+        let input = vec!["1"; 1_000_000].join("+");
+        let (expr, spans) = parse_spanned_expression(&input).unwrap();
+        assert_eq!(expr.calculate(&Environment::new()), Ok(Value::Number(1e6)));
+        drop((expr, spans));
+
+        let input = format!("{} + {{1, 2}}", vec!["x"; 100_000].join("+"));
+        let mut env = Environment::new();
+        env.set("x", Value::Number(1.0));
+        assert_eq!(
+            evaluate(&input, &env),
+            Err(CalcError::at(
+                "Cannot add 100000 and {1, 2}: a list can only be given to a function",
+                utils::span::Span::new(0, input.chars().count())
+            ))
+        );
     }
 
     proptest! {
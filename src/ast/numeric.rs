@@ -0,0 +1,388 @@
+use crate::{
+    ast::{ast::Node, derivative::differentiate, environment::Environment, value::Value},
+    utils::error::CalcError,
+};
+
+/// How close an answer has to be: relative to its size, and absolute near zero.
+pub const TOLERANCE: f64 = 1e-10;
+/// How many Newton steps `solve` takes before falling back to bisection.
+pub const MAX_ITERATIONS: usize = 100;
+/// How many times `integrate` may halve an interval before giving up.
+pub const MAX_DEPTH: u32 = 50;
+/// How many times an expression may be evaluated for one call.
+pub const MAX_EVALUATIONS: usize = 1_000_000;
+
+/// An expression as a function of one of its variables.
+struct Function<'a> {
+    form: &'static str,
+    expr: &'a Node,
+    by: &'a str,
+    scope: Environment,
+    evaluations: usize,
+}
+
+impl<'a> Function<'a> {
+    fn new(form: &'static str, expr: &'a Node, by: &'a str, env: &Environment) -> Function<'a> {
+        Function {
+            form,
+            expr,
+            by,
+            scope: env.clone(),
+            evaluations: 0,
+        }
+    }
+
+    fn at(&mut self, x: f64) -> Result<f64, CalcError> {
+        self.evaluations += 1;
+        if self.evaluations > MAX_EVALUATIONS {
+            return Err(CalcError::new(format!(
+                "{} gave up after {} evaluations",
+                self.form, MAX_EVALUATIONS
+            )));
+        }
+        self.scope.set(self.by, Value::Number(x));
+        match self.expr.calculate(&self.scope)? {
+            Value::Number(value) => Ok(value),
+            other => Err(CalcError::new(format!(
+                "{} needs {} to be a number, found {}",
+                self.form, self.expr, other
+            ))),
+        }
+    }
+}
+
+/// The integral of `expr` over `by` from `a` to `b`, by adaptive Simpson's rule.
+pub fn integrate(
+    expr: &Node,
+    by: &str,
+    a: f64,
+    b: f64,
+    env: &Environment,
+) -> Result<f64, CalcError> {
+    if a == b {
+        return Ok(0.0);
+    }
+    if b < a {
+        return integrate(expr, by, b, a, env).map(|integral| -integral);
+    }
+    let mut f = Function::new("integrate", expr, by, env);
+    let fa = finite(&mut f, a)?;
+    let fb = finite(&mut f, b)?;
+    let (m, fm, whole) = simpson(&mut f, a, fa, b, fb)?;
+    let tolerance = TOLERANCE * whole.abs().max(1.0);
+    adaptive_simpson(&mut f, [a, m, b], [fa, fm, fb], whole, tolerance, MAX_DEPTH)
+}
+
+fn finite(f: &mut Function, x: f64) -> Result<f64, CalcError> {
+    let value = f.at(x)?;
+    if !value.is_finite() {
+        return Err(CalcError::new(format!(
+            "integrate cannot handle {} = {} at {} = {}",
+            f.expr, value, f.by, x
+        )));
+    }
+    Ok(value)
+}
+
+/// Simpson's rule over `[a, b]`, returning the midpoint and its value along with the estimate.
+fn simpson(
+    f: &mut Function,
+    a: f64,
+    fa: f64,
+    b: f64,
+    fb: f64,
+) -> Result<(f64, f64, f64), CalcError> {
+    let m = (a + b) / 2.0;
+    let fm = finite(f, m)?;
+    Ok((m, fm, (b - a) / 6.0 * (fa + 4.0 * fm + fb)))
+}
+
+fn adaptive_simpson(
+    f: &mut Function,
+    [a, m, b]: [f64; 3],
+    [fa, fm, fb]: [f64; 3],
+    whole: f64,
+    tolerance: f64,
+    depth: u32,
+) -> Result<f64, CalcError> {
+    let (left_m, left_fm, left) = simpson(f, a, fa, m, fm)?;
+    let (right_m, right_fm, right) = simpson(f, m, fm, b, fb)?;
+    let delta = left + right - whole;
+    // a few forced splits stop a periodic integrand from fooling the first estimates
+    if MAX_DEPTH - depth >= 4 && delta.abs() <= 15.0 * tolerance {
+        return Ok(left + right + delta / 15.0);
+    }
+    if depth == 0 {
+        return Err(CalcError::new(format!(
+            "integrate did not converge between {} = {} and {}",
+            f.by, a, b
+        )));
+    }
+    Ok(adaptive_simpson(
+        f,
+        [a, left_m, m],
+        [fa, left_fm, fm],
+        left,
+        tolerance / 2.0,
+        depth - 1,
+    )? + adaptive_simpson(
+        f,
+        [m, right_m, b],
+        [fm, right_fm, fb],
+        right,
+        tolerance / 2.0,
+        depth - 1,
+    )?)
+}
+
+/// A value of `by` near `guess` that makes `expr` zero, by Newton's method, falling back to
+/// bisection when Newton's method stalls or wanders off.
+///
+/// Newton's method has converged only once its step is tiny and `expr` is also close to zero,
+/// next to how large it was at `guess`: at the edge of the domain of `sqrt(x)` the slope is
+/// infinite, so the step is zero wherever the root is.
+pub fn solve(expr: &Node, by: &str, guess: f64, env: &Environment) -> Result<f64, CalcError> {
+    let mut f = Function::new("solve", expr, by, env);
+    // a symbolic derivative where there is one, otherwise a central difference
+    let derivative = differentiate(expr, by, env.budget()).ok();
+    let mut df = derivative
+        .as_ref()
+        .map(|derivative| Function::new("solve", derivative, by, env));
+
+    let start = f.at(guess)?;
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f.at(x)?;
+        if fx == 0.0 {
+            return Ok(x);
+        }
+        let slope = match &mut df {
+            Some(df) => df.at(x)?,
+            None => {
+                let h = 1e-7 * (1.0 + x.abs());
+                (f.at(x + h)? - f.at(x - h)?) / (2.0 * h)
+            }
+        };
+        let next = x - fx / slope;
+        if !next.is_finite() {
+            break;
+        }
+        if (next - x).abs() <= TOLERANCE * (1.0 + next.abs()) {
+            if f.at(next)?.abs() <= TOLERANCE * (1.0 + start.abs()) {
+                return Ok(next);
+            }
+            break;
+        }
+        x = next;
+    }
+
+    let (a, b) = bracket(&mut f, guess)?;
+    bisect(&mut f, a, b)
+}
+
+/// Searches further and further either side of `guess` for an interval where `f` changes sign.
+fn bracket(f: &mut Function, guess: f64) -> Result<(f64, f64), CalcError> {
+    let start = (guess, f.at(guess)?);
+    let (mut left, mut right) = (start, start);
+    for i in 0..64 {
+        let step = 1e-3 * (1.0 + guess.abs()) * 2f64.powi(i);
+        for (side, x) in [(&mut left, guess - step), (&mut right, guess + step)] {
+            let fx = f.at(x)?;
+            if side.1.is_finite() && fx.is_finite() && side.1.signum() != fx.signum() {
+                return Ok((side.0.min(x), side.0.max(x)));
+            }
+            if fx.is_finite() {
+                *side = (x, fx);
+            }
+        }
+    }
+    Err(CalcError::new(format!(
+        "solve could not find where {} = 0 near {} = {}",
+        f.expr, f.by, guess
+    )))
+}
+
+fn bisect(f: &mut Function, mut a: f64, mut b: f64) -> Result<f64, CalcError> {
+    let (fa, fb) = (f.at(a)?, f.at(b)?);
+    let mut fa_sign = fa.signum();
+    for _ in 0..200 {
+        let m = (a + b) / 2.0;
+        let fm = f.at(m)?;
+        if fm == 0.0 || (b - a) / 2.0 <= TOLERANCE * (1.0 + m.abs()) {
+            // a sign change can also be a pole, where the value grows instead of vanishing
+            if fm.abs() > fa.abs().max(fb.abs()) {
+                return Err(CalcError::new(format!(
+                    "solve found {} changing sign at {} = {} but not through zero",
+                    f.expr, f.by, m
+                )));
+            }
+            return Ok(m);
+        }
+        if fm.signum() == fa_sign {
+            a = m;
+            fa_sign = fm.signum();
+        } else {
+            b = m;
+        }
+    }
+    Err(CalcError::new(format!(
+        "solve did not converge between {} = {} and {}",
+        f.by, a, b
+    )))
+}
+
+/// Adds up `expr` for every whole number `by` from `from` to `to` inclusive.
+pub fn nsum(
+    expr: &Node,
+    by: &str,
+    from: f64,
+    to: f64,
+    env: &Environment,
+) -> Result<f64, CalcError> {
+    for bound in [from, to] {
+        if bound.fract() != 0.0 {
+            return Err(CalcError::new(format!(
+                "nsum expects whole-number bounds, found {}",
+                bound
+            )));
+        }
+    }
+    let count = (to - from + 1.0).max(0.0);
+    if count > MAX_EVALUATIONS as f64 {
+        return Err(CalcError::new(format!(
+            "nsum over {} terms is more than the limit of {}",
+            count, MAX_EVALUATIONS
+        )));
+    }
+    let mut f = Function::new("nsum", expr, by, env);
+    let mut sum = KahanSum::default();
+    for k in 0..count as u64 {
+        sum.add(f.at(from + k as f64)?);
+    }
+    Ok(sum.total())
+}
+
+/// A running total that keeps track of the rounding error of each addition.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KahanSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl KahanSum {
+    pub fn add(&mut self, value: f64) {
+        let y = value - self.compensation;
+        let t = self.sum + y;
+        self.compensation = (t - self.sum) - y;
+        self.sum = t;
+    }
+
+    pub fn total(&self) -> f64 {
+        self.sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_expression;
+    use pretty_assertions::assert_eq;
+
+    fn expr(input: &str) -> Node {
+        parse_expression(input).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= 1e-8 * expected.abs().max(1.0),
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_integrate() {
+        let env = Environment::new();
+        assert_close(integrate(&expr("x^2"), "x", 0.0, 3.0, &env).unwrap(), 9.0);
+        assert_close(
+            integrate(&expr("exp(t)"), "t", 1.0, 0.0, &env).unwrap(),
+            1.0 - 1f64.exp(),
+        );
+        assert_close(
+            integrate(&expr("sin(x)"), "x", 0.0, 2.0 * std::f64::consts::PI, &env).unwrap(),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn test_integrate_singularity() {
+        assert_eq!(
+            integrate(&expr("1/x"), "x", 0.0, 1.0, &Environment::new()),
+            Err(CalcError::new("integrate cannot handle 1/x = inf at x = 0"))
+        );
+    }
+
+    #[test]
+    fn test_solve_newton() {
+        let env = Environment::new();
+        assert_close(
+            solve(&expr("x^2 - 2"), "x", 1.0, &env).unwrap(),
+            2f64.sqrt(),
+        );
+        assert_close(
+            solve(&expr("cos(x) - x"), "x", 0.0, &env).unwrap(),
+            0.7390851332151607,
+        );
+    }
+
+    #[test]
+    fn test_solve_falls_back_to_bisection() {
+        // Newton's method cycles between 0 and 1 on this cubic
+        let root = solve(&expr("x^3 - 2*x + 2"), "x", 0.0, &Environment::new()).unwrap();
+        assert_close(root, -1.7692923542386314);
+        // the slope is infinite at 0, so Newton's method does not move from there
+        let env = Environment::new();
+        assert_close(solve(&expr("sqrt(x) - 3"), "x", 0.0, &env).unwrap(), 9.0);
+        assert_close(solve(&expr("x^(1/3) - 2"), "x", 0.0, &env).unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_solve_failures() {
+        let env = Environment::new();
+        assert_eq!(
+            solve(&expr("x^2 + 1"), "x", 0.0, &env),
+            Err(CalcError::new(
+                "solve could not find where x^2 + 1 = 0 near x = 0"
+            ))
+        );
+        assert!(
+            solve(&expr("1/x"), "x", 0.5, &env)
+                .unwrap_err()
+                .message
+                .contains("not through zero")
+        );
+    }
+
+    #[test]
+    fn test_nsum() {
+        let env = Environment::new();
+        assert_eq!(nsum(&expr("k^2"), "k", 1.0, 100.0, &env), Ok(338350.0));
+        assert_eq!(nsum(&expr("k"), "k", 5.0, 4.0, &env), Ok(0.0));
+        assert_eq!(
+            nsum(&expr("k"), "k", 1.0, 1e7, &env),
+            Err(CalcError::new(
+                "nsum over 10000000 terms is more than the limit of 1000000"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_kahan_sum() {
+        let mut sum = KahanSum::default();
+        for _ in 0..10 {
+            sum.add(0.1);
+        }
+        assert_eq!(sum.total(), 1.0);
+    }
+}
@@ -1,165 +1,550 @@
 use crate::{
-    ast::ast::Node,
-    utils::operators::{Associativity, get_operator_info},
+    ast::{
+        ast::{Node, SpanTree, Statement},
+        functions,
+    },
+    datetime::DateTime,
+    units::{dimension::Dimension, registry},
+    utils::{
+        error::CalcError,
+        input::Token,
+        operators::{
+            Associativity, BinaryOperator, IMPLICIT_ADDITION, IMPLICIT_MULTIPLICATION,
+            get_operator_info, is_prefix,
+        },
+        span::Span,
+    },
 };
 
-pub fn infix_to_ast(input: Vec<String>) -> Result<Node, String> {
-    let mut output: Vec<Node> = Vec::new();
-    let mut operators: Vec<String> = Vec::new();
+pub fn parse_statement(input: Vec<Token>) -> Result<Statement, CalcError> {
+    if input.len() >= 2 && input[0].is_identifier() && input[1].text == "=" {
+        let mut input = input.into_iter();
+        let name = input.next().unwrap().text;
+        let equals = input.next().unwrap();
+        let rest: Vec<Token> = input.collect();
+        if rest.is_empty() {
+            return Err(CalcError::at(
+                format!("Missing expression after {} =", name),
+                equals.span,
+            ));
+        }
+        return Ok(Statement::Assignment {
+            name,
+            expr: infix_to_ast(rest)?,
+        });
+    }
+    Ok(Statement::Expression(infix_to_ast(input)?))
+}
+
+pub fn infix_to_ast(input: Vec<Token>) -> Result<Node, CalcError> {
+    infix_to_spanned_ast(input).map(|(node, _)| node)
+}
+
+/// Parses like [`infix_to_ast`], also returning where each node came from in the input.
+pub fn infix_to_spanned_ast(input: Vec<Token>) -> Result<(Node, SpanTree), CalcError> {
+    let mut output: Vec<(Node, SpanTree)> = Vec::new();
+    let mut operators: Vec<Token> = Vec::new();
+    // whether the previous token finished a value, so that a value straight after it is multiplied in
+    let mut after_value = false;
+    // whether the previous tokens were a number and a unit of time or length, so that `3 h 20 min`
+    // adds up
+    let mut after_unit_literal = false;
+    let mut after_number = false;
+    let mut brackets: Vec<Bracket> = Vec::new();
 
-    for token in input.into_iter() {
-        if token.parse::<f64>().is_ok() {
-            output.push(Node::Operand {
-                value: token.parse().unwrap(),
-            });
+    let mut input = input.into_iter().peekable();
+    while let Some(token) = input.next() {
+        let is_operator = binding(&token.text).is_some();
+        let is_call = is_function_name(&token) && input.peek().is_some_and(|next| next.text == "(");
+        let date = DateTime::parse(&token.text);
+        let starts_value = !is_operator
+            && (token.is_number()
+                || token.is_identifier()
+                || date.is_some()
+                || token.text == "("
+                || token.text == "[");
+        if starts_value && after_value {
+            // two numbers side by side are more likely a typo than a product
+            let implicit = match token.is_number() {
+                true if after_unit_literal => Some(IMPLICIT_ADDITION),
+                true => None,
+                false => Some(IMPLICIT_MULTIPLICATION),
+            };
+            if let Some(implicit) = implicit {
+                let implicit = Token::new(implicit, Span::new(token.span.start, token.span.start));
+                push_operator(&mut output, &mut operators, implicit)?;
+            }
+        }
+        after_value = (starts_value && token.text != "(" && token.text != "[" && !is_call)
+            || matches!(token.text.as_str(), ")" | "}" | "]");
+        after_unit_literal = after_number && !is_operator && !is_call && is_compound_unit(&token);
+        after_number = token.is_number();
+
+        if is_call {
+            operators.push(token);
             continue;
         }
 
-        match token.as_str() {
-            "+" | "-" | "/" | "^" | "*" => {
-                loop {
-                    let o2 = operators.last();
-                    if let Some(o2) = o2 {
-                        if o2 == "(" {
-                            break;
-                        }
+        if token.is_number() {
+            output.push((
+                Node::Operand {
+                    value: token.text.parse().unwrap(),
+                },
+                SpanTree::leaf(token.span),
+            ));
+            continue;
+        }
 
-                        let o1_config = get_operator_info(&token).unwrap();
-                        let o2_config = get_operator_info(o2).unwrap();
-                        if o2_config.precedence > o1_config.precedence
-                            || (o2_config.precedence == o1_config.precedence
-                                && o1_config.associativity == Associativity::Left)
-                        {
-                            let operation =
-                                match get_operator_info(operators.pop().unwrap().as_str()) {
-                                    Some(info) => info.operation,
-                                    None => {
-                                        return Err(format!(
-                                            "{} is not a valid operator",
-                                            operators.pop().unwrap()
-                                        ))?;
-                                    }
-                                };
-                            let right = match output.pop() {
-                                Some(right) => right,
-                                None => return Err("Not enough values on stack".to_string()),
-                            };
-                            let left = match output.pop() {
-                                Some(left) => left,
-                                None => return Err("Not enough values on stack".to_string()),
-                            };
-                            output.push(Node::BinaryExpr {
-                                operation,
-                                lhs: Box::new(left),
-                                rhs: Box::new(right),
-                            });
-                        } else {
-                            break;
+        if let Some(value) = date {
+            output.push((Node::Date { value }, SpanTree::leaf(token.span)));
+            continue;
+        }
+
+        if token.is_identifier() && !is_operator {
+            output.push((
+                Node::Variable {
+                    name: token.text,
+                    span: token.span,
+                },
+                SpanTree::leaf(token.span),
+            ));
+            continue;
+        }
+
+        match token.text.as_str() {
+            _ if is_operator => push_operator(&mut output, &mut operators, token)?,
+
+            "(" | "{" | "[" => {
+                brackets.push(Bracket {
+                    start: output.len(),
+                    commas: 0,
+                    rows: Vec::new(),
+                    comma: None,
+                });
+                operators.push(token);
+            }
+
+            "," => {
+                while let Some(o) = operators.last()
+                    && !is_open(o)
+                {
+                    apply_operator(&mut output, operators.pop().unwrap())?;
+                }
+                let in_call =
+                    operators.len() >= 2 && is_call_token(&operators[operators.len() - 2]);
+                let in_list = operators.last().is_some_and(|o| o.text != "(");
+                match brackets.last_mut() {
+                    Some(bracket) => {
+                        // only the parameters of a function can be listed in plain brackets,
+                        // which is not known until the `->` after them
+                        if !in_call && !in_list && bracket.comma.is_none() {
+                            bracket.comma = Some(token.span);
                         }
-                    } else {
-                        break;
+                        bracket.commas += 1;
                     }
+                    None => return Err(CalcError::at(stray_comma(), token.span)),
                 }
-
-                operators.push(token.clone())
             }
 
-            "(" => operators.push(token.clone()),
+            ";" => {
+                while let Some(o) = operators.last()
+                    && !is_open(o)
+                {
+                    apply_operator(&mut output, operators.pop().unwrap())?;
+                }
+                match (operators.last(), brackets.last_mut()) {
+                    (Some(o), Some(bracket)) if o.text == "[" => {
+                        bracket.rows.push((output.len(), bracket.commas));
+                    }
+                    _ => return Err(CalcError::at("Found ; outside a matrix", token.span)),
+                }
+            }
 
-            ")" => {
+            ")" | "}" | "]" => {
+                let open = match token.text.as_str() {
+                    ")" => "(",
+                    "}" => "{",
+                    _ => "[",
+                };
                 loop {
-                    let o = operators.last();
-                    match o {
-                        Some(o) => {
-                            if o == "(" {
-                                break;
-                            }
-
-                            let operation =
-                                match get_operator_info(operators.pop().unwrap().as_str()) {
-                                    Some(info) => info.operation,
-                                    None => {
-                                        return Err(format!(
-                                            "{} is not a valid operator",
-                                            operators.pop().unwrap()
-                                        ));
-                                    }
-                                };
-                            let right = match output.pop() {
-                                Some(right) => right,
-                                None => return Err("Not enough values on stack".to_string()),
-                            };
-                            let left = match output.pop() {
-                                Some(left) => left,
-                                None => return Err("Not enough values on stack".to_string()),
-                            };
-                            output.push(Node::BinaryExpr {
-                                operation,
-                                lhs: Box::new(left),
-                                rhs: Box::new(right),
-                            });
+                    match operators.last() {
+                        Some(o) if o.text == open => break,
+                        Some(o) if !is_open(o) => {
+                            apply_operator(&mut output, operators.pop().unwrap())?;
                         }
-                        None => return Err("Mismatched parentheses found!".to_string()),
+                        // nothing left open, or a bracket of the other kind still is
+                        _ => return Err(CalcError::at(mismatched(open), token.span)),
                     }
                 }
 
                 let o = operators.pop().unwrap();
-                if o != "(" {
-                    return Err("Expected left parenthesis".to_string());
+                let bracket = brackets.pop().unwrap();
+                let span = Span::new(o.span.start, token.span.end);
+                if open == "{" {
+                    let list = finish_list(&mut output, bracket, span)?;
+                    output.push(list);
+                } else if open == "[" {
+                    let matrix = finish_matrix(&mut output, bracket, span)?;
+                    output.push(matrix);
+                } else if operators.last().is_some_and(is_call_token) {
+                    let function = operators.pop().unwrap();
+                    let call = finish_call(&mut output, function, bracket, token.span)?;
+                    output.push(call);
+                } else if let Some(comma) = bracket.comma {
+                    if input.peek().is_none_or(|next| next.text != LAMBDA) {
+                        return Err(CalcError::at(stray_comma(), comma));
+                    }
+                    let params = finish_list(&mut output, bracket, span)?;
+                    output.push(params);
+                } else if let Some((_, spans)) = output.last_mut() {
+                    // a bracketed expression covers its brackets
+                    spans.span = span;
                 }
             }
-            _ => return Err(format!("Found unsupported token: {}", token)),
+            _ => {
+                return Err(CalcError::at(
+                    format!("Found unsupported token: {}", token.text),
+                    token.span,
+                ));
+            }
         }
     }
 
     while let Some(o) = operators.pop() {
-        match o.as_str() {
-            "(" => return Err("Mismatched parentheses found!".to_string()),
-            _ => {
-                let operation = match get_operator_info(o.as_str()) {
-                    Some(info) => info.operation,
-                    None => {
-                        return Err(format!(
-                            "{} is not a valid operator",
-                            operators.pop().unwrap()
-                        ));
-                    }
-                };
-                let right = match output.pop() {
-                    Some(right) => right,
-                    None => return Err("Not enough values on stack".to_string()),
-                };
-                let left = match output.pop() {
-                    Some(left) => left,
-                    None => return Err("Not enough values on stack".to_string()),
-                };
-                output.push(Node::BinaryExpr {
-                    operation,
-                    lhs: Box::new(left),
-                    rhs: Box::new(right),
-                });
-            }
+        match o.text.as_str() {
+            "(" | "{" | "[" => return Err(CalcError::at(mismatched(&o.text), o.span)),
+            _ => apply_operator(&mut output, o)?,
         }
     }
     if output.len() != 1 {
-        return Err(format!(
+        return Err(CalcError::new(format!(
             "Invalid expression: {} values remain",
             output.len()
-        ));
+        )));
     }
     Ok(output.pop().unwrap())
 }
 
+/// An open bracket, with how many values were already output when it opened and how many commas
+/// it has seen, and for a matrix the same at the `;` that ended each row. For a plain bracket
+/// that is not a call, the first comma is kept in case the bracket turns out not to hold parameters.
+struct Bracket {
+    start: usize,
+    commas: usize,
+    rows: Vec<(usize, usize)>,
+    comma: Option<Span>,
+}
+
+/// Binds the names on its left in the expression on its right, as in `x -> x^2`.
+const LAMBDA: &str = "->";
+/// Makes `body for k in list` mean `map(k -> body, list)`.
+const FOR: &str = "for";
+
+/// The precedence and associativity of an operator. `->` and `for` bind loosest of all,
+/// so that the body of a function runs to the end of the argument it is written in.
+fn binding(text: &str) -> Option<(u8, Associativity)> {
+    match text {
+        LAMBDA => Some((0, Associativity::Right)),
+        FOR => Some((0, Associativity::Left)),
+        _ => get_operator_info(text).map(|info| (info.precedence, info.associativity)),
+    }
+}
+
+fn stray_comma() -> &'static str {
+    "Found , outside a function call, list or matrix"
+}
+
+fn is_open(token: &Token) -> bool {
+    matches!(token.text.as_str(), "(" | "{" | "[")
+}
+
+fn mismatched(open: &str) -> &'static str {
+    match open {
+        "(" => "Mismatched parentheses found!",
+        "{" => "Mismatched braces found!",
+        _ => "Mismatched square brackets found!",
+    }
+}
+
+/// Whether a quantity in `token` is usually written in parts, like `3 h 20 min` or `5 ft 3 inch`.
+fn is_compound_unit(token: &Token) -> bool {
+    token.is_identifier()
+        && registry::lookup(&token.text)
+            .is_some_and(|unit| matches!(unit.dimension, Dimension::TIME | Dimension::LENGTH))
+}
+
+fn is_function_name(token: &Token) -> bool {
+    token.is_identifier() && functions::is_function(&token.text)
+}
+
+/// Function names are the only identifiers that wait on the operator stack.
+fn is_call_token(token: &Token) -> bool {
+    token.is_identifier() && binding(&token.text).is_none()
+}
+
+/// Gathers the arguments output since a call's `(` into a function node.
+fn finish_call(
+    output: &mut Vec<(Node, SpanTree)>,
+    function: Token,
+    Bracket { start, commas, .. }: Bracket,
+    close: Span,
+) -> Result<(Node, SpanTree), CalcError> {
+    let span = Span::new(function.span.start, close.end);
+    let count = output.len() - start;
+    if count <= commas && !(count == 0 && commas == 0) {
+        return Err(CalcError::at(
+            format!("Missing argument in call to {}", function.text),
+            span,
+        ));
+    }
+    if count > commas + 1 {
+        return Err(CalcError::at(
+            format!("Missing , between arguments to {}", function.text),
+            span,
+        ));
+    }
+    functions::check_arity(&function.text, count)
+        .map_err(|message| CalcError::at(message, span))?;
+    let (args, children) = output.split_off(start).into_iter().unzip();
+    Ok((
+        Node::Function {
+            name: function.text,
+            args,
+            span,
+        },
+        SpanTree { span, children },
+    ))
+}
+
+/// Gathers the items output since a `{` into a list node.
+fn finish_list(
+    output: &mut Vec<(Node, SpanTree)>,
+    Bracket { start, commas, .. }: Bracket,
+    span: Span,
+) -> Result<(Node, SpanTree), CalcError> {
+    let count = output.len() - start;
+    if count <= commas && !(count == 0 && commas == 0) {
+        return Err(CalcError::at("Missing item in list", span));
+    }
+    if count > commas + 1 {
+        return Err(CalcError::at("Missing , between items in list", span));
+    }
+    let (items, children) = output.split_off(start).into_iter().unzip();
+    Ok((Node::List { items, span }, SpanTree { span, children }))
+}
+
+/// Gathers the entries output since a `[` into a matrix node, row by row.
+fn finish_matrix(
+    output: &mut Vec<(Node, SpanTree)>,
+    bracket: Bracket,
+    span: Span,
+) -> Result<(Node, SpanTree), CalcError> {
+    let mut lengths = Vec::new();
+    let (mut row_start, mut row_commas) = (bracket.start, 0);
+    for (end, commas) in bracket
+        .rows
+        .into_iter()
+        .chain([(output.len(), bracket.commas)])
+    {
+        let (count, commas) = (end - row_start, commas - row_commas);
+        if count <= commas {
+            return Err(CalcError::at("Missing entry in matrix", span));
+        }
+        if count > commas + 1 {
+            return Err(CalcError::at("Missing , between entries in matrix", span));
+        }
+        lengths.push(count);
+        (row_start, row_commas) = (end, row_commas + commas);
+    }
+    if let Some(length) = lengths.iter().find(|length| **length != lengths[0]) {
+        return Err(CalcError::at(
+            format!(
+                "Rows of a matrix must be the same length, found {} and {}",
+                lengths[0], length
+            ),
+            span,
+        ));
+    }
+
+    let (entries, children): (Vec<Node>, Vec<SpanTree>) =
+        output.split_off(bracket.start).into_iter().unzip();
+    let mut entries = entries.into_iter();
+    let rows = lengths
+        .iter()
+        .map(|length| entries.by_ref().take(*length).collect())
+        .collect();
+    Ok((Node::Matrix { rows, span }, SpanTree { span, children }))
+}
+
+fn push_operator(
+    output: &mut Vec<(Node, SpanTree)>,
+    operators: &mut Vec<Token>,
+    token: Token,
+) -> Result<(), CalcError> {
+    // a sign has nothing before it to take
+    while !is_prefix(&token.text) {
+        let o2 = operators.last();
+        if let Some(o2) = o2 {
+            if is_open(o2) {
+                break;
+            }
+
+            let (o1_precedence, o1_associativity) = binding(&token.text).unwrap();
+            let (o2_precedence, _) = binding(&o2.text).unwrap();
+            if o2_precedence > o1_precedence
+                || (o2_precedence == o1_precedence && o1_associativity == Associativity::Left)
+            {
+                apply_operator(output, operators.pop().unwrap())?;
+            } else {
+                break;
+            }
+        } else {
+            break;
+        }
+    }
+
+    operators.push(token);
+    Ok(())
+}
+
+fn apply_operator(output: &mut Vec<(Node, SpanTree)>, operator: Token) -> Result<(), CalcError> {
+    let operation = match get_operator_info(&operator.text) {
+        Some(info) => Some(info.operation),
+        None if binding(&operator.text).is_some() => None,
+        None => {
+            return Err(CalcError::at(
+                format!("{} is not a valid operator", operator.text),
+                operator.span,
+            ));
+        }
+    };
+    let right = match output.pop() {
+        Some(right) => right,
+        None => return Err(CalcError::at("Not enough values on stack", operator.span)),
+    };
+    if is_prefix(&operator.text) {
+        let (operand, operand_spans) = right;
+        output.push((
+            Node::negation(operand),
+            SpanTree {
+                span: Span::new(operator.span.start, operand_spans.span.end),
+                children: vec![operand_spans],
+            },
+        ));
+        return Ok(());
+    }
+    let left = match output.pop() {
+        Some(left) => left,
+        None => return Err(CalcError::at("Not enough values on stack", operator.span)),
+    };
+    let Some(operation) = operation else {
+        let node = match operator.text.as_str() {
+            LAMBDA => lambda(left, right)?,
+            _ => generator(left, right)?,
+        };
+        output.push(node);
+        return Ok(());
+    };
+    let (left, left_spans) = left;
+    let (right, right_spans) = right;
+    output.push((
+        Node::BinaryExpr {
+            operation,
+            lhs: Box::new(left),
+            rhs: Box::new(right),
+        },
+        SpanTree {
+            span: Span::new(left_spans.span.start, right_spans.span.end),
+            children: vec![left_spans, right_spans],
+        },
+    ));
+    Ok(())
+}
+
+/// Makes a function of the names in `params`, a single name or a bracketed list of them.
+fn lambda(
+    (mut params, param_spans): (Node, SpanTree),
+    (body, body_spans): (Node, SpanTree),
+) -> Result<(Node, SpanTree), CalcError> {
+    let not_a_name = |node: &Node, spans: &SpanTree| {
+        CalcError::at(
+            format!("A function takes names, found {}", node),
+            spans.span,
+        )
+    };
+    let names = match &mut params {
+        Node::Variable { name, .. } => vec![std::mem::take(name)],
+        Node::List { items, .. } => items
+            .iter()
+            .zip(&param_spans.children)
+            .map(|(item, spans)| match item {
+                Node::Variable { name, .. } => Ok(name.clone()),
+                other => Err(not_a_name(other, spans)),
+            })
+            .collect::<Result<_, _>>()?,
+        other => return Err(not_a_name(other, &param_spans)),
+    };
+    // a generator writes its body before its name
+    let span = Span::new(
+        param_spans.span.start.min(body_spans.span.start),
+        param_spans.span.end.max(body_spans.span.end),
+    );
+    Ok((
+        Node::Lambda {
+            params: names,
+            body: Box::new(body),
+            span,
+        },
+        SpanTree {
+            span,
+            children: vec![body_spans],
+        },
+    ))
+}
+
+/// Turns `body for k in list` into `map(k -> body, list)`.
+fn generator(
+    (body, body_spans): (Node, SpanTree),
+    (binding, mut binding_spans): (Node, SpanTree),
+) -> Result<(Node, SpanTree), CalcError> {
+    let Node::BinaryExpr {
+        operation: BinaryOperator::Conversion,
+        ..
+    } = binding
+    else {
+        return Err(CalcError::at(
+            format!("Expected for name in list, found for {}", binding),
+            binding_spans.span,
+        ));
+    };
+    let (name, list) = binding.into_operands();
+    let mut children = std::mem::take(&mut binding_spans.children).into_iter();
+    let (name_spans, list_spans) = (children.next().unwrap(), children.next().unwrap());
+    let function = lambda((name, name_spans), (body, body_spans))?;
+    let span = Span::new(function.1.span.start, list_spans.span.end);
+    Ok((
+        Node::Function {
+            name: "map".to_string(),
+            args: vec![function.0, list],
+            span,
+        },
+        SpanTree {
+            span,
+            children: vec![function.1, list_spans],
+        },
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::utils::operators::BinaryOperator;
-    use crate::utils::test::tokens;
+    use crate::utils::test::spanned_tokens;
     use pretty_assertions::assert_eq;
 
     #[test]
     fn test_basic_infix_conversion_addition() {
-        let input = tokens(&["4", "+", "5"]);
+        let input = spanned_tokens(&["4", "+", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -172,7 +557,7 @@ mod tests {
 
     #[test]
     fn test_basic_infix_conversion_subtraction() {
-        let input = tokens(&["4", "-", "5"]);
+        let input = spanned_tokens(&["4", "-", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -185,7 +570,7 @@ mod tests {
 
     #[test]
     fn test_basic_infix_conversion_multiplication() {
-        let input = tokens(&["4", "*", "5"]);
+        let input = spanned_tokens(&["4", "*", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -198,7 +583,7 @@ mod tests {
 
     #[test]
     fn test_basic_infix_conversion_division() {
-        let input = tokens(&["4", "/", "5"]);
+        let input = spanned_tokens(&["4", "/", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -211,7 +596,7 @@ mod tests {
 
     #[test]
     fn test_basic_infix_conversion_index() {
-        let input = tokens(&["4", "^", "5"]);
+        let input = spanned_tokens(&["4", "^", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -224,7 +609,7 @@ mod tests {
 
     #[test]
     fn test_foo() {
-        let input = tokens(&["4", "+", "(", "1", "-", "5", ")"]);
+        let input = spanned_tokens(&["4", "+", "(", "1", "-", "5", ")"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -240,7 +625,7 @@ mod tests {
     }
     #[test]
     fn test_another_example() {
-        let input = tokens(&["4", "+", "5", "-", "2", "*", "5"]);
+        let input = spanned_tokens(&["4", "+", "5", "-", "2", "*", "5"]);
         assert_eq!(
             infix_to_ast(input),
             Ok(Node::BinaryExpr {
@@ -258,4 +643,305 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_variables() {
+        let input = spanned_tokens(&["x", "*", "2"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::BinaryExpr {
+                operation: BinaryOperator::Multiplication,
+                lhs: Box::new(Node::Variable {
+                    name: "x".to_string(),
+                    span: Span::new(0, 1),
+                }),
+                rhs: Box::new(Node::Operand { value: 2.0 }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_assignment_statement() {
+        let input = spanned_tokens(&["x", "=", "4"]);
+        assert_eq!(
+            parse_statement(input),
+            Ok(Statement::Assignment {
+                name: "x".to_string(),
+                expr: Node::Operand { value: 4.0 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_mismatched_parenthesis_span() {
+        let input = spanned_tokens(&["(", "1", "+", "2"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Mismatched parentheses found!",
+                Span::new(0, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_implicit_multiplication_binds_tighter_than_division() {
+        let input = spanned_tokens(&["1", "m", "/", "2", "s"]);
+        let unit = |name: &str, start| Node::Variable {
+            name: name.to_string(),
+            span: Span::new(start, start + 1),
+        };
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::BinaryExpr {
+                operation: BinaryOperator::Division,
+                lhs: Box::new(Node::BinaryExpr {
+                    operation: BinaryOperator::Multiplication,
+                    lhs: Box::new(Node::Operand { value: 1.0 }),
+                    rhs: Box::new(unit("m", 2)),
+                }),
+                rhs: Box::new(Node::BinaryExpr {
+                    operation: BinaryOperator::Multiplication,
+                    lhs: Box::new(Node::Operand { value: 2.0 }),
+                    rhs: Box::new(unit("s", 8)),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_conversion_binds_loosest() {
+        let input = spanned_tokens(&["1", "+", "2", "to", "x"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::BinaryExpr {
+                operation: BinaryOperator::Conversion,
+                lhs: Box::new(Node::BinaryExpr {
+                    operation: BinaryOperator::Addition,
+                    lhs: Box::new(Node::Operand { value: 1.0 }),
+                    rhs: Box::new(Node::Operand { value: 2.0 }),
+                }),
+                rhs: Box::new(Node::Variable {
+                    name: "x".to_string(),
+                    span: Span::new(9, 10),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_compound_unit_literal() {
+        let input = spanned_tokens(&["3", "h", "20", "min", "*", "4"]);
+        let unit = |name: &str, start, end| Node::Variable {
+            name: name.to_string(),
+            span: Span::new(start, end),
+        };
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::BinaryExpr {
+                operation: BinaryOperator::Multiplication,
+                lhs: Box::new(Node::BinaryExpr {
+                    operation: BinaryOperator::Addition,
+                    lhs: Box::new(Node::BinaryExpr {
+                        operation: BinaryOperator::Multiplication,
+                        lhs: Box::new(Node::Operand { value: 3.0 }),
+                        rhs: Box::new(unit("h", 2, 3)),
+                    }),
+                    rhs: Box::new(Node::BinaryExpr {
+                        operation: BinaryOperator::Multiplication,
+                        lhs: Box::new(Node::Operand { value: 20.0 }),
+                        rhs: Box::new(unit("min", 7, 10)),
+                    }),
+                }),
+                rhs: Box::new(Node::Operand { value: 4.0 }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_negation_keeps_the_unit() {
+        let node = infix_to_ast(crate::utils::input::tokenize("2 * -3 m").unwrap());
+        assert_eq!(
+            node,
+            Ok(Node::binary(
+                BinaryOperator::Multiplication,
+                Node::Operand { value: 2.0 },
+                Node::negation(Node::binary(
+                    BinaryOperator::Multiplication,
+                    Node::Operand { value: 3.0 },
+                    Node::Variable {
+                        name: "m".to_string(),
+                        span: Span::new(7, 8),
+                    },
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_implicit_addition_needs_time_or_length() {
+        let input = spanned_tokens(&["5", "ft", "3", "inch"]);
+        assert!(matches!(
+            infix_to_ast(input),
+            Ok(Node::BinaryExpr {
+                operation: BinaryOperator::Addition,
+                ..
+            })
+        ));
+        let input = spanned_tokens(&["2", "a", "3"]);
+        assert!(infix_to_ast(input).is_err());
+        let input = spanned_tokens(&["2", "kg", "3"]);
+        assert!(infix_to_ast(input).is_err());
+    }
+
+    #[test]
+    fn test_spans_cover_brackets() {
+        let input = spanned_tokens(&["2", "*", "(", "1", "+", "x", ")"]);
+        let (_, spans) = infix_to_spanned_ast(input).unwrap();
+        assert_eq!(
+            spans,
+            SpanTree {
+                span: Span::new(0, 13),
+                children: vec![
+                    SpanTree::leaf(Span::new(0, 1)),
+                    SpanTree {
+                        span: Span::new(4, 13),
+                        children: vec![
+                            SpanTree::leaf(Span::new(6, 7)),
+                            SpanTree::leaf(Span::new(10, 11)),
+                        ],
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let input = spanned_tokens(&["{", "x", ",", "y", "}"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::List {
+                items: vec![
+                    Node::Variable {
+                        name: "x".to_string(),
+                        span: Span::new(2, 3),
+                    },
+                    Node::Variable {
+                        name: "y".to_string(),
+                        span: Span::new(6, 7),
+                    },
+                ],
+                span: Span::new(0, 9),
+            })
+        );
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let input = spanned_tokens(&["(", "a", ",", "b", ")", "->", "a", "+", "b"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::Lambda {
+                params: vec!["a".to_string(), "b".to_string()],
+                body: Box::new(Node::binary(
+                    BinaryOperator::Addition,
+                    Node::Variable {
+                        name: "a".to_string(),
+                        span: Span::new(13, 14),
+                    },
+                    Node::Variable {
+                        name: "b".to_string(),
+                        span: Span::new(17, 18),
+                    },
+                )),
+                span: Span::new(0, 18),
+            })
+        );
+        let input = spanned_tokens(&["(", "a", ",", "2", ")", "->", "a"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "A function takes names, found 2",
+                Span::new(6, 7)
+            ))
+        );
+        let input = spanned_tokens(&["(", "a", ",", "b", ")", "+", "1"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Found , outside a function call, list or matrix",
+                Span::new(4, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_generators() {
+        let input = spanned_tokens(&["k", "^", "2", "for", "k", "in", "1", "..", "n"]);
+        let (node, spans) = infix_to_spanned_ast(input).unwrap();
+        assert_eq!(node.to_string(), "map(k -> k^2, 1..n)");
+        assert_eq!(spans.span, Span::new(0, 21));
+        let input = spanned_tokens(&["k", "for", "k", "+", "1"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Expected for name in list, found for k + 1",
+                Span::new(6, 11)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_mismatched_braces() {
+        let input = spanned_tokens(&["{", "1", ",", "2", ")"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Mismatched parentheses found!",
+                Span::new(8, 9)
+            ))
+        );
+        let input = spanned_tokens(&["{", "1", "2", "}"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Missing , between items in list",
+                Span::new(0, 7)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_matrices() {
+        let input = spanned_tokens(&["[", "1", ",", "2", ";", "3", ",", "4", "]"]);
+        let number = |value| Node::Operand { value };
+        assert_eq!(
+            infix_to_ast(input),
+            Ok(Node::Matrix {
+                rows: vec![
+                    vec![number(1.0), number(2.0)],
+                    vec![number(3.0), number(4.0)]
+                ],
+                span: Span::new(0, 17),
+            })
+        );
+    }
+
+    #[test]
+    fn test_ragged_matrix() {
+        let input = spanned_tokens(&["[", "1", ",", "2", ";", "3", "]"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at(
+                "Rows of a matrix must be the same length, found 2 and 1",
+                Span::new(0, 13)
+            ))
+        );
+        let input = spanned_tokens(&["1", ";", "2"]);
+        assert_eq!(
+            infix_to_ast(input),
+            Err(CalcError::at("Found ; outside a matrix", Span::new(2, 3)))
+        );
+    }
 }
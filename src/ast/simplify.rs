@@ -0,0 +1,543 @@
+use crate::{
+    ast::{
+        ast::Node,
+        functions,
+        value::Value,
+        visit::{Visitor, visit},
+    },
+    utils::{
+        limits::{Budget, LimitError},
+        operators::BinaryOperator,
+        span::Span,
+    },
+};
+
+/// Rewrites `expr` into a simpler expression with the same value: constant subtrees are folded,
+/// identities such as `x + 0`, `x*1`, `x*0`, `x^1` and `x^0` are applied, and like terms of sums
+/// and products are collected.
+///
+/// Names are treated as plain numbers, so `x*0` becomes `0` even if `x` turns out to be a unit.
+pub fn simplify(expr: &Node) -> Node {
+    // without a budget nothing can run out
+    simplify_within(expr, None).unwrap()
+}
+
+/// [`simplify`], counting each node simplified against `budget`.
+pub fn simplify_within(expr: &Node, budget: Option<&Budget>) -> Result<Node, LimitError> {
+    visit(&mut Simplifier { budget }, expr)
+}
+
+/// Simplifies each node from its children, which are simplified first.
+struct Simplifier<'b> {
+    budget: Option<&'b Budget>,
+}
+
+impl Simplifier<'_> {
+    fn step(&self) -> Result<(), LimitError> {
+        match self.budget {
+            Some(budget) => budget.step(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Simplifier<'_> {
+    type Output = Node;
+    type Error = LimitError;
+
+    /// The target of a conversion is kept as written.
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        match operation {
+            BinaryOperator::Conversion => vec![lhs],
+            _ => vec![lhs, rhs],
+        }
+    }
+
+    /// Lists, matrices and closures are made again from their simplified parts.
+    fn visit_node(&mut self, node: &'a Node, outputs: Vec<Node>) -> Result<Node, LimitError> {
+        self.step()?;
+        let mut outputs = outputs.into_iter();
+        Ok(match node {
+            Node::List { span, .. } => Node::List {
+                items: outputs.collect(),
+                span: *span,
+            },
+            Node::Matrix { rows, span } => Node::Matrix {
+                rows: rows
+                    .iter()
+                    .map(|row| outputs.by_ref().take(row.len()).collect())
+                    .collect(),
+                span: *span,
+            },
+            Node::Lambda { params, span, .. } => Node::Lambda {
+                params: params.clone(),
+                body: Box::new(outputs.next().unwrap()),
+                span: *span,
+            },
+            node => node.clone(),
+        })
+    }
+
+    fn visit_binary(
+        &mut self,
+        _node: &'a Node,
+        operation: BinaryOperator,
+        _lhs: &'a Node,
+        rhs: &'a Node,
+        outputs: Vec<Node>,
+    ) -> Result<Node, LimitError> {
+        self.step()?;
+        let mut outputs = outputs.into_iter();
+        let lhs = outputs.next().unwrap();
+        Ok(match operation {
+            BinaryOperator::Conversion => Node::binary(operation, lhs, rhs.clone()),
+            _ => binary(operation, lhs, outputs.next().unwrap()),
+        })
+    }
+
+    fn visit_negation(
+        &mut self,
+        _node: &'a Node,
+        _operand: &'a Node,
+        outputs: Vec<Node>,
+    ) -> Result<Node, LimitError> {
+        self.step()?;
+        let mut terms = Terms::default();
+        terms.add(outputs.into_iter().next().unwrap(), -1.0);
+        Ok(terms.into_node())
+    }
+
+    fn visit_function(
+        &mut self,
+        _node: &'a Node,
+        name: &'a str,
+        _args: &'a [Node],
+        span: Span,
+        args: Vec<Node>,
+    ) -> Result<Node, LimitError> {
+        self.step()?;
+        // only exact results are folded, so that `ln(2)` is not replaced by its digits
+        if let (Some(builtin), [Node::Operand { value }]) = (functions::builtin(name), &args[..])
+            && (builtin.apply)(*value).fract() == 0.0
+        {
+            return Ok(Node::Operand {
+                value: (builtin.apply)(*value),
+            });
+        }
+        Ok(Node::Function {
+            name: name.to_string(),
+            args,
+            span,
+        })
+    }
+}
+
+/// Simplifies an operation on operands that are simplified already.
+fn binary(operation: BinaryOperator, lhs: Node, rhs: Node) -> Node {
+    if let (Node::Operand { value: lhs }, Node::Operand { value: rhs }) = (&lhs, &rhs)
+        && let Ok(Value::Number(value)) = Value::apply(operation, (*lhs).into(), (*rhs).into())
+    {
+        return Node::Operand { value };
+    }
+    match operation {
+        BinaryOperator::Addition | BinaryOperator::Subtraction => {
+            let mut terms = Terms::default();
+            terms.add(lhs, 1.0);
+            terms.add(
+                rhs,
+                match operation {
+                    BinaryOperator::Addition => 1.0,
+                    _ => -1.0,
+                },
+            );
+            terms.into_node()
+        }
+        BinaryOperator::Multiplication | BinaryOperator::Division => {
+            let mut factors = Factors::new();
+            factors.add(lhs, 1.0);
+            factors.add(
+                rhs,
+                match operation {
+                    BinaryOperator::Multiplication => 1.0,
+                    _ => -1.0,
+                },
+            );
+            factors.into_node()
+        }
+        BinaryOperator::Index => match rhs {
+            Node::Operand { value: 0.0 } => Node::Operand { value: 1.0 },
+            Node::Operand { value: 1.0 } => lhs,
+            rhs => Node::binary(BinaryOperator::Index, lhs, rhs),
+        },
+        BinaryOperator::Range | BinaryOperator::Equation => Node::binary(operation, lhs, rhs),
+        BinaryOperator::Conversion => unreachable!(),
+    }
+}
+
+/// The terms of a sum, as a coefficient for each distinct term in the order they first appear.
+/// Terms are told apart by how they print, so that spans do not matter.
+#[derive(Default)]
+struct Terms {
+    constant: f64,
+    terms: Vec<(String, Node, f64)>,
+}
+
+impl Terms {
+    fn add(&mut self, node: Node, sign: f64) {
+        let mut pending = vec![(node, sign)];
+        while let Some((mut node, sign)) = pending.pop() {
+            match node {
+                Node::BinaryExpr {
+                    operation: operation @ (BinaryOperator::Addition | BinaryOperator::Subtraction),
+                    ..
+                } => {
+                    let (lhs, rhs) = node.into_operands();
+                    let rhs_sign = match operation {
+                        BinaryOperator::Addition => sign,
+                        _ => -sign,
+                    };
+                    pending.extend([(rhs, rhs_sign), (lhs, sign)]);
+                }
+                Node::Negation { ref mut operand } => {
+                    let operand = std::mem::replace(&mut **operand, Node::Operand { value: 0.0 });
+                    pending.push((operand, -sign));
+                }
+                node => {
+                    let (coefficient, term) = split_coefficient(node);
+                    let Some(term) = term else {
+                        self.constant += sign * coefficient;
+                        continue;
+                    };
+                    let key = term.to_string();
+                    match self.terms.iter_mut().find(|(other, _, _)| *other == key) {
+                        Some((_, _, total)) => *total += sign * coefficient,
+                        None => self.terms.push((key, term, sign * coefficient)),
+                    }
+                }
+            }
+        }
+    }
+
+    fn into_node(self) -> Node {
+        let mut terms: Vec<(Node, f64)> = self
+            .terms
+            .into_iter()
+            .filter(|(_, _, coefficient)| *coefficient != 0.0)
+            .map(|(_, term, coefficient)| (term, coefficient))
+            .collect();
+        if self.constant != 0.0 {
+            terms.push((Node::Operand { value: 1.0 }, self.constant));
+        }
+        // a sum reads best starting with a positive term
+        terms.sort_by_key(|(_, coefficient)| coefficient.is_sign_negative());
+
+        let mut terms = terms.into_iter();
+        let Some((first, coefficient)) = terms.next() else {
+            return Node::Operand { value: 0.0 };
+        };
+        let mut sum = match coefficient.is_sign_negative() {
+            true => negate(scale(-coefficient, first)),
+            false => scale(coefficient, first),
+        };
+        for (term, coefficient) in terms {
+            sum = match coefficient.is_sign_negative() {
+                true => Node::binary(BinaryOperator::Subtraction, sum, scale(-coefficient, term)),
+                false => Node::binary(BinaryOperator::Addition, sum, scale(coefficient, term)),
+            };
+        }
+        sum
+    }
+}
+
+/// `-node`, folded into the node if it is a number.
+fn negate(node: Node) -> Node {
+    match node {
+        Node::Operand { value } => Node::Operand { value: 0.0 - value },
+        node => Node::negation(node),
+    }
+}
+
+/// Splits a simplified term into its numeric coefficient, which is always the leftmost factor, and the rest.
+fn split_coefficient(node: Node) -> (f64, Option<Node>) {
+    let (leftmost, factors) = factors(node);
+    let (coefficient, term) = match leftmost {
+        Node::Operand { value } => (value, None),
+        node => (1.0, Some(node)),
+    };
+    let term = factors.into_iter().fold(term, |term, factor| match term {
+        Some(term) => Some(Node::binary(BinaryOperator::Multiplication, term, factor)),
+        None => Some(factor),
+    });
+    (coefficient, term)
+}
+
+/// Multiplies `term` by `coefficient`, written as its leftmost factor.
+fn scale(coefficient: f64, term: Node) -> Node {
+    if coefficient == 1.0 {
+        return term;
+    }
+    let (leftmost, factors) = factors(term);
+    let leftmost = match leftmost {
+        Node::Operand { value } => Node::Operand {
+            value: coefficient * value,
+        },
+        leftmost => Node::binary(
+            BinaryOperator::Multiplication,
+            Node::Operand { value: coefficient },
+            leftmost,
+        ),
+    };
+    factors.into_iter().fold(leftmost, |product, factor| {
+        Node::binary(BinaryOperator::Multiplication, product, factor)
+    })
+}
+
+/// The leftmost factor of a product and the factors after it, in order.
+fn factors(node: Node) -> (Node, Vec<Node>) {
+    let mut node = node;
+    let mut factors = Vec::new();
+    while let Node::BinaryExpr {
+        operation: BinaryOperator::Multiplication,
+        ..
+    } = node
+    {
+        let (lhs, rhs) = node.into_operands();
+        factors.push(rhs);
+        node = lhs;
+    }
+    factors.reverse();
+    (node, factors)
+}
+
+/// The factors of a product or quotient, as a whole-number exponent for each distinct base,
+/// negative for those that divide. Numbers are kept as a fraction so that `x/3` stays exact.
+struct Factors {
+    numerator: f64,
+    denominator: f64,
+    factors: Vec<(String, Node, f64)>,
+}
+
+impl Factors {
+    fn new() -> Factors {
+        Factors {
+            numerator: 1.0,
+            denominator: 1.0,
+            factors: Vec::new(),
+        }
+    }
+
+    /// Multiplies in `node` raised to `power`, which is 1 to multiply and -1 to divide.
+    fn add(&mut self, node: Node, power: f64) {
+        let mut pending = vec![(node, power)];
+        while let Some((mut node, power)) = pending.pop() {
+            let (base, exponent) = match node {
+                Node::Operand { value } => {
+                    match power > 0.0 {
+                        true => self.numerator *= value,
+                        false => self.denominator *= value,
+                    }
+                    continue;
+                }
+                Node::BinaryExpr {
+                    operation:
+                        operation @ (BinaryOperator::Multiplication | BinaryOperator::Division),
+                    ..
+                } => {
+                    let (lhs, rhs) = node.into_operands();
+                    let rhs_power = match operation {
+                        BinaryOperator::Multiplication => power,
+                        _ => -power,
+                    };
+                    pending.extend([(rhs, rhs_power), (lhs, power)]);
+                    continue;
+                }
+                Node::Negation { ref mut operand } => {
+                    self.numerator = -self.numerator;
+                    let operand = std::mem::replace(&mut **operand, Node::Operand { value: 0.0 });
+                    pending.push((operand, power));
+                    continue;
+                }
+                Node::BinaryExpr {
+                    operation: BinaryOperator::Index,
+                    ref rhs,
+                    ..
+                } if matches!(**rhs, Node::Operand { value } if value.fract() == 0.0) => {
+                    let (lhs, rhs) = node.into_operands();
+                    let Node::Operand { value } = rhs else {
+                        unreachable!()
+                    };
+                    (lhs, value * power)
+                }
+                node => (node, power),
+            };
+            let key = base.to_string();
+            match self.factors.iter_mut().find(|(other, _, _)| *other == key) {
+                Some((_, _, total)) => *total += exponent,
+                None => self.factors.push((key, base, exponent)),
+            }
+        }
+    }
+
+    fn into_node(self) -> Node {
+        let (mut numerator, mut denominator) = (self.numerator, self.denominator);
+        if numerator == 0.0 {
+            return Node::Operand { value: 0.0 };
+        }
+        let whole = |value: f64| value.fract() == 0.0 && value.abs() < 2f64.powi(53);
+        if whole(numerator) && whole(denominator) && !whole(numerator / denominator) {
+            let divisor = gcd(numerator.abs() as u64, denominator.abs() as u64) as f64;
+            numerator /= divisor.copysign(denominator);
+            denominator = (denominator / divisor).abs();
+        } else {
+            numerator /= denominator;
+            denominator = 1.0;
+        }
+
+        let product = |factors: Vec<(Node, f64)>, coefficient: f64| {
+            let product = factors
+                .into_iter()
+                .map(|(base, exponent)| match exponent {
+                    1.0 => base,
+                    exponent => Node::binary(
+                        BinaryOperator::Index,
+                        base,
+                        Node::Operand { value: exponent },
+                    ),
+                })
+                .reduce(|product, factor| {
+                    Node::binary(BinaryOperator::Multiplication, product, factor)
+                });
+            match product {
+                Some(product) => scale(coefficient, product),
+                None => Node::Operand { value: coefficient },
+            }
+        };
+        let (above, below): (Vec<_>, Vec<_>) = self
+            .factors
+            .into_iter()
+            .filter(|(_, _, exponent)| *exponent != 0.0)
+            .map(|(_, base, exponent)| (base, exponent))
+            .partition(|(_, exponent)| *exponent > 0.0);
+        let below: Vec<_> = below
+            .into_iter()
+            .map(|(base, exponent)| (base, -exponent))
+            .collect();
+
+        let has_denominator = !below.is_empty() || denominator != 1.0;
+        let quotient = match has_denominator {
+            true => Node::binary(
+                BinaryOperator::Division,
+                product(above, numerator.abs()),
+                product(below, denominator),
+            ),
+            false => product(above, numerator.abs()),
+        };
+        match numerator.is_sign_negative() {
+            true => negate(quotient),
+            false => quotient,
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{environment::Environment, parse_expression};
+    use crate::utils::span::Span;
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+
+    fn simplified(input: &str) -> String {
+        simplify(&parse_expression(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_constants_and_identities() {
+        assert_eq!(simplified("x*1 + 0 + 2*3"), "x + 6");
+        assert_eq!(simplified("x*0 + y^1"), "y");
+        assert_eq!(simplified("(x + y)^0"), "1");
+        assert_eq!(simplified("x/1 - 0"), "x");
+    }
+
+    #[test]
+    fn test_like_terms() {
+        assert_eq!(simplified("2*x + 3 + x*3 - 1"), "5*x + 2");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("6 - x"), "6 - x");
+        assert_eq!(simplified("x*y*2*x"), "2*x^2*y");
+        assert_eq!(simplified("x^2*x^-2*y"), "y");
+        assert_eq!(simplified("x^3/x + y/x"), "x^2 + y/x");
+        assert_eq!(simplified("-2*x/(6*y)"), "-x/(3*y)");
+    }
+
+    #[test]
+    fn test_conversion_target_is_kept() {
+        assert_eq!(simplified("3 m + 2 m to km"), "5*m to km");
+    }
+
+    fn arbitrary_node() -> impl Strategy<Value = Node> {
+        let leaf = prop_oneof![
+            (0u32..10).prop_map(|value| Node::Operand {
+                value: f64::from(value),
+            }),
+            prop_oneof![Just("x"), Just("y")].prop_map(|name| Node::Variable {
+                name: name.to_string(),
+                span: Span::default(),
+            }),
+        ];
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                (
+                    prop_oneof![
+                        Just(BinaryOperator::Addition),
+                        Just(BinaryOperator::Subtraction),
+                        Just(BinaryOperator::Multiplication),
+                        Just(BinaryOperator::Division),
+                    ],
+                    inner.clone(),
+                    inner.clone(),
+                )
+                    .prop_map(|(operation, lhs, rhs)| Node::binary(operation, lhs, rhs)),
+                (inner, 0u32..4).prop_map(|(base, exponent)| Node::binary(
+                    BinaryOperator::Index,
+                    base,
+                    Node::Operand {
+                        value: f64::from(exponent),
+                    },
+                )),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn simplification_preserves_value(expr in arbitrary_node()) {
+            let mut env = Environment::new();
+            env.set("x".to_string(), 1.5.into());
+            env.set("y".to_string(), (-2.0).into());
+            let Ok(Value::Number(expected)) = expr.calculate(&env) else {
+                panic!("{} did not evaluate to a number", expr);
+            };
+            prop_assume!(expected.is_finite());
+            let Ok(Value::Number(actual)) = simplify(&expr).calculate(&env) else {
+                panic!("simplified {} did not evaluate to a number", expr);
+            };
+            prop_assert!(
+                (actual - expected).abs() <= 1e-9 * expected.abs().max(1.0),
+                "{} = {} but {} = {}", expr, expected, simplify(&expr), actual
+            );
+        }
+    }
+}
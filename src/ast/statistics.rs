@@ -0,0 +1,233 @@
+use crate::ast::{numeric::KahanSum, value::Value};
+
+/// Functions that summarise any number of values, given one by one or as lists,
+/// with the fewest arguments each takes. `percentile` takes the percentile as its last argument.
+pub const FUNCTIONS: &[(&str, usize)] = &[
+    ("sum", 1),
+    ("prod", 1),
+    ("mean", 1),
+    ("median", 1),
+    ("variance", 1),
+    ("stdev", 1),
+    ("percentile", 2),
+    ("min", 1),
+    ("max", 1),
+];
+
+pub fn is_statistic(name: &str) -> bool {
+    FUNCTIONS.iter().any(|(function, _)| *function == name)
+}
+
+/// Calls `name` with evaluated arguments, which are numbers or lists of numbers.
+pub fn call(name: &str, args: Vec<Value>) -> Result<Value, String> {
+    if name == "percentile" && !matches!(args.last(), Some(Value::Number(_))) {
+        return Err(format!(
+            "percentile expects a number as its last argument, found {}",
+            args.last().map_or("nothing".to_string(), Value::to_string)
+        ));
+    }
+    let mut values = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Number(value) => values.push(value),
+            Value::List(items) => values.extend(items),
+            other => {
+                return Err(format!(
+                    "{} expects numbers or lists of numbers, found {}",
+                    name, other
+                ));
+            }
+        }
+    }
+    summarise(name, &values).map(Value::Number)
+}
+
+/// Works out the statistic `name` of `values`. For `percentile` the last value is the percentile.
+pub fn summarise(name: &str, values: &[f64]) -> Result<f64, String> {
+    let (values, percent) = match (name, values) {
+        ("percentile", [values @ .., percent]) => (values, Some(*percent)),
+        _ => (values, None),
+    };
+    let empty = || format!("{} of an empty list has no value", name);
+    let sum = || {
+        let mut sum = KahanSum::default();
+        values.iter().for_each(|value| sum.add(*value));
+        sum.total()
+    };
+    match name {
+        "sum" => Ok(sum()),
+        "prod" => Ok(values.iter().product()),
+        "min" => values.iter().copied().reduce(f64::min).ok_or_else(empty),
+        "max" => values.iter().copied().reduce(f64::max).ok_or_else(empty),
+        "mean" if values.is_empty() => Err(empty()),
+        "mean" => Ok(sum() / values.len() as f64),
+        "variance" | "stdev" => {
+            let moments = Moments::of(values);
+            if moments.count < 2 {
+                return Err(format!(
+                    "{} needs at least 2 values, found {}",
+                    name, moments.count
+                ));
+            }
+            let variance = moments.sample_variance();
+            Ok(match name {
+                "stdev" => variance.sqrt(),
+                _ => variance,
+            })
+        }
+        "median" => percentile(values, 50.0).ok_or_else(empty),
+        "percentile" => {
+            let percent = percent.unwrap();
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!(
+                    "percentile must be between 0 and 100, found {}",
+                    percent
+                ));
+            }
+            percentile(values, percent).ok_or_else(empty)
+        }
+        _ => Err(format!("Unknown function: {}", name)),
+    }
+}
+
+/// The value below which `percent` of `values` lie, interpolating linearly between the two
+/// closest ranks so that the 50th percentile is the median.
+fn percentile(values: &[f64], percent: f64) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = percent / 100.0 * (sorted.len().checked_sub(1)? as f64);
+    let (below, above) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+    Some(below + (above - below) * rank.fract())
+}
+
+/// The count, mean and sum of squared deviations from the mean of some values, built up one value
+/// at a time with Welford's algorithm, which does not lose precision when the mean is large.
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    count: usize,
+    mean: f64,
+    squares: f64,
+}
+
+impl Moments {
+    fn of(values: &[f64]) -> Moments {
+        let mut moments = Moments::default();
+        for value in values {
+            moments.add(*value);
+        }
+        moments
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (value - self.mean);
+    }
+
+    fn sample_variance(&self) -> f64 {
+        self.squares / (self.count - 1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{environment::Environment, evaluate};
+    use pretty_assertions::assert_eq;
+
+    fn calculated(input: &str) -> String {
+        match evaluate(input, &Environment::new()) {
+            Ok(value) => value.to_string(),
+            Err(error) => format!("Error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_summaries() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(summarise("sum", &values), Ok(40.0));
+        assert_eq!(summarise("prod", &[1.0, 2.0, 3.0, 4.0]), Ok(24.0));
+        assert_eq!(summarise("mean", &values), Ok(5.0));
+        assert_eq!(summarise("median", &values), Ok(4.5));
+        assert_eq!(summarise("variance", &values), Ok(32.0 / 7.0));
+        assert_eq!(summarise("min", &values), Ok(2.0));
+        assert_eq!(summarise("max", &values), Ok(9.0));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = [15.0, 20.0, 35.0, 40.0, 50.0];
+        let at = |percent: f64| summarise("percentile", &[&values[..], &[percent]].concat());
+        assert_eq!(at(0.0), Ok(15.0));
+        assert_eq!(at(40.0), Ok(29.0));
+        assert_eq!(at(100.0), Ok(50.0));
+        assert_eq!(
+            at(120.0),
+            Err("percentile must be between 0 and 100, found 120".to_string())
+        );
+    }
+
+    #[test]
+    fn test_variance_is_stable() {
+        // the naive sum of squares loses every digit of these
+        let values = [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0];
+        assert_eq!(summarise("variance", &values), Ok(30.0));
+        assert_eq!(summarise("sum", &[0.1; 10]), Ok(1.0));
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(summarise("sum", &[]), Ok(0.0));
+        assert_eq!(
+            summarise("mean", &[]),
+            Err("mean of an empty list has no value".to_string())
+        );
+        assert_eq!(
+            summarise("stdev", &[1.0]),
+            Err("stdev needs at least 2 values, found 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lists_and_ranges() {
+        assert_eq!(calculated("{1, 2 + 3, 2^3}"), "{1, 5, 8}");
+        assert_eq!(calculated("2..2*3"), "{2, 3, 4, 5, 6}");
+        assert_eq!(calculated("mean(1, 4, 9)"), calculated("14/3"));
+        assert_eq!(calculated("sum(1..100)"), "5050");
+        assert_eq!(calculated("max({3, 1}, 7, 2..5)"), "7");
+        assert_eq!(calculated("percentile(1..11, 90)"), "10");
+        assert_eq!(calculated("sqrt({4, 9})"), "{2, 3}");
+    }
+
+    #[test]
+    fn test_list_errors() {
+        assert_eq!(
+            calculated("{1, 2} * 2"),
+            "Error: Cannot multiply {1, 2} and 2: a list can only be given to a function"
+        );
+        assert_eq!(
+            calculated("mean({1, 2 m})"),
+            "Error: A list holds plain numbers, found 2 m"
+        );
+        assert_eq!(
+            calculated("median()"),
+            "Error: median expects at least 1 argument, found 0"
+        );
+    }
+
+    #[test]
+    fn test_call_flattens_lists() {
+        assert_eq!(
+            call(
+                "mean",
+                vec![Value::List(vec![1.0, 4.0]), Value::Number(9.0)]
+            ),
+            Ok(Value::Number(14.0 / 3.0))
+        );
+        assert_eq!(
+            call("percentile", vec![Value::List(vec![1.0, 2.0])]),
+            Err("percentile expects a number as its last argument, found {1, 2}".to_string())
+        );
+    }
+}
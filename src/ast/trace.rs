@@ -0,0 +1,207 @@
+use crate::{
+    ast::{
+        ast::Node,
+        display::{needs_parentheses, negation_info, operator_info},
+        value::Value,
+    },
+    utils::operators::{BinaryOperator, OperatorInfo},
+};
+
+/// Expressions with more nodes than this are not traced, since every step prints them again.
+pub const MAX_NODES: usize = 1000;
+
+/// One reduction: the node at `path` (child indices from the root) evaluated to `value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub path: Vec<usize>,
+    pub value: Value,
+}
+
+/// The reductions made while evaluating an expression, in the order they happened.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Trace {
+    path: Vec<usize>,
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    pub(crate) fn enter(&mut self, index: usize) {
+        self.path.push(index);
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    pub(crate) fn record(&mut self, value: Value) {
+        self.steps.push(Step {
+            path: self.path.clone(),
+            value,
+        });
+    }
+
+    /// The expression as it stood before each step, starting with `node` as written.
+    /// The step after the last line is the one that produced the result, or the one that failed.
+    pub fn rewrites(&self, node: &Node) -> Vec<String> {
+        (0..self.steps.len())
+            .map(|done| {
+                let mut line = String::new();
+                write_reduced(&mut line, node, &mut Vec::new(), &self.steps[..done]);
+                line
+            })
+            .collect()
+    }
+}
+
+fn write_reduced(out: &mut String, node: &Node, path: &mut Vec<usize>, steps: &[Step]) {
+    if let Some(step) = steps.iter().find(|step| step.path == *path) {
+        out.push_str(&step.value.to_string());
+        return;
+    }
+    match node {
+        Node::BinaryExpr {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let info = operator_info(*operation);
+            let separator = match operation {
+                BinaryOperator::Addition
+                | BinaryOperator::Subtraction
+                | BinaryOperator::Conversion
+                | BinaryOperator::Equation => " ",
+                _ => "",
+            };
+            write_child(out, lhs, info, false, path, 0, steps);
+            out.push_str(&format!("{}{}{}", separator, operation.symbol(), separator));
+            write_child(out, rhs, info, true, path, 1, steps);
+        }
+        Node::Negation { operand } => {
+            out.push('-');
+            write_child(out, operand, negation_info(), true, path, 0, steps);
+        }
+        Node::Function { name, args, .. } => {
+            out.push_str(name);
+            out.push('(');
+            for (index, arg) in args.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                path.push(index);
+                write_reduced(out, arg, path, steps);
+                path.pop();
+            }
+            out.push(')');
+        }
+        _ => out.push_str(&node.to_string()),
+    }
+}
+
+fn write_child(
+    out: &mut String,
+    child: &Node,
+    parent: &OperatorInfo,
+    is_rhs: bool,
+    path: &mut Vec<usize>,
+    index: usize,
+    steps: &[Step],
+) {
+    path.push(index);
+    let wrap = match steps.iter().find(|step| step.path == *path) {
+        Some(step) => value_needs_parentheses(&step.value, parent, is_rhs),
+        None => needs_parentheses(child, parent, is_rhs),
+    };
+    if wrap {
+        out.push('(');
+    }
+    write_reduced(out, child, path, steps);
+    if wrap {
+        out.push(')');
+    }
+    path.pop();
+}
+
+/// Numbers follow the same rule as written operands, and a quantity reads as one term only
+/// beside the loosest operators.
+fn value_needs_parentheses(value: &Value, parent: &OperatorInfo, is_rhs: bool) -> bool {
+    match value {
+        Value::Number(value) => needs_parentheses(&Node::Operand { value: *value }, parent, is_rhs),
+        Value::Quantity(_) => !matches!(
+            parent.operation,
+            BinaryOperator::Addition | BinaryOperator::Subtraction | BinaryOperator::Conversion
+        ),
+        Value::DateTime(_)
+        | Value::Solutions(_)
+        | Value::Matrix(_)
+        | Value::List(_)
+        | Value::Closure(_) => false,
+        Value::Expression(expr) => needs_parentheses(expr, parent, is_rhs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{environment::Environment, parse_expression};
+    use pretty_assertions::assert_eq;
+
+    fn trace(input: &str, env: &Environment) -> Vec<String> {
+        let expr = parse_expression(input).unwrap();
+        let mut trace = Trace::new();
+        expr.calculate_traced(env, &mut trace).unwrap();
+        trace.rewrites(&expr)
+    }
+
+    #[test]
+    fn test_wikipedia_example() {
+        assert_eq!(
+            trace("3 + 4 * 2 ÷ ( 1 - 5 ) ^ 2 ^ 3", &Environment::new()),
+            vec![
+                "3 + 4*2/(1 - 5)^2^3",
+                "3 + 8/(1 - 5)^2^3",
+                "3 + 8/(-4)^2^3",
+                "3 + 8/(-4)^8",
+                "3 + 8/65536",
+                "3 + 0.0001220703125",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables_are_substituted() {
+        let mut env = Environment::new();
+        env.set("x".to_string(), 3.0.into());
+        assert_eq!(
+            trace("2 * x + 1", &env),
+            vec!["2*x + 1", "2*3 + 1", "6 + 1"]
+        );
+    }
+
+    #[test]
+    fn test_units_stay_as_written() {
+        assert_eq!(
+            trace("2 km + 300 m to m", &Environment::new()),
+            vec![
+                "2*km + 300*m to m",
+                "2 km + 300*m to m",
+                "2 km + 300 m to m",
+                "2.3 km to m",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_stops_at_error() {
+        let expr = parse_expression("1 + 2 * y").unwrap();
+        let mut trace = Trace::new();
+        assert!(
+            expr.calculate_traced(&Environment::new(), &mut trace)
+                .is_err()
+        );
+        assert_eq!(trace.steps, Vec::new());
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use crate::{
+    ast::{ast::Node, closure::Closure, equation::SolutionSet, matrix::Matrix, simplify::simplify},
+    datetime::DateTime,
+    units::{
+        dimension::Dimension,
+        quantity::{DisplayUnit, Quantity},
+    },
+    utils::operators::BinaryOperator,
+};
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Quantity(Quantity),
+    DateTime(DateTime),
+    /// A symbolic result, such as a derivative with names that have no value.
+    Expression(Node),
+    /// The values that solve an equation.
+    Solutions(SolutionSet),
+    Matrix(Matrix),
+    /// Numbers written between braces or made by a range, to be summarised by a statistic.
+    List(Vec<f64>),
+    /// A function to give to `map`, `filter` or `reduce`.
+    Closure(Closure),
+}
+
+impl Value {
+    fn into_quantity(self) -> Result<Quantity, String> {
+        match self {
+            Value::Number(value) => Ok(Quantity::dimensionless(value)),
+            Value::Quantity(quantity) => Ok(quantity),
+            Value::DateTime(date) => Err(format!("{} is a date, not a quantity", date)),
+            Value::Expression(expr) => Err(format!("{} is an expression, not a quantity", expr)),
+            Value::Solutions(solutions) => {
+                Err(format!("{} is a solution, not a quantity", solutions))
+            }
+            Value::Matrix(matrix) => Err(format!("{} is a matrix, not a quantity", matrix)),
+            Value::List(_) => Err(format!("{} is a list, not a quantity", self)),
+            Value::Closure(closure) => Err(format!("{} is a function, not a quantity", closure)),
+        }
+    }
+
+    /// Collapses quantities that have lost all their units back into plain numbers.
+    fn from_quantity(quantity: Quantity) -> Value {
+        if quantity.dimension.is_dimensionless() {
+            Value::Number(quantity.value)
+        } else {
+            Value::Quantity(quantity)
+        }
+    }
+
+    /// Collapses a matrix with a single entry, like the product of a row and a column, into a number.
+    pub fn from_matrix(matrix: Matrix) -> Value {
+        match matrix.entries() {
+            [entry] => Value::Number(*entry),
+            _ => Value::Matrix(matrix),
+        }
+    }
+
+    pub fn apply(operation: BinaryOperator, lhs: Value, rhs: Value) -> Result<Value, String> {
+        if let (Value::Number(lhs), Value::Number(rhs)) = (&lhs, &rhs) {
+            let (lhs, rhs) = (*lhs, *rhs);
+            return match operation {
+                BinaryOperator::Addition => Ok(Value::Number(lhs + rhs)),
+                BinaryOperator::Subtraction => Ok(Value::Number(lhs - rhs)),
+                BinaryOperator::Multiplication => Ok(Value::Number(lhs * rhs)),
+                BinaryOperator::Division => Ok(Value::Number(lhs / rhs)),
+                BinaryOperator::Index => Ok(Value::Number(lhs.powf(rhs))),
+                BinaryOperator::Conversion => Err(format!("{} is not a unit", rhs)),
+                BinaryOperator::Range => range(lhs, rhs).map(Value::List),
+                BinaryOperator::Equation => Err(equation_error()),
+            };
+        }
+
+        if matches!(lhs, Value::List(_)) || matches!(rhs, Value::List(_)) {
+            return Err(format!(
+                "Cannot {} {} and {}: a list can only be given to a function",
+                operation.verb(),
+                lhs,
+                rhs
+            ));
+        }
+
+        if matches!(lhs, Value::Matrix(_)) || matches!(rhs, Value::Matrix(_)) {
+            return Matrix::apply(operation, lhs, rhs);
+        }
+
+        if matches!(lhs, Value::Expression(_)) || matches!(rhs, Value::Expression(_)) {
+            return Value::apply_to_expressions(operation, lhs, rhs);
+        }
+
+        if matches!(lhs, Value::DateTime(_)) || matches!(rhs, Value::DateTime(_)) {
+            return Value::apply_to_dates(operation, lhs, rhs);
+        }
+
+        let description = format!("{} and {}", lhs, rhs);
+        let lhs_is_number = matches!(lhs, Value::Number(_));
+        let rhs_is_number = matches!(rhs, Value::Number(_));
+        let lhs = lhs.into_quantity()?;
+        let rhs = rhs.into_quantity()?;
+
+        let quantity = match operation {
+            BinaryOperator::Addition | BinaryOperator::Subtraction => {
+                if lhs.dimension != rhs.dimension {
+                    return Err(format!(
+                        "Dimension mismatch: cannot {} {}",
+                        operation.verb(),
+                        description
+                    ));
+                }
+                let value = match operation {
+                    BinaryOperator::Addition => lhs.value + rhs.value,
+                    _ => lhs.value - rhs.value,
+                };
+                Quantity {
+                    value,
+                    dimension: lhs.dimension,
+                    display: lhs.display.or(rhs.display),
+                }
+            }
+            BinaryOperator::Multiplication | BinaryOperator::Division => {
+                let is_multiplication = operation == BinaryOperator::Multiplication;
+                let dimension = match is_multiplication {
+                    true => lhs.dimension.checked_mul(rhs.dimension),
+                    false => lhs.dimension.checked_div(rhs.dimension),
+                };
+                let Some(dimension) = dimension else {
+                    return Err(format!("Unit exponent out of range for {}", description));
+                };
+                // scaling by a plain number keeps the unit the quantity was written in
+                let display = match (lhs_is_number, rhs_is_number) {
+                    (true, false) if is_multiplication => rhs.display,
+                    (false, true) => lhs.display,
+                    _ => None,
+                };
+                Quantity {
+                    value: match is_multiplication {
+                        true => lhs.value * rhs.value,
+                        false => lhs.value / rhs.value,
+                    },
+                    dimension,
+                    display,
+                }
+            }
+            BinaryOperator::Index => {
+                if !rhs.dimension.is_dimensionless() {
+                    return Err(format!(
+                        "Exponent must be dimensionless, found {}",
+                        Value::Quantity(rhs)
+                    ));
+                }
+                let Some(dimension) = lhs.dimension.pow(rhs.value) else {
+                    return Err(format!(
+                        "Cannot raise {} to the power of {}",
+                        Value::Quantity(lhs),
+                        rhs.value
+                    ));
+                };
+                let display = lhs
+                    .display
+                    .filter(|display| !display.label.contains([' ', '/', '^']))
+                    .map(|display| DisplayUnit {
+                        label: format!("{}^{}", display.label, rhs.value),
+                        factor: display.factor.powf(rhs.value),
+                    });
+                Quantity {
+                    value: lhs.value.powf(rhs.value),
+                    dimension,
+                    display,
+                }
+            }
+            BinaryOperator::Conversion => {
+                return Value::Quantity(lhs).convert(Value::Quantity(rhs), None);
+            }
+            BinaryOperator::Range => {
+                return Err(format!(
+                    "Cannot {} {}: a range runs between plain numbers",
+                    operation.verb(),
+                    description
+                ));
+            }
+            BinaryOperator::Equation => return Err(equation_error()),
+        };
+
+        Ok(Value::from_quantity(quantity))
+    }
+
+    /// The value with its sign flipped, in the same unit.
+    /// Subtracts from zero rather than flipping the sign bit, so `-0` is plain 0.
+    pub fn negate(self) -> Result<Value, String> {
+        match self {
+            Value::Number(value) => Ok(Value::Number(0.0 - value)),
+            Value::Quantity(quantity) => Ok(Value::Quantity(Quantity {
+                value: 0.0 - quantity.value,
+                ..quantity
+            })),
+            Value::Matrix(matrix) => Ok(Value::Matrix(matrix.map(|entry| 0.0 - entry))),
+            Value::Expression(expr) => match simplify(&Node::negation(expr)) {
+                Node::Operand { value } => Ok(Value::Number(value)),
+                expr => Ok(Value::Expression(expr)),
+            },
+            other => Err(format!("Cannot negate {}", other)),
+        }
+    }
+
+    /// Builds a bigger expression out of symbolic operands and plain numbers.
+    fn apply_to_expressions(
+        operation: BinaryOperator,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<Value, String> {
+        let description = format!("Cannot {} {} and {}", operation.verb(), lhs, rhs);
+        let node = |value: Value| match value {
+            Value::Number(value) => Some(Node::Operand { value }),
+            Value::Expression(expr) => Some(expr),
+            _ => None,
+        };
+        match (operation, node(lhs), node(rhs)) {
+            (BinaryOperator::Conversion | BinaryOperator::Equation, _, _)
+            | (_, None, _)
+            | (_, _, None) => Err(description),
+            (operation, Some(lhs), Some(rhs)) => {
+                match simplify(&Node::binary(operation, lhs, rhs)) {
+                    Node::Operand { value } => Ok(Value::Number(value)),
+                    expr => Ok(Value::Expression(expr)),
+                }
+            }
+        }
+    }
+
+    fn apply_to_dates(operation: BinaryOperator, lhs: Value, rhs: Value) -> Result<Value, String> {
+        match (operation, lhs, rhs) {
+            (BinaryOperator::Addition, Value::DateTime(date), Value::Quantity(duration))
+            | (BinaryOperator::Addition, Value::Quantity(duration), Value::DateTime(date))
+                if duration.dimension == Dimension::TIME =>
+            {
+                Ok(Value::DateTime(shift(date, &duration, 1.0)))
+            }
+            (BinaryOperator::Subtraction, Value::DateTime(date), Value::Quantity(duration))
+                if duration.dimension == Dimension::TIME =>
+            {
+                Ok(Value::DateTime(shift(date, &duration, -1.0)))
+            }
+            (BinaryOperator::Subtraction, Value::DateTime(lhs), Value::DateTime(rhs)) => {
+                if lhs.utc != rhs.utc {
+                    return Err(format!(
+                        "Cannot mix timezone-less and UTC times: {} and {}",
+                        lhs, rhs
+                    ));
+                }
+                // the gap between two plain dates reads best in days
+                let display = (!lhs.has_time && !rhs.has_time).then(|| DisplayUnit {
+                    label: "days".to_string(),
+                    factor: 86400.0,
+                });
+                Ok(Value::Quantity(Quantity {
+                    value: lhs.seconds - rhs.seconds,
+                    dimension: Dimension::TIME,
+                    display,
+                }))
+            }
+            (operation, lhs, rhs) => {
+                Err(format!("Cannot {} {} and {}", operation.verb(), lhs, rhs))
+            }
+        }
+    }
+
+    /// Expresses `self` in the unit given by `target`, labelled with `label` when given.
+    pub fn convert(self, target: Value, label: Option<String>) -> Result<Value, String> {
+        let target = match target {
+            Value::Quantity(target) => target,
+            target => return Err(format!("{} is not a unit", target)),
+        };
+        let quantity = self.into_quantity()?;
+        if quantity.dimension != target.dimension {
+            return Err(format!(
+                "Dimension mismatch: cannot convert {} to {}",
+                Value::from_quantity(quantity),
+                target.dimension
+            ));
+        }
+        let label = match (label, target.display) {
+            (Some(label), _) => label,
+            (None, Some(display)) => display.label,
+            (None, None) => target.dimension.to_string(),
+        };
+        Ok(Value::Quantity(Quantity {
+            value: quantity.value,
+            dimension: quantity.dimension,
+            display: Some(DisplayUnit {
+                label,
+                factor: target.value,
+            }),
+        }))
+    }
+}
+
+pub(crate) fn equation_error() -> String {
+    "= relates the two sides of an equation and has no value of its own".to_string()
+}
+
+/// The most items a list can hold, so that a range like `1..10^12` fails rather than fills memory.
+pub const MAX_LIST_LEN: usize = 1_000_000;
+
+/// Every whole step from `start` up to `end`, including `end` when it is a whole number of steps away.
+fn range(start: f64, end: f64) -> Result<Vec<f64>, String> {
+    let count = (end - start).floor() + 1.0;
+    if count.is_nan() || count > MAX_LIST_LEN as f64 {
+        return Err(format!(
+            "The range {}..{} has more than {} items",
+            start, end, MAX_LIST_LEN
+        ));
+    }
+    Ok((0..count.max(0.0) as u64)
+        .map(|step| start + step as f64)
+        .collect())
+}
+
+/// Moves a date by a duration, using calendar months when the duration is a whole number of months or years.
+fn shift(date: DateTime, duration: &Quantity, direction: f64) -> DateTime {
+    if let Some(display) = &duration.display {
+        let months_per_unit = match display.label.as_str() {
+            "month" | "months" => Some(1.0),
+            "year" | "years" => Some(12.0),
+            _ => None,
+        };
+        let count = duration.value / display.factor;
+        if let Some(months_per_unit) = months_per_unit
+            && count.fract() == 0.0
+        {
+            return date.add_months((direction * count * months_per_unit) as i64);
+        }
+    }
+    date.add_seconds(direction * duration.value)
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Number(value)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Quantity(quantity) => write!(f, "{}", quantity),
+            Value::DateTime(date) => write!(f, "{}", date),
+            Value::Expression(expr) => write!(f, "{}", expr),
+            Value::Solutions(solutions) => write!(f, "{}", solutions),
+            Value::Matrix(matrix) => write!(f, "{}", matrix),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+            Value::Closure(closure) => write!(f, "{}", closure),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{quantity::Quantity, registry::lookup};
+    use pretty_assertions::assert_eq;
+
+    fn unit(name: &str) -> Value {
+        Value::Quantity(Quantity::from_unit(name, lookup(name).unwrap()))
+    }
+
+    fn quantity(value: f64, name: &str) -> Value {
+        Value::apply(
+            BinaryOperator::Multiplication,
+            Value::Number(value),
+            unit(name),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_numbers_are_unchanged() {
+        assert_eq!(
+            Value::apply(BinaryOperator::Index, 2.0.into(), 3.0.into()),
+            Ok(Value::Number(8.0))
+        );
+    }
+
+    #[test]
+    fn test_addition_keeps_left_unit() {
+        let sum = Value::apply(
+            BinaryOperator::Addition,
+            quantity(5.0, "km"),
+            quantity(300.0, "m"),
+        )
+        .unwrap();
+        assert_eq!(sum.to_string(), "5.3 km");
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        assert_eq!(
+            Value::apply(
+                BinaryOperator::Addition,
+                quantity(5.0, "kg"),
+                quantity(3.0, "m"),
+            ),
+            Err("Dimension mismatch: cannot add 5 kg and 3 m".to_string())
+        );
+    }
+
+    #[test]
+    fn test_units_cancel() {
+        assert_eq!(
+            Value::apply(
+                BinaryOperator::Division,
+                quantity(6.0, "m"),
+                quantity(2.0, "m"),
+            ),
+            Ok(Value::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            Value::apply(BinaryOperator::Range, 1.0.into(), 4.0.into()),
+            Ok(Value::List(vec![1.0, 2.0, 3.0, 4.0]))
+        );
+        assert_eq!(
+            Value::apply(BinaryOperator::Range, 0.5.into(), 2.0.into()),
+            Ok(Value::List(vec![0.5, 1.5]))
+        );
+        assert_eq!(
+            Value::apply(BinaryOperator::Range, 3.0.into(), 1.0.into()),
+            Ok(Value::List(Vec::new()))
+        );
+        assert_eq!(
+            Value::apply(BinaryOperator::Range, quantity(1.0, "m"), 3.0.into()),
+            Err(
+                "Cannot make a range from 1 m and 3: a range runs between plain numbers"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let speed = Value::apply(
+            BinaryOperator::Division,
+            quantity(10.0, "m"),
+            quantity(1.0, "s"),
+        )
+        .unwrap();
+        let target = Value::apply(BinaryOperator::Division, unit("km"), unit("h")).unwrap();
+        assert_eq!(
+            speed
+                .convert(target, Some("km/h".to_string()))
+                .unwrap()
+                .to_string(),
+            "36 km/h"
+        );
+    }
+}
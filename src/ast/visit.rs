@@ -0,0 +1,481 @@
+use crate::{
+    ast::ast::Node,
+    datetime::DateTime,
+    utils::{operators::BinaryOperator, span::Span},
+};
+
+/// Works something out for each node of a tree from what it worked out for the node's children,
+/// which [`visit`] visits first. The `walk_` methods choose which children those are, by default
+/// all of them in the order they are written, and the `visit_` methods fall back on
+/// [`Visitor::visit_node`] for the kinds of node they are not written for.
+#[allow(unused_variables)]
+pub trait Visitor<'a> {
+    type Output;
+    type Error;
+
+    /// Called on the way down, before any of the node's children. `parent` is the node above it
+    /// and which of the children it walked this one is.
+    fn enter(&mut self, node: &'a Node, parent: Option<(&'a Node, usize)>) {}
+
+    /// Called on the way back up, once the node has been visited without an error.
+    fn leave(&mut self, node: &'a Node) {}
+
+    fn walk_binary(
+        &mut self,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+    ) -> Vec<&'a Node> {
+        vec![lhs, rhs]
+    }
+
+    fn walk_negation(&mut self, operand: &'a Node) -> Vec<&'a Node> {
+        vec![operand]
+    }
+
+    fn walk_function(&mut self, name: &'a str, args: &'a [Node]) -> Vec<&'a Node> {
+        args.iter().collect()
+    }
+
+    fn walk_list(&mut self, items: &'a [Node]) -> Vec<&'a Node> {
+        items.iter().collect()
+    }
+
+    fn walk_matrix(&mut self, rows: &'a [Vec<Node>]) -> Vec<&'a Node> {
+        rows.iter().flatten().collect()
+    }
+
+    fn walk_lambda(&mut self, params: &'a [String], body: &'a Node) -> Vec<&'a Node> {
+        vec![body]
+    }
+
+    /// What a node comes to, from the outputs of the children it walked.
+    fn visit_node(
+        &mut self,
+        node: &'a Node,
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error>;
+
+    fn visit_operand(&mut self, node: &'a Node, value: f64) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, Vec::new())
+    }
+
+    fn visit_variable(
+        &mut self,
+        node: &'a Node,
+        name: &'a str,
+        span: Span,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, Vec::new())
+    }
+
+    fn visit_date(
+        &mut self,
+        node: &'a Node,
+        value: &'a DateTime,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, Vec::new())
+    }
+
+    fn visit_binary(
+        &mut self,
+        node: &'a Node,
+        operation: BinaryOperator,
+        lhs: &'a Node,
+        rhs: &'a Node,
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_negation(
+        &mut self,
+        node: &'a Node,
+        operand: &'a Node,
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_function(
+        &mut self,
+        node: &'a Node,
+        name: &'a str,
+        args: &'a [Node],
+        span: Span,
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_list(
+        &mut self,
+        node: &'a Node,
+        items: &'a [Node],
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_matrix(
+        &mut self,
+        node: &'a Node,
+        rows: &'a [Vec<Node>],
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+
+    fn visit_lambda(
+        &mut self,
+        node: &'a Node,
+        params: &'a [String],
+        body: &'a Node,
+        outputs: Vec<Self::Output>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_node(node, outputs)
+    }
+}
+
+/// Rewrites a tree from the leaves up: each method is given its node's children already folded,
+/// and by default builds the same kind of node from them.
+pub trait Folder {
+    fn fold_operand(&mut self, value: f64) -> Node {
+        Node::Operand { value }
+    }
+
+    fn fold_variable(&mut self, name: String, span: Span) -> Node {
+        Node::Variable { name, span }
+    }
+
+    fn fold_date(&mut self, value: DateTime) -> Node {
+        Node::Date { value }
+    }
+
+    fn fold_binary(&mut self, operation: BinaryOperator, lhs: Node, rhs: Node) -> Node {
+        Node::binary(operation, lhs, rhs)
+    }
+
+    fn fold_negation(&mut self, operand: Node) -> Node {
+        Node::negation(operand)
+    }
+
+    fn fold_function(&mut self, name: String, args: Vec<Node>, span: Span) -> Node {
+        Node::Function { name, args, span }
+    }
+
+    fn fold_list(&mut self, items: Vec<Node>, span: Span) -> Node {
+        Node::List { items, span }
+    }
+
+    fn fold_matrix(&mut self, rows: Vec<Vec<Node>>, span: Span) -> Node {
+        Node::Matrix { rows, span }
+    }
+
+    fn fold_lambda(&mut self, params: Vec<String>, body: Node, span: Span) -> Node {
+        Node::Lambda {
+            params,
+            body: Box::new(body),
+            span,
+        }
+    }
+}
+
+/// A step of [`visit`]. A node is entered, its children are visited, then it is visited itself.
+enum Step<'a> {
+    Enter(&'a Node, Option<(&'a Node, usize)>),
+    Exit(&'a Node, usize),
+}
+
+/// Visits every node `visitor` walks to under `node`, with a stack rather than by recursion so
+/// that however deeply the tree nests it cannot overflow. Stops at the first error.
+pub fn visit<'a, V: Visitor<'a> + ?Sized>(
+    visitor: &mut V,
+    node: &'a Node,
+) -> Result<V::Output, V::Error> {
+    let mut steps = vec![Step::Enter(node, None)];
+    let mut outputs: Vec<V::Output> = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(node, parent) => {
+                visitor.enter(node, parent);
+                let children = match node {
+                    Node::BinaryExpr {
+                        operation,
+                        lhs,
+                        rhs,
+                    } => visitor.walk_binary(*operation, lhs, rhs),
+                    Node::Negation { operand } => visitor.walk_negation(operand),
+                    Node::Function { name, args, .. } => visitor.walk_function(name, args),
+                    Node::List { items, .. } => visitor.walk_list(items),
+                    Node::Matrix { rows, .. } => visitor.walk_matrix(rows),
+                    Node::Lambda { params, body, .. } => visitor.walk_lambda(params, body),
+                    Node::Operand { .. } | Node::Variable { .. } | Node::Date { .. } => Vec::new(),
+                };
+                steps.push(Step::Exit(node, children.len()));
+                for (i, child) in children.into_iter().enumerate().rev() {
+                    steps.push(Step::Enter(child, Some((node, i))));
+                }
+            }
+            Step::Exit(node, count) => {
+                let children = outputs.split_off(outputs.len() - count);
+                let output = match node {
+                    Node::Operand { value } => visitor.visit_operand(node, *value),
+                    Node::Variable { name, span } => visitor.visit_variable(node, name, *span),
+                    Node::Date { value } => visitor.visit_date(node, value),
+                    Node::BinaryExpr {
+                        operation,
+                        lhs,
+                        rhs,
+                    } => visitor.visit_binary(node, *operation, lhs, rhs, children),
+                    Node::Negation { operand } => visitor.visit_negation(node, operand, children),
+                    Node::Function { name, args, span } => {
+                        visitor.visit_function(node, name, args, *span, children)
+                    }
+                    Node::List { items, .. } => visitor.visit_list(node, items, children),
+                    Node::Matrix { rows, .. } => visitor.visit_matrix(node, rows, children),
+                    Node::Lambda { params, body, .. } => {
+                        visitor.visit_lambda(node, params, body, children)
+                    }
+                }?;
+                visitor.leave(node);
+                outputs.push(output);
+            }
+        }
+    }
+    Ok(outputs.pop().unwrap())
+}
+
+/// A node of [`fold`] whose children have been taken out to be folded first.
+enum Shell {
+    Binary(BinaryOperator),
+    Negation,
+    Function(String, Span),
+    List(Span),
+    Matrix(Vec<usize>, Span),
+    Lambda(Vec<String>, Span),
+}
+
+enum FoldStep {
+    Enter(Node),
+    Exit(Shell, usize),
+}
+
+/// Rewrites `node` with `folder`, with a stack rather than by recursion.
+pub fn fold<F: Folder + ?Sized>(folder: &mut F, node: Node) -> Node {
+    use std::mem::take;
+
+    let mut steps = vec![FoldStep::Enter(node)];
+    let mut folded: Vec<Node> = Vec::new();
+    while let Some(step) = steps.pop() {
+        let mut node = match step {
+            FoldStep::Enter(node) => node,
+            FoldStep::Exit(shell, count) => {
+                let mut children = folded.split_off(folded.len() - count).into_iter();
+                folded.push(match shell {
+                    Shell::Binary(operation) => {
+                        let lhs = children.next().unwrap();
+                        folder.fold_binary(operation, lhs, children.next().unwrap())
+                    }
+                    Shell::Negation => folder.fold_negation(children.next().unwrap()),
+                    Shell::Function(name, span) => {
+                        folder.fold_function(name, children.collect(), span)
+                    }
+                    Shell::List(span) => folder.fold_list(children.collect(), span),
+                    Shell::Matrix(widths, span) => {
+                        let rows = widths
+                            .iter()
+                            .map(|width| children.by_ref().take(*width).collect())
+                            .collect();
+                        folder.fold_matrix(rows, span)
+                    }
+                    Shell::Lambda(params, span) => {
+                        folder.fold_lambda(params, children.next().unwrap(), span)
+                    }
+                });
+                continue;
+            }
+        };
+        let (shell, children) = match &mut node {
+            Node::Operand { value } => {
+                folded.push(folder.fold_operand(*value));
+                continue;
+            }
+            Node::Variable { name, span } => {
+                folded.push(folder.fold_variable(take(name), *span));
+                continue;
+            }
+            Node::Date { value } => {
+                folded.push(folder.fold_date(*value));
+                continue;
+            }
+            Node::BinaryExpr { operation, .. } => {
+                let operation = *operation;
+                let (lhs, rhs) = node.into_operands();
+                (Shell::Binary(operation), vec![lhs, rhs])
+            }
+            Node::Negation { operand } => {
+                let operand = std::mem::replace(&mut **operand, Node::Operand { value: 0.0 });
+                (Shell::Negation, vec![operand])
+            }
+            Node::Function { name, args, span } => (Shell::Function(take(name), *span), take(args)),
+            Node::List { items, span } => (Shell::List(*span), take(items)),
+            Node::Matrix { rows, span } => {
+                let rows = take(rows);
+                let widths = rows.iter().map(Vec::len).collect();
+                (
+                    Shell::Matrix(widths, *span),
+                    rows.into_iter().flatten().collect(),
+                )
+            }
+            Node::Lambda { params, body, span } => {
+                let body = std::mem::replace(&mut **body, Node::Operand { value: 0.0 });
+                (Shell::Lambda(take(params), *span), vec![body])
+            }
+        };
+        steps.push(FoldStep::Exit(shell, children.len()));
+        steps.extend(children.into_iter().rev().map(FoldStep::Enter));
+    }
+    folded.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_expression;
+    use pretty_assertions::assert_eq;
+    use std::convert::Infallible;
+
+    /// The names in an expression, leaving out lambda bodies.
+    struct Names(Vec<String>);
+
+    impl<'a> Visitor<'a> for Names {
+        type Output = ();
+        type Error = Infallible;
+
+        fn walk_lambda(&mut self, _params: &'a [String], _body: &'a Node) -> Vec<&'a Node> {
+            vec![]
+        }
+
+        fn visit_node(&mut self, _node: &'a Node, _outputs: Vec<()>) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn visit_variable(
+            &mut self,
+            _node: &'a Node,
+            name: &'a str,
+            _span: Span,
+        ) -> Result<(), Infallible> {
+            self.0.push(name.to_string());
+            Ok(())
+        }
+    }
+
+    /// How deeply operations nest.
+    struct Depth;
+
+    impl<'a> Visitor<'a> for Depth {
+        type Output = usize;
+        type Error = Infallible;
+
+        fn visit_node(
+            &mut self,
+            _node: &'a Node,
+            outputs: Vec<usize>,
+        ) -> Result<usize, Infallible> {
+            Ok(outputs.into_iter().max().map_or(0, |depth| depth + 1))
+        }
+    }
+
+    /// Puts numbers in place of variables.
+    struct Substitute<'a>(&'a [(&'a str, f64)]);
+
+    impl Folder for Substitute<'_> {
+        fn fold_variable(&mut self, name: String, span: Span) -> Node {
+            match self.0.iter().find(|(known, _)| *known == name) {
+                Some((_, value)) => Node::Operand { value: *value },
+                None => Node::Variable { name, span },
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let node = parse_expression("x * sin(y) + map({a, 2}, a -> a * z) + [b; 1]").unwrap();
+        let mut names = Names(Vec::new());
+        visit(&mut names, &node).unwrap();
+        assert_eq!(names.0, vec!["x", "y", "a", "b"]);
+        assert_eq!(visit(&mut Depth, &node), Ok(5));
+        assert_eq!(visit(&mut Depth, &parse_expression("7").unwrap()), Ok(0));
+    }
+
+    #[test]
+    fn test_enter_and_leave() {
+        struct Path(Vec<String>);
+
+        impl<'a> Visitor<'a> for Path {
+            type Output = ();
+            type Error = String;
+
+            fn enter(&mut self, node: &'a Node, parent: Option<(&'a Node, usize)>) {
+                let index = parent.map_or("root".to_string(), |(_, index)| index.to_string());
+                self.0.push(format!("enter {} ({})", node, index));
+            }
+
+            fn leave(&mut self, node: &'a Node) {
+                self.0.push(format!("leave {}", node));
+            }
+
+            fn visit_node(&mut self, node: &'a Node, _outputs: Vec<()>) -> Result<(), String> {
+                match node {
+                    Node::Variable { name, .. } if name == "oops" => Err(name.clone()),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let mut path = Path(Vec::new());
+        visit(&mut path, &parse_expression("1 + x").unwrap()).unwrap();
+        assert_eq!(
+            path.0,
+            vec![
+                "enter 1 + x (root)",
+                "enter 1 (0)",
+                "leave 1",
+                "enter x (1)",
+                "leave x",
+                "leave 1 + x",
+            ]
+        );
+        let mut path = Path(Vec::new());
+        assert_eq!(
+            visit(&mut path, &parse_expression("oops * 2").unwrap()),
+            Err("oops".to_string())
+        );
+        assert_eq!(path.0, vec!["enter oops*2 (root)", "enter oops (0)"]);
+    }
+
+    #[test]
+    fn test_fold() {
+        let node = parse_expression("x * max(y, [x, 1; 2, z]) + (t -> t + x)").unwrap();
+        let folded = fold(&mut Substitute(&[("x", 2.0), ("z", 3.0)]), node);
+        assert_eq!(folded.to_string(), "2*max(y, [2, 1; 2, 3]) + (t -> t + 2)");
+
+        struct Identity;
+        impl Folder for Identity {}
+        let node = parse_expression("{1, a} + today - 3 days").unwrap();
+        assert_eq!(fold(&mut Identity, node.clone()), node);
+    }
+
+    #[test]
+    fn test_deep_trees() {
+        let input = format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000));
+        let node = parse_expression(&format!("{} + 1", vec![input; 2].join(" * "))).unwrap();
+        assert_eq!(visit(&mut Depth, &node), Ok(2));
+        let sum = parse_expression(&vec!["x"; 100_000].join(" + ")).unwrap();
+        assert_eq!(visit(&mut Depth, &sum), Ok(99_999));
+        let folded = fold(&mut Substitute(&[("x", 1.0)]), sum);
+        assert_eq!(visit(&mut Names(Vec::new()), &folded), Ok(()));
+    }
+}
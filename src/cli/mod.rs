@@ -0,0 +1,245 @@
+use calculator::{
+    ast::format::{DateFormat, DurationFormat, OutputFormat},
+    datetime::TimeMode,
+};
+
+/// How many days old exchange rates may be before they are refused, unless `--max-rates-age` says otherwise.
+pub const DEFAULT_MAX_RATES_AGE: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrintMode {
+    #[default]
+    All,
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    #[default]
+    Text,
+    Latex,
+    MathMl,
+}
+
+/// How `--shunting-yard` prints the steps of the conversion to postfix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShuntingFormat {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Options {
+    pub use_rpn: bool,
+    pub echo: bool,
+    pub file: Option<String>,
+    pub print: PrintMode,
+    pub rates: Option<String>,
+    pub max_rates_age: Option<i64>,
+    pub time_mode: TimeMode,
+    pub format: OutputFormat,
+    pub output: Output,
+    pub show_tree: bool,
+    pub dot: bool,
+    pub spans: bool,
+    pub subtotals: bool,
+    pub trace: bool,
+    pub shunting_yard: Option<ShuntingFormat>,
+    pub simplify: bool,
+    pub csv: Option<String>,
+    pub expr: Option<String>,
+    pub out: Option<String>,
+    pub skip_errors: bool,
+    pub sheet: Option<String>,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rpn" => options.use_rpn = true,
+            "--echo" => options.echo = true,
+            "--show-tree" => options.show_tree = true,
+            "--dot" => options.dot = true,
+            "--spans" => options.spans = true,
+            "--subtotals" => options.subtotals = true,
+            "--trace" => options.trace = true,
+            "--simplify" => options.simplify = true,
+            "--skip-errors" => options.skip_errors = true,
+            "-f" | "--file" => match args.next() {
+                Some(file) => options.file = Some(file),
+                None => return Err(format!("{} expects a file name", arg)),
+            },
+            "--csv" => match args.next() {
+                Some(file) => options.csv = Some(file),
+                None => return Err("--csv expects a file name".to_string()),
+            },
+            "--sheet" => match args.next() {
+                Some(file) => options.sheet = Some(file),
+                None => return Err("--sheet expects a file name".to_string()),
+            },
+            "--expr" => match args.next() {
+                Some(expr) => options.expr = Some(expr),
+                None => return Err("--expr expects an expression".to_string()),
+            },
+            "--out" => match args.next() {
+                Some(column) => options.out = Some(column),
+                None => return Err("--out expects a column name".to_string()),
+            },
+            "--print" => match args.next().as_deref() {
+                Some("all") => options.print = PrintMode::All,
+                Some("last") => options.print = PrintMode::Last,
+                _ => return Err("--print expects one of: all, last".to_string()),
+            },
+            "--rates" => match args.next() {
+                Some(file) => options.rates = Some(file),
+                None => return Err("--rates expects a file name".to_string()),
+            },
+            "--max-rates-age" => match args.next().and_then(|days| days.parse().ok()) {
+                Some(days) => options.max_rates_age = Some(days),
+                None => return Err("--max-rates-age expects a number of days".to_string()),
+            },
+            "--output" => match args.next().as_deref() {
+                Some("text") => options.output = Output::Text,
+                Some("latex") => options.output = Output::Latex,
+                Some("mathml") => options.output = Output::MathMl,
+                _ => return Err("--output expects one of: text, latex, mathml".to_string()),
+            },
+            "--shunting-yard" => match args.next().as_deref() {
+                Some("table") => options.shunting_yard = Some(ShuntingFormat::Table),
+                Some("json") => options.shunting_yard = Some(ShuntingFormat::Json),
+                _ => return Err("--shunting-yard expects one of: table, json".to_string()),
+            },
+            "--utc" => options.time_mode = TimeMode::Utc,
+            "--date-format" => match args.next().as_deref() {
+                Some("iso") => options.format.dates = DateFormat::Iso,
+                Some("long") => options.format.dates = DateFormat::Long,
+                _ => return Err("--date-format expects one of: iso, long".to_string()),
+            },
+            "--duration-format" => match args.next().as_deref() {
+                Some("decimal") => options.format.durations = DurationFormat::Decimal,
+                Some("compound") => options.format.durations = DurationFormat::Compound,
+                _ => {
+                    return Err("--duration-format expects one of: decimal, compound".to_string());
+                }
+            },
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if options.csv.is_some() && options.expr.is_none() {
+        return Err("--csv needs an --expr to compute for each row".to_string());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calculator::utils::test::tokens;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_no_arguments() {
+        assert_eq!(parse_args(tokens(&[])), Ok(Options::default()));
+    }
+
+    #[test]
+    fn test_script_file() {
+        assert_eq!(
+            parse_args(tokens(&["-f", "model.calc", "--print", "last"])),
+            Ok(Options {
+                use_rpn: false,
+                echo: false,
+                file: Some("model.calc".to_string()),
+                print: PrintMode::Last,
+                ..Options::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_file_name() {
+        assert_eq!(
+            parse_args(tokens(&["-f"])),
+            Err("-f expects a file name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        assert_eq!(
+            parse_args(tokens(&["--rates", "rates.json", "--max-rates-age", "30"])),
+            Ok(Options {
+                rates: Some("rates.json".to_string()),
+                max_rates_age: Some(30),
+                ..Options::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_time_options() {
+        assert_eq!(
+            parse_args(tokens(&["--utc", "--date-format", "long"])),
+            Ok(Options {
+                time_mode: TimeMode::Utc,
+                format: OutputFormat {
+                    dates: DateFormat::Long,
+                    ..OutputFormat::default()
+                },
+                ..Options::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            parse_args(tokens(&[
+                "--csv",
+                "data.csv",
+                "--expr",
+                "price * qty",
+                "--out",
+                "total",
+                "--skip-errors",
+            ])),
+            Ok(Options {
+                csv: Some("data.csv".to_string()),
+                expr: Some("price * qty".to_string()),
+                out: Some("total".to_string()),
+                skip_errors: true,
+                ..Options::default()
+            })
+        );
+        assert_eq!(
+            parse_args(tokens(&["--sheet", "budget.sheet"])),
+            Ok(Options {
+                sheet: Some("budget.sheet".to_string()),
+                ..Options::default()
+            })
+        );
+        assert_eq!(
+            parse_args(tokens(&["--csv", "data.csv"])),
+            Err("--csv needs an --expr to compute for each row".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shunting_yard() {
+        assert_eq!(
+            parse_args(tokens(&["--shunting-yard", "json"])),
+            Ok(Options {
+                shunting_yard: Some(ShuntingFormat::Json),
+                ..Options::default()
+            })
+        );
+        assert_eq!(
+            parse_args(tokens(&["--shunting-yard"])),
+            Err("--shunting-yard expects one of: table, json".to_string())
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+
+use crate::{
+    ast::{derivative::contains, environment::Environment, parse_expression, value::Value},
+    utils::{error::CalcError, input::tokenize},
+};
+
+/// A CSV file read as text: a header row naming the columns and the rows under it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// An error in one row, numbered as a spreadsheet would with the header as row 1.
+/// The span, if any, points into the expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub row: usize,
+    pub error: CalcError,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.error.span {
+            Some(span) => write!(
+                f,
+                "row {}, column {}: {}",
+                self.row,
+                span.start + 1,
+                self.error.message
+            ),
+            None => write!(f, "row {}: {}", self.row, self.error.message),
+        }
+    }
+}
+
+/// Reads CSV text, where fields may be quoted with `"` to hold commas, newlines or `""` for a quote.
+/// Every row must have as many fields as the header.
+pub fn parse(source: &str) -> Result<Table, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = source.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(ch),
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(ch),
+        }
+    }
+    if quoted {
+        return Err(format!(
+            "Row {} has a quote that is never closed",
+            records.len() + 1
+        ));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // a blank line has one empty field
+    records.retain(|record| record != &[""]);
+
+    let mut records = records.into_iter();
+    let headers: Vec<String> = match records.next() {
+        Some(headers) => headers
+            .iter()
+            .map(|header| header.trim().to_string())
+            .collect(),
+        None => return Err("The CSV file has no header row".to_string()),
+    };
+    let rows: Vec<Vec<String>> = records.collect();
+    if let Some((i, row)) = rows
+        .iter()
+        .enumerate()
+        .find(|(_, row)| row.len() != headers.len())
+    {
+        return Err(format!(
+            "Row {} has {} fields, but the header has {}",
+            i + 2,
+            row.len(),
+            headers.len()
+        ));
+    }
+    Ok(Table { headers, rows })
+}
+
+/// Writes rows as CSV, quoting the fields that need it.
+pub fn write(rows: &[Vec<String>]) -> String {
+    let mut csv = String::new();
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|field| match field.contains([',', '"', '\n', '\r']) {
+                true => format!("\"{}\"", field.replace('"', "\"\"")),
+                false => field.clone(),
+            })
+            .collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Evaluates `input` once for each row of `table`, with each column's header bound to the
+/// number in that row. A column the expression uses must hold a number in every row; others may
+/// hold anything.
+pub fn compute(
+    table: &Table,
+    input: &str,
+    env: &Environment,
+) -> Result<Vec<Result<Value, RowError>>, CalcError> {
+    let expr = parse_expression(input)?;
+    let tokens = tokenize(input)?;
+    let used: Vec<bool> = table
+        .headers
+        .iter()
+        .map(|header| contains(&expr, header))
+        .collect();
+
+    let mut env = env.clone();
+    Ok(table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let error = |error: CalcError| RowError { row: i + 2, error };
+            for ((header, cell), used) in table.headers.iter().zip(row).zip(&used) {
+                match cell.trim().parse::<f64>() {
+                    Ok(value) => env.set(header.as_str(), Value::Number(value)),
+                    Err(_) if *used => {
+                        let span = tokens.iter().find(|token| token.text == *header);
+                        return Err(error(CalcError {
+                            message: format!("{} is \"{}\", which is not a number", header, cell),
+                            span: span.map(|token| token.span),
+                            limit: None,
+                        }));
+                    }
+                    Err(_) => {}
+                }
+            }
+            expr.calculate(&env).map_err(error)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::span::Span;
+    use pretty_assertions::assert_eq;
+
+    fn strings(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|field| field.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let source = "name,price, qty\r\n\"Widget, large\",2.5,4\n\n\"Say \"\"hi\"\"\",1,\"2\"\n";
+        assert_eq!(
+            parse(source),
+            Ok(Table {
+                headers: strings(&["name", "price", "qty"]),
+                rows: vec![
+                    strings(&["Widget, large", "2.5", "4"]),
+                    strings(&["Say \"hi\"", "1", "2"]),
+                ],
+            })
+        );
+        assert_eq!(
+            parse("a,b\n1,2,3"),
+            Err("Row 2 has 3 fields, but the header has 2".to_string())
+        );
+        assert_eq!(
+            parse("a\n\"1\n"),
+            Err("Row 2 has a quote that is never closed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let rows = vec![
+            strings(&["name", "total"]),
+            strings(&["Widget, \"large\"", "10"]),
+        ];
+        let written = write(&rows);
+        assert_eq!(written, "name,total\n\"Widget, \"\"large\"\"\",10\n");
+        let table = parse(&written).unwrap();
+        assert_eq!(table.rows[0], rows[1]);
+    }
+
+    #[test]
+    fn test_compute() {
+        let table = parse("name,price,qty,tax\nA,2.5,4,20\nB,1,x,0\nC,3,2,120").unwrap();
+        let results = compute(
+            &table,
+            "price * qty * (1 + percentile({0, 1}, tax))",
+            &Environment::new(),
+        )
+        .unwrap();
+        assert_eq!(results[0], Ok(Value::Number(12.0)));
+        assert_eq!(
+            results[1],
+            Err(RowError {
+                row: 3,
+                error: CalcError::at("qty is \"x\", which is not a number", Span::new(8, 11)),
+            })
+        );
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "row 3, column 9: qty is \"x\", which is not a number"
+        );
+        assert_eq!(
+            results[2],
+            Err(RowError {
+                row: 4,
+                error: CalcError::at(
+                    "percentile must be between 0 and 100, found 120",
+                    Span::new(19, 42)
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        let table = parse("a\n1").unwrap();
+        assert_eq!(
+            compute(&table, "a +", &Environment::new()),
+            Err(CalcError::at("Not enough values on stack", Span::new(2, 3)))
+        );
+    }
+}
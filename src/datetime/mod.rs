@@ -0,0 +1,285 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::utils::calendar::{self, civil_from_days, days_from_civil, days_in_month};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Whether date and time values without an explicit `Z` are wall-clock times or UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeMode {
+    #[default]
+    Naive,
+    Utc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    /// Seconds since 1970-01-01T00:00:00.
+    pub seconds: f64,
+    /// Plain dates print without a time of day until arithmetic gives them one.
+    pub has_time: bool,
+    pub utc: bool,
+}
+
+impl DateTime {
+    /// Parses `YYYY-MM-DD`, optionally followed by `THH:MM`, seconds and a `Z` for UTC.
+    pub fn parse(text: &str) -> Option<DateTime> {
+        let (text, utc) = match text.strip_suffix('Z') {
+            Some(text) => (text, true),
+            None => (text, false),
+        };
+        let (date, time) = match text.split_once('T') {
+            Some((date, time)) => (date, Some(time)),
+            None => (text, None),
+        };
+        let days = calendar::parse_date(date)?;
+        let time_of_day = match time {
+            Some(time) => parse_time(time)?,
+            None => 0.0,
+        };
+        Some(DateTime {
+            seconds: days as f64 * SECONDS_PER_DAY + time_of_day,
+            has_time: time.is_some(),
+            utc,
+        })
+    }
+
+    pub fn today(mode: TimeMode) -> DateTime {
+        DateTime {
+            seconds: calendar::today() as f64 * SECONDS_PER_DAY,
+            has_time: false,
+            utc: mode == TimeMode::Utc,
+        }
+    }
+
+    pub fn now(mode: TimeMode) -> DateTime {
+        let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as f64,
+            Err(error) => -(error.duration().as_secs() as f64),
+        };
+        DateTime {
+            seconds,
+            has_time: true,
+            utc: mode == TimeMode::Utc,
+        }
+    }
+
+    /// Literals without a `Z` are taken as UTC when running in UTC mode.
+    pub fn in_mode(self, mode: TimeMode) -> DateTime {
+        DateTime {
+            utc: self.utc || mode == TimeMode::Utc,
+            ..self
+        }
+    }
+
+    pub fn days(&self) -> i64 {
+        (self.seconds / SECONDS_PER_DAY).floor() as i64
+    }
+
+    pub fn time_of_day(&self) -> f64 {
+        self.seconds - self.days() as f64 * SECONDS_PER_DAY
+    }
+
+    pub fn add_seconds(self, seconds: f64) -> DateTime {
+        let whole_days = (seconds / SECONDS_PER_DAY).fract() == 0.0;
+        DateTime {
+            seconds: self.seconds + seconds,
+            has_time: self.has_time || !whole_days,
+            ..self
+        }
+    }
+
+    /// Moves by whole calendar months, clamping to the end of shorter months, so
+    /// `2026-01-31 + 1 month` is `2026-02-28`.
+    pub fn add_months(self, months: i64) -> DateTime {
+        let (year, month, day) = civil_from_days(self.days());
+        let total = year * 12 + i64::from(month) - 1 + months;
+        let year = total.div_euclid(12);
+        let month = total.rem_euclid(12) as u32 + 1;
+        let day = day.min(days_in_month(year, month));
+        DateTime {
+            seconds: days_from_civil(year, month, day) as f64 * SECONDS_PER_DAY
+                + self.time_of_day(),
+            ..self
+        }
+    }
+
+    pub fn weekday(&self) -> &'static str {
+        const WEEKDAYS: [&str; 7] = [
+            "Thursday",
+            "Friday",
+            "Saturday",
+            "Sunday",
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+        ];
+        WEEKDAYS[self.days().rem_euclid(7) as usize]
+    }
+
+    /// Formats as `Sunday 18 October 2026`, followed by the time if there is one.
+    pub fn to_long_string(self) -> String {
+        const MONTHS: [&str; 12] = [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ];
+        let (year, month, day) = civil_from_days(self.days());
+        let date = format!(
+            "{} {} {} {}",
+            self.weekday(),
+            day,
+            MONTHS[month as usize - 1],
+            year
+        );
+        match (self.has_time, self.utc) {
+            (false, _) => date,
+            (true, false) => format!("{} {}", date, format_time(self.time_of_day())),
+            (true, true) => format!("{} {} UTC", date, format_time(self.time_of_day())),
+        }
+    }
+}
+
+fn parse_time(text: &str) -> Option<f64> {
+    let mut parts = text.splitn(3, ':');
+    let hours: u32 = parts.next().filter(|p| p.len() == 2)?.parse().ok()?;
+    let minutes: u32 = parts.next().filter(|p| p.len() == 2)?.parse().ok()?;
+    let seconds: f64 = match parts.next() {
+        Some(p) if p.len() >= 2 && p.chars().all(|c| c.is_ascii_digit() || c == '.') => {
+            p.parse().ok()?
+        }
+        Some(_) => return None,
+        None => 0.0,
+    };
+    if hours > 23 || minutes > 59 || seconds >= 60.0 {
+        return None;
+    }
+    Some(f64::from(hours) * 3600.0 + f64::from(minutes) * 60.0 + seconds)
+}
+
+fn format_time(seconds: f64) -> String {
+    let whole = seconds.floor();
+    let (hours, minutes, secs) = (
+        whole as u32 / 3600,
+        whole as u32 / 60 % 60,
+        whole as u32 % 60,
+    );
+    let fraction = seconds - whole;
+    if fraction > 0.0 {
+        let fraction = format!("{:.3}", fraction);
+        format!(
+            "{:02}:{:02}:{:02}{}",
+            hours,
+            minutes,
+            secs,
+            fraction.trim_start_matches('0').trim_end_matches('0')
+        )
+    } else {
+        format!("{:02}:{:02}:{:02}", hours, minutes, secs)
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", calendar::format_date(self.days()))?;
+        if self.has_time {
+            write!(f, "T{}", format_time(self.time_of_day()))?;
+        }
+        if self.utc {
+            write!(f, "Z")?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a number of seconds into days, hours, minutes and seconds, e.g. `13h 20min`.
+pub fn format_duration(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return format!("{} s", seconds);
+    }
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let mut remaining = seconds.abs();
+    let mut parts = Vec::new();
+    for (unit, size) in [("d", 86400.0), ("h", 3600.0), ("min", 60.0)] {
+        let count = (remaining / size).floor();
+        if count > 0.0 {
+            parts.push(format!("{}{}", count, unit));
+            remaining -= count * size;
+        }
+    }
+    // rounding keeps float noise like 59.99999 s from showing up
+    let remaining = (remaining * 1000.0).round() / 1000.0;
+    if remaining > 0.0 || parts.is_empty() {
+        parts.push(format!("{}s", remaining));
+    }
+    format!("{}{}", sign, parts.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn date(text: &str) -> DateTime {
+        DateTime::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(date("2026-10-18").to_string(), "2026-10-18");
+        assert_eq!(date("2026-10-18T14:30").to_string(), "2026-10-18T14:30:00");
+        assert_eq!(
+            date("2026-10-18T14:30:05.25Z").to_string(),
+            "2026-10-18T14:30:05.25Z"
+        );
+        assert_eq!(DateTime::parse("2026-10-18T25:00"), None);
+        assert_eq!(DateTime::parse("2026-10-18T"), None);
+    }
+
+    #[test]
+    fn test_add_days() {
+        let later = date("2026-10-18").add_seconds(90.0 * 86400.0);
+        assert_eq!(later.to_string(), "2027-01-16");
+    }
+
+    #[test]
+    fn test_add_hours_gives_a_time() {
+        let later = date("2026-10-18").add_seconds(3.5 * 3600.0);
+        assert_eq!(later.to_string(), "2026-10-18T03:30:00");
+    }
+
+    #[test]
+    fn test_add_months_clamps() {
+        assert_eq!(date("2026-01-31").add_months(1).to_string(), "2026-02-28");
+        assert_eq!(date("2024-02-29").add_months(12).to_string(), "2025-02-28");
+        assert_eq!(date("2026-03-15").add_months(-3).to_string(), "2025-12-15");
+    }
+
+    #[test]
+    fn test_long_format() {
+        assert_eq!(
+            date("2026-10-18T09:05Z").to_long_string(),
+            "Sunday 18 October 2026 09:05:00 UTC"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(48000.0), "13h 20min");
+        assert_eq!(format_duration(90061.5), "1d 1h 1min 1.5s");
+        assert_eq!(format_duration(-60.0), "-1min");
+        assert_eq!(format_duration(0.0), "0s");
+    }
+}
@@ -0,0 +1,10 @@
+pub mod ast;
+pub mod csv;
+pub mod datetime;
+pub mod render;
+pub mod rpn;
+pub mod script;
+pub mod sheet;
+pub mod units;
+pub mod utils;
+pub mod vm;
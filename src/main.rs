@@ -1,19 +1,261 @@
-mod ast;
-mod rpn;
-mod utils;
+use std::{fs, path::Path, process};
+
+use calculator::{
+    ast::{
+        self,
+        ast::Statement,
+        environment::Environment,
+        format::describe,
+        simplify::simplify,
+        trace::{self, Trace},
+        value::Value,
+    },
+    csv,
+    render::{self, Annotations, dot, latex, mathml, tree},
+    rpn, script,
+    sheet::Sheet,
+    units::currency::CurrencyRates,
+    utils,
+};
+
+use crate::cli::{DEFAULT_MAX_RATES_AGE, Options, Output, PrintMode, ShuntingFormat};
+
+mod cli;
 
 fn main() {
-    let use_rpn = false;
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(2);
+        }
+    };
+
+    let mut env = Environment::new();
+    env.set_time_mode(options.time_mode);
+    if let Some(rates) = &options.rates {
+        let max_age = options.max_rates_age.unwrap_or(DEFAULT_MAX_RATES_AGE);
+        match CurrencyRates::load(Path::new(rates), Some(max_age)) {
+            Ok(rates) => env.set_rates(rates),
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(file) = &options.sheet {
+        run_sheet(file, &options, env);
+        return;
+    }
+    if let Some(file) = &options.csv {
+        run_csv(file, &options, &env);
+        return;
+    }
+    if let Some(file) = &options.file {
+        run_script(file, &options, env);
+        return;
+    }
+
     let input = utils::input::get_input();
-    if use_rpn {
-        match rpn::calculate(input) {
+    if let Some(format) = options.shunting_yard {
+        let mut trace = Vec::new();
+        let result = rpn::to_postfix_traced(&input, &mut trace);
+        match format {
+            ShuntingFormat::Table => print!("{}", rpn::parse::format_shunting_table(&trace)),
+            ShuntingFormat::Json => println!("{}", rpn::parse::shunting_json(&trace)),
+        }
+        if let Err(error) = result {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+        return;
+    }
+    if options.use_rpn {
+        if options.echo
+            && let Ok(postfix) = rpn::to_postfix(&input)
+        {
+            println!("{}", postfix.join(" "));
+        }
+        let result = match options.trace {
+            true => {
+                let mut trace = Vec::new();
+                let result = rpn::calculate_traced(input, &mut trace);
+                print!("{}", rpn::calculate::format_stack_table(&trace));
+                result
+            }
+            false => rpn::calculate(input),
+        };
+        match result {
             Ok(result) => println!("{}", result),
             Err(error) => println!("Error: {}", error),
         }
     } else {
-        match ast::calculate(input) {
-            Ok(result) => println!("{}", result),
+        let (expr, spans) = match ast::parse_spanned_expression(&input) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                println!("Error: {}", error);
+                return;
+            }
+        };
+        if options.echo {
+            println!("{}", expr);
+        }
+        if options.simplify {
+            println!("{}", simplify(&expr));
+            return;
+        }
+        let too_deep = expr.depth() > render::MAX_DEPTH;
+        if too_deep && options.subtotals {
+            eprintln!(
+                "Note: too deep for subtotals, over {} levels",
+                render::MAX_DEPTH
+            );
+        }
+        let annotations = Annotations {
+            spans: options.spans.then_some(&spans),
+            subtotals: (options.subtotals && !too_deep).then_some(&env),
+        };
+        if options.dot {
+            print!("{}", dot::render(&expr, annotations));
+            return;
+        }
+        if options.show_tree {
+            match too_deep {
+                true => eprintln!("Note: too deep to draw, over {} levels", render::MAX_DEPTH),
+                false => print!("{}", tree::render(&expr, annotations)),
+            }
+        }
+        let traced = options.trace && expr.size() <= trace::MAX_NODES;
+        if options.trace && !traced {
+            eprintln!("Note: too large to trace, over {} nodes", trace::MAX_NODES);
+        }
+        let result = match traced {
+            true => {
+                let mut trace = Trace::new();
+                let result = expr.calculate_traced(&env, &mut trace);
+                for line in trace.rewrites(&expr) {
+                    println!("{}", line);
+                }
+                result
+            }
+            false => expr.calculate(&env),
+        };
+        let statement = Statement::Expression(expr);
+        match result {
+            Ok(result) => println!("{}", render(&statement, &result, &env, &options)),
+            // a formula can still be typeset when it cannot be evaluated
+            Err(error) if options.output != Output::Text => {
+                eprintln!("Error: {}", error);
+                println!("{}", render_unevaluated(&statement, options.output));
+            }
             Err(error) => println!("Error: {}", error),
         }
     }
 }
+
+fn render(statement: &Statement, result: &Value, env: &Environment, options: &Options) -> String {
+    match options.output {
+        Output::Text => describe(result, env, &options.format),
+        Output::Latex => latex::render(statement, Some(result)),
+        Output::MathMl => mathml::render(statement, Some(result)),
+    }
+}
+
+fn render_unevaluated(statement: &Statement, output: Output) -> String {
+    match output {
+        Output::Text => statement.to_string(),
+        Output::Latex => latex::render(statement, None),
+        Output::MathMl => mathml::render(statement, None),
+    }
+}
+
+fn run_script(file: &str, options: &Options, mut env: Environment) {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Error: could not read {}: {}", file, error);
+            process::exit(1);
+        }
+    };
+
+    let mut results = Vec::new();
+    let result = script::run(&source, &mut env, |statement, result| match options.print {
+        PrintMode::All => results.push((statement.clone(), result)),
+        PrintMode::Last => results = vec![(statement.clone(), result)],
+    });
+    for (statement, result) in &results {
+        if options.echo {
+            println!("{}", statement);
+        }
+        println!("{}", render(statement, result, &env, options));
+    }
+    if let Err(error) = result {
+        eprintln!("{}", script::format_error(file, &source, &error));
+        process::exit(1);
+    }
+}
+
+/// Appends a column computed from each row of a CSV file and prints the file again. Rows that
+/// cannot be computed are reported, and either stop the output or with `--skip-errors` are left out.
+fn run_csv(file: &str, options: &Options, env: &Environment) {
+    let table = match fs::read_to_string(file) {
+        Ok(source) => csv::parse(&source),
+        Err(error) => Err(format!("could not read {}: {}", file, error)),
+    };
+    let table = table.unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    });
+    let input = options.expr.as_deref().unwrap_or_default();
+    let results = csv::compute(&table, input, env).unwrap_or_else(|error| {
+        eprintln!("Error: --expr: {}", error);
+        process::exit(1);
+    });
+
+    let mut headers = table.headers.clone();
+    headers.push(options.out.clone().unwrap_or_else(|| input.to_string()));
+    let mut rows = vec![headers];
+    let mut failed = false;
+    for (row, result) in table.rows.iter().zip(results) {
+        match result {
+            Ok(value) => {
+                let mut row = row.clone();
+                row.push(describe(&value, env, &options.format));
+                rows.push(row);
+            }
+            Err(error) => {
+                eprintln!("{}: {}", file, error);
+                failed = true;
+            }
+        }
+    }
+    if failed && !options.skip_errors {
+        process::exit(1);
+    }
+    print!("{}", csv::write(&rows));
+}
+
+/// Loads a spreadsheet and prints the value of every cell that is not empty.
+fn run_sheet(file: &str, options: &Options, env: Environment) {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Error: could not read {}: {}", file, error);
+            process::exit(1);
+        }
+    };
+    let sheet = match Sheet::load(&source, env.clone()) {
+        Ok(sheet) => sheet,
+        Err(error) => {
+            eprintln!("{}", script::format_error(file, &source, &error));
+            process::exit(1);
+        }
+    };
+    for (cell, value) in sheet.cells() {
+        match value {
+            Ok(value) => println!("{} = {}", cell, describe(value, &env, &options.format)),
+            Err(error) => println!("{} = Error: {}", cell, error),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{
+    ast::ast::Node,
+    render::{Annotations, children},
+};
+
+/// A step of writing the digraph: a node and everything below it, or the edge to the node
+/// whose subtree was written last.
+enum Step<'a> {
+    Node(&'a Node, Annotations<'a>),
+    Edge(usize),
+}
+
+/// Writes the expression tree as a Graphviz DOT digraph.
+pub fn render(node: &Node, annotations: Annotations) -> String {
+    let mut dot = String::from("digraph ast {\n    node [shape=box];\n");
+    let mut steps = vec![Step::Node(node, annotations)];
+    // the ids of the nodes written whose edge from above is still to come
+    let mut ids = Vec::new();
+    let mut next_id = 0;
+    while let Some(step) = steps.pop() {
+        let (node, annotations) = match step {
+            Step::Node(node, annotations) => (node, annotations),
+            Step::Edge(parent) => {
+                let child = ids.pop().unwrap();
+                dot.push_str(&format!("    n{} -> n{};\n", parent, child));
+                continue;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        ids.push(id);
+        let label: Vec<String> = annotations
+            .label(node)
+            .iter()
+            .map(|part| escape(part))
+            .collect();
+        dot.push_str(&format!("    n{} [label=\"{}\"];\n", id, label.join("\\n")));
+
+        for (i, child) in children(node).into_iter().enumerate().rev() {
+            steps.push(Step::Edge(id));
+            steps.push(Step::Node(child, annotations.child(i)));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast::parse::infix_to_spanned_ast, utils::input::tokenize};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_dot() {
+        let (node, spans) = infix_to_spanned_ast(tokenize("1 + x").unwrap()).unwrap();
+        let annotations = Annotations {
+            spans: Some(&spans),
+            subtotals: None,
+        };
+        assert_eq!(
+            render(&node, annotations),
+            r#"digraph ast {
+    node [shape=box];
+    n0 [label="+\n[0..5]"];
+    n1 [label="1\n[0..1]"];
+    n0 -> n1;
+    n2 [label="x\n[4..5]"];
+    n0 -> n2;
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    ast::{
+        ast::{Node, Statement},
+        display::{Part, separated, write_parts},
+        equation::{SolutionSet, Solutions},
+        value::Value,
+    },
+    render::{is_square_root, needs_brackets},
+    utils::operators::BinaryOperator,
+};
+
+/// Renders a statement as LaTeX, followed by `= result` when a result is given.
+pub fn render(statement: &Statement, result: Option<&Value>) -> String {
+    let mut latex = match statement {
+        Statement::Assignment { name, expr } => {
+            format!("{} = {}", identifier(name), expression(expr))
+        }
+        Statement::Expression(expr) => expression(expr),
+    };
+    let expr = match statement {
+        Statement::Assignment { expr, .. } | Statement::Expression(expr) => expr,
+    };
+    // a bare number is its own result
+    if let Some(result) = result.filter(|_| !matches!(expr, Node::Operand { .. })) {
+        // an equation is not equal to its solutions, they follow from it
+        latex.push_str(match result {
+            Value::Solutions(_) => " \\implies ",
+            _ => " = ",
+        });
+        latex.push_str(&value(result));
+    }
+    latex
+}
+
+pub fn expression(node: &Node) -> String {
+    let mut latex = String::new();
+    write_parts(&mut latex, node, parts).unwrap();
+    latex
+}
+
+fn parts(node: &Node) -> Vec<Part<'_>> {
+    match node {
+        Node::Operand { value } => vec![Part::text(value.to_string())],
+        Node::Variable { name, .. } => vec![Part::text(identifier(name))],
+        Node::Date { value } => vec![Part::text(format!("\\text{{{}}}", value))],
+        Node::Negation { operand } => {
+            let mut parts = vec![Part::text("-")];
+            parts.extend(child(operand, BinaryOperator::Subtraction, true));
+            parts
+        }
+        Node::BinaryExpr {
+            operation: BinaryOperator::Index,
+            lhs,
+            rhs,
+        } if is_square_root(rhs) => {
+            vec![Part::text("\\sqrt{"), Part::Node(lhs), Part::text("}")]
+        }
+        Node::BinaryExpr {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let (before, between, after) = match operation {
+                BinaryOperator::Addition => ("", " + ", ""),
+                BinaryOperator::Subtraction => ("", " - ", ""),
+                BinaryOperator::Multiplication => ("", " \\cdot ", ""),
+                BinaryOperator::Division => ("\\frac{", "}{", "}"),
+                BinaryOperator::Index => ("", "^{", "}"),
+                BinaryOperator::Conversion => ("", " \\rightarrow ", ""),
+                BinaryOperator::Range => ("", " \\ldots ", ""),
+                BinaryOperator::Equation => ("", " = ", ""),
+            };
+            let mut parts = vec![Part::text(before)];
+            parts.extend(child(lhs, *operation, false));
+            parts.push(Part::text(between));
+            parts.extend(child(rhs, *operation, true));
+            parts.push(Part::text(after));
+            parts
+        }
+        Node::Function { name, args, .. } => function(name, args),
+        Node::List { items, .. } => {
+            let mut parts = vec![Part::text("\\left\\{")];
+            parts.extend(separated(items, ", "));
+            parts.push(Part::text("\\right\\}"));
+            parts
+        }
+        Node::Matrix { rows, .. } => {
+            let mut parts = vec![Part::text("\\begin{bmatrix}")];
+            for (i, row) in rows.iter().enumerate() {
+                if i > 0 {
+                    parts.push(Part::text(" \\\\ "));
+                }
+                parts.extend(separated(row, " & "));
+            }
+            parts.push(Part::text("\\end{bmatrix}"));
+            parts
+        }
+        Node::Lambda { params, body, .. } => vec![Part::text(lambda(params)), Part::Node(body)],
+    }
+}
+
+fn matrix(rows: Vec<Vec<String>>) -> String {
+    let rows: Vec<String> = rows.iter().map(|row| row.join(" & ")).collect();
+    format!("\\begin{{bmatrix}}{}\\end{{bmatrix}}", rows.join(" \\\\ "))
+}
+
+fn function<'a>(name: &str, args: &'a [Node]) -> Vec<Part<'a>> {
+    match (name, args) {
+        ("sqrt", [arg]) => vec![Part::text("\\sqrt{"), Part::Node(arg), Part::text("}")],
+        ("abs", [arg]) => vec![
+            Part::text("\\left|"),
+            Part::Node(arg),
+            Part::text("\\right|"),
+        ],
+        ("diff", [expr, Node::Variable { name: by, .. }]) => vec![
+            Part::text(format!("\\frac{{d}}{{d{}}}\\left(", identifier(by))),
+            Part::Node(expr),
+            Part::text("\\right)"),
+        ],
+        _ => {
+            let operator = match name {
+                "asin" | "acos" | "atan" => format!("\\arc{}", &name[1..]),
+                "sin" | "cos" | "tan" | "sinh" | "cosh" | "tanh" | "exp" | "ln" | "log" => {
+                    format!("\\{}", name)
+                }
+                _ => format!("\\operatorname{{{}}}", name.replace('_', "\\_")),
+            };
+            let mut parts = vec![Part::text(format!("{}\\left(", operator))];
+            parts.extend(separated(args, ", "));
+            parts.push(Part::text("\\right)"));
+            parts
+        }
+    }
+}
+
+fn child(node: &Node, parent: BinaryOperator, is_rhs: bool) -> Vec<Part<'_>> {
+    match needs_brackets(node, parent, is_rhs) {
+        true => vec![
+            Part::text("\\left("),
+            Part::Node(node),
+            Part::text("\\right)"),
+        ],
+        false => vec![Part::Node(node)],
+    }
+}
+
+/// Single letters are set as maths italics, longer names upright.
+fn identifier(name: &str) -> String {
+    if name.chars().count() == 1 {
+        name.to_string()
+    } else {
+        format!("\\mathrm{{{}}}", name.replace('_', "\\_"))
+    }
+}
+
+/// The parameters of a function and the arrow to its body.
+fn lambda(params: &[String]) -> String {
+    let params: Vec<String> = params.iter().map(|param| identifier(param)).collect();
+    match &params[..] {
+        [param] => format!("{} \\mapsto ", param),
+        params => format!("\\left({}\\right) \\mapsto ", params.join(", ")),
+    }
+}
+
+fn value(value: &Value) -> String {
+    match value {
+        Value::Closure(closure) => lambda(&closure.params) + &expression(&closure.body),
+        Value::Number(number) => number.to_string(),
+        Value::Expression(expr) => expression(expr),
+        Value::Matrix(value) => matrix(
+            (0..value.rows())
+                .map(|row| {
+                    (0..value.columns())
+                        .map(|column| value.get(row, column).to_string())
+                        .collect()
+                })
+                .collect(),
+        ),
+        Value::List(items) => {
+            let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+            format!("\\left\\{{{}\\right\\}}", items.join(", "))
+        }
+        Value::Solutions(SolutionSet {
+            unknowns,
+            solutions: Solutions::Values(values),
+        }) => values
+            .iter()
+            .flat_map(|values| {
+                unknowns
+                    .iter()
+                    .zip(values)
+                    .map(|(unknown, value)| format!("{} = {}", identifier(unknown), value))
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => format!("\\text{{{}}}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_expression;
+    use pretty_assertions::assert_eq;
+
+    fn latex(input: &str) -> String {
+        expression(&parse_expression(input).unwrap())
+    }
+
+    #[test]
+    fn test_fraction_needs_no_brackets() {
+        assert_eq!(latex("(1 + 2) / (3 - x)"), "\\frac{1 + 2}{3 - x}");
+        assert_eq!(latex("a * (b / c)"), "a \\cdot \\frac{b}{c}");
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(latex("(1 + 2)^(x + 1)"), "\\left(1 + 2\\right)^{x + 1}");
+        assert_eq!(latex("2^3^2"), "2^{3^{2}}");
+    }
+
+    #[test]
+    fn test_square_root() {
+        assert_eq!(latex("(x + 1)^0.5"), "\\sqrt{x + 1}");
+        assert_eq!(latex("x^(1/2)"), "\\sqrt{x}");
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(latex("(a + b) * c"), "\\left(a + b\\right) \\cdot c");
+        assert_eq!(
+            latex("rate_2 - (b - c)"),
+            "\\mathrm{rate\\_2} - \\left(b - c\\right)"
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            latex("diff(-sin(x)^2, x)"),
+            "\\frac{d}{dx}\\left(-\\sin\\left(x\\right)^{2}\\right)"
+        );
+        assert_eq!(
+            latex("abs(asin(x))"),
+            "\\left|\\arcsin\\left(x\\right)\\right|"
+        );
+    }
+
+    #[test]
+    fn test_with_result() {
+        let statement = Statement::Assignment {
+            name: "total".to_string(),
+            expr: parse_expression("4 + 5").unwrap(),
+        };
+        assert_eq!(
+            render(&statement, Some(&Value::Number(9.0))),
+            "\\mathrm{total} = 4 + 5 = 9"
+        );
+    }
+
+    #[test]
+    fn test_equation_with_solutions() {
+        let statement = Statement::Expression(parse_expression("x^2 = 4").unwrap());
+        let solutions = Value::Solutions(SolutionSet {
+            unknowns: vec!["x".to_string()],
+            solutions: Solutions::Values(vec![vec![-2.0], vec![2.0]]),
+        });
+        assert_eq!(
+            render(&statement, Some(&solutions)),
+            "x^{2} = 4 \\implies x = -2, x = 2"
+        );
+    }
+}
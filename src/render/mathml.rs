@@ -0,0 +1,271 @@
+use crate::{
+    ast::{
+        ast::{Node, Statement},
+        display::{Part, separated, write_parts},
+        equation::{SolutionSet, Solutions},
+        value::Value,
+    },
+    render::{is_square_root, needs_brackets},
+    utils::operators::BinaryOperator,
+};
+
+/// Renders a statement as a presentation MathML `<math>` element, followed by `= result` when a result is given.
+pub fn render(statement: &Statement, result: Option<&Value>) -> String {
+    let mut mathml = match statement {
+        Statement::Assignment { name, expr } => {
+            format!("<mi>{}</mi><mo>=</mo>{}", escape(name), expression(expr))
+        }
+        Statement::Expression(expr) => expression(expr),
+    };
+    let expr = match statement {
+        Statement::Assignment { expr, .. } | Statement::Expression(expr) => expr,
+    };
+    // a bare number is its own result
+    if let Some(result) = result.filter(|_| !matches!(expr, Node::Operand { .. })) {
+        // an equation is not equal to its solutions, they follow from it
+        mathml.push_str(match result {
+            Value::Solutions(_) => "<mo>&#x21D2;</mo>",
+            _ => "<mo>=</mo>",
+        });
+        mathml.push_str(&value(result));
+    }
+    format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>{}</mrow></math>",
+        mathml
+    )
+}
+
+pub fn expression(node: &Node) -> String {
+    let mut mathml = String::new();
+    write_parts(&mut mathml, node, parts).unwrap();
+    mathml
+}
+
+fn parts(node: &Node) -> Vec<Part<'_>> {
+    match node {
+        Node::Operand { value } => vec![Part::text(format!("<mn>{}</mn>", value))],
+        Node::Variable { name, .. } => vec![Part::text(format!("<mi>{}</mi>", escape(name)))],
+        Node::Date { value } => vec![Part::text(format!("<mtext>{}</mtext>", value))],
+        Node::Negation { operand } => {
+            let mut parts = vec![Part::text("<mo>&#x2212;</mo>")];
+            parts.extend(child(operand, BinaryOperator::Subtraction, true));
+            parts
+        }
+        Node::BinaryExpr {
+            operation: BinaryOperator::Index,
+            lhs,
+            rhs,
+        } if is_square_root(rhs) => {
+            vec![
+                Part::text("<msqrt>"),
+                Part::Node(lhs),
+                Part::text("</msqrt>"),
+            ]
+        }
+        Node::BinaryExpr {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let (before, between, after) = match operation {
+                BinaryOperator::Addition => ("", "<mo>+</mo>", ""),
+                BinaryOperator::Subtraction => ("", "<mo>&#x2212;</mo>", ""),
+                BinaryOperator::Multiplication => ("", "<mo>&#x22C5;</mo>", ""),
+                BinaryOperator::Conversion => ("", "<mo>&#x2192;</mo>", ""),
+                BinaryOperator::Range => ("", "<mo>&#x2026;</mo>", ""),
+                BinaryOperator::Equation => ("", "<mo>=</mo>", ""),
+                BinaryOperator::Division => ("<mfrac><mrow>", "</mrow><mrow>", "</mrow></mfrac>"),
+                BinaryOperator::Index => ("<msup><mrow>", "</mrow><mrow>", "</mrow></msup>"),
+            };
+            let mut parts = vec![Part::text(before)];
+            parts.extend(child(lhs, *operation, false));
+            parts.push(Part::text(between));
+            parts.extend(child(rhs, *operation, true));
+            parts.push(Part::text(after));
+            parts
+        }
+        Node::Function { name, args, .. } => function(name, args),
+        Node::Matrix { rows, .. } => {
+            let mut parts = vec![Part::text("<mrow><mo>[</mo><mtable>")];
+            for row in rows {
+                parts.push(Part::text("<mtr>"));
+                for entry in row {
+                    parts.extend([Part::text("<mtd>"), Part::Node(entry), Part::text("</mtd>")]);
+                }
+                parts.push(Part::text("</mtr>"));
+            }
+            parts.push(Part::text("</mtable><mo>]</mo></mrow>"));
+            parts
+        }
+        Node::Lambda { params, body, .. } => vec![Part::text(lambda(params)), Part::Node(body)],
+        Node::List { items, .. } => {
+            let mut parts = vec![Part::text("<mrow><mo>{</mo>")];
+            parts.extend(separated(items, "<mo>,</mo>"));
+            parts.push(Part::text("<mo>}</mo></mrow>"));
+            parts
+        }
+    }
+}
+
+fn matrix(rows: Vec<Vec<String>>) -> String {
+    let rows: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let entries: Vec<String> = row
+                .iter()
+                .map(|entry| format!("<mtd>{}</mtd>", entry))
+                .collect();
+            format!("<mtr>{}</mtr>", entries.concat())
+        })
+        .collect();
+    format!(
+        "<mrow><mo>[</mo><mtable>{}</mtable><mo>]</mo></mrow>",
+        rows.concat()
+    )
+}
+
+fn function<'a>(name: &str, args: &'a [Node]) -> Vec<Part<'a>> {
+    match (name, args) {
+        ("sqrt", [arg]) => vec![
+            Part::text("<msqrt>"),
+            Part::Node(arg),
+            Part::text("</msqrt>"),
+        ],
+        ("abs", [arg]) => vec![
+            Part::text("<mo>|</mo>"),
+            Part::Node(arg),
+            Part::text("<mo>|</mo>"),
+        ],
+        ("diff", [expr, Node::Variable { name: by, .. }]) => vec![
+            Part::text(format!(
+                "<mfrac><mi>d</mi><mrow><mi>d</mi><mi>{}</mi></mrow></mfrac><mrow><mo>(</mo>",
+                escape(by)
+            )),
+            Part::Node(expr),
+            Part::text("<mo>)</mo></mrow>"),
+        ],
+        _ => {
+            let mut parts = vec![Part::text(format!(
+                "<mi>{}</mi><mo>&#x2061;</mo><mrow><mo>(</mo>",
+                escape(name)
+            ))];
+            parts.extend(separated(args, "<mo>,</mo>"));
+            parts.push(Part::text("<mo>)</mo></mrow>"));
+            parts
+        }
+    }
+}
+
+fn child(node: &Node, parent: BinaryOperator, is_rhs: bool) -> Vec<Part<'_>> {
+    match needs_brackets(node, parent, is_rhs) {
+        true => vec![
+            Part::text("<mo>(</mo>"),
+            Part::Node(node),
+            Part::text("<mo>)</mo>"),
+        ],
+        false => vec![Part::Node(node)],
+    }
+}
+
+/// The parameters of a function and the arrow to its body.
+fn lambda(params: &[String]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| format!("<mi>{}</mi>", escape(param)))
+        .collect();
+    let params = match &params[..] {
+        [param] => param.clone(),
+        params => format!("<mo>(</mo>{}<mo>)</mo>", params.join("<mo>,</mo>")),
+    };
+    format!("{}<mo>&#x21A6;</mo>", params)
+}
+
+fn value(value: &Value) -> String {
+    match value {
+        Value::Closure(closure) => lambda(&closure.params) + &expression(&closure.body),
+        Value::Number(number) => format!("<mn>{}</mn>", number),
+        Value::Expression(expr) => expression(expr),
+        Value::Matrix(value) => matrix(
+            (0..value.rows())
+                .map(|row| {
+                    (0..value.columns())
+                        .map(|column| format!("<mn>{}</mn>", value.get(row, column)))
+                        .collect()
+                })
+                .collect(),
+        ),
+        Value::List(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| format!("<mn>{}</mn>", item))
+                .collect();
+            format!(
+                "<mrow><mo>{{</mo>{}<mo>}}</mo></mrow>",
+                items.join("<mo>,</mo>")
+            )
+        }
+        Value::Solutions(SolutionSet {
+            unknowns,
+            solutions: Solutions::Values(values),
+        }) => values
+            .iter()
+            .flat_map(|values| {
+                unknowns.iter().zip(values).map(|(unknown, value)| {
+                    format!("<mi>{}</mi><mo>=</mo><mn>{}</mn>", escape(unknown), value)
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("<mo>,</mo>"),
+        _ => format!("<mtext>{}</mtext>", escape(&value.to_string())),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_expression;
+    use pretty_assertions::assert_eq;
+
+    fn mathml(input: &str) -> String {
+        expression(&parse_expression(input).unwrap())
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(
+            mathml("(1 + x) / 2"),
+            "<mfrac><mrow><mn>1</mn><mo>+</mo><mi>x</mi></mrow><mrow><mn>2</mn></mrow></mfrac>"
+        );
+    }
+
+    #[test]
+    fn test_power_and_brackets() {
+        assert_eq!(
+            mathml("(a - b)^2"),
+            "<msup><mrow><mo>(</mo><mi>a</mi><mo>&#x2212;</mo><mi>b</mi><mo>)</mo></mrow><mrow><mn>2</mn></mrow></msup>"
+        );
+    }
+
+    #[test]
+    fn test_function() {
+        assert_eq!(
+            mathml("-sin(x)"),
+            "<mo>&#x2212;</mo><mi>sin</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>x</mi><mo>)</mo></mrow>"
+        );
+    }
+
+    #[test]
+    fn test_document_with_result() {
+        let statement = Statement::Expression(parse_expression("x^0.5").unwrap());
+        assert_eq!(
+            render(&statement, Some(&Value::Number(3.0))),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><msqrt><mi>x</mi></msqrt><mo>=</mo><mn>3</mn></mrow></math>"
+        );
+    }
+}
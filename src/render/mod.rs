@@ -0,0 +1,107 @@
+use crate::{
+    ast::{
+        ast::{Node, SpanTree},
+        display::{needs_parentheses, operator_info},
+        environment::Environment,
+    },
+    utils::operators::BinaryOperator,
+};
+
+pub mod dot;
+pub mod latex;
+pub mod mathml;
+pub mod tree;
+
+/// Trees deeper than this are not drawn as text or given subtotals, since every node would
+/// repeat the prefix or the evaluation of the ones below it.
+pub const MAX_DEPTH: usize = 1000;
+
+/// Extra detail to show on each node of a tree diagram.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Annotations<'a> {
+    /// Where each node came from in the input.
+    pub spans: Option<&'a SpanTree>,
+    /// Evaluate every subtree in this environment and show its value.
+    pub subtotals: Option<&'a Environment>,
+}
+
+impl<'a> Annotations<'a> {
+    /// The annotations for the `index`th child of the node these annotations are for.
+    fn child(&self, index: usize) -> Annotations<'a> {
+        Annotations {
+            spans: self.spans.and_then(|spans| spans.children.get(index)),
+            subtotals: self.subtotals,
+        }
+    }
+
+    /// The label for a node in a diagram, with its annotations as extra parts.
+    fn label(&self, node: &Node) -> Vec<String> {
+        let mut parts = vec![match node {
+            Node::Operand { value } => value.to_string(),
+            Node::Variable { name, .. } => name.clone(),
+            Node::Date { value } => value.to_string(),
+            Node::BinaryExpr { operation, .. } => operation.symbol().to_string(),
+            Node::Negation { .. } => "-".to_string(),
+            Node::Function { name, .. } => format!("{}()", name),
+            Node::List { .. } => "{}".to_string(),
+            Node::Matrix { rows, .. } => {
+                format!("[{}×{}]", rows.len(), rows.first().map_or(0, Vec::len))
+            }
+            Node::Lambda { params, .. } => format!("{} ->", params.join(", ")),
+        }];
+        if let Some(spans) = self.spans {
+            parts.push(format!("[{}..{}]", spans.span.start, spans.span.end));
+        }
+        if let Some(env) = self.subtotals {
+            parts.push(match node.calculate(env) {
+                Ok(value) => format!("= {}", value),
+                Err(error) => format!("! {}", error),
+            });
+        }
+        parts
+    }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+    match node {
+        Node::BinaryExpr { lhs, rhs, .. } => vec![lhs, rhs],
+        Node::Function { args, .. } | Node::List { items: args, .. } => args.iter().collect(),
+        Node::Matrix { rows, .. } => rows.iter().flatten().collect(),
+        Node::Negation { operand: body } | Node::Lambda { body, .. } => vec![body],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `child` needs brackets under `parent` when typeset, where fractions and exponents
+/// already group their contents and so need fewer brackets than plain infix.
+fn needs_brackets(child: &Node, parent: BinaryOperator, is_rhs: bool) -> bool {
+    match (parent, child) {
+        (BinaryOperator::Division, _) => false,
+        (BinaryOperator::Index, _) if is_rhs => false,
+        (BinaryOperator::Index, Node::BinaryExpr { .. }) => true,
+        (
+            _,
+            Node::BinaryExpr {
+                operation: BinaryOperator::Division,
+                ..
+            },
+        ) => false,
+        _ => needs_parentheses(child, operator_info(parent), is_rhs),
+    }
+}
+
+/// Whether an exponent is a half, so the power can be typeset as a square root.
+fn is_square_root(exponent: &Node) -> bool {
+    match exponent {
+        Node::Operand { value } => *value == 0.5,
+        Node::BinaryExpr {
+            operation: BinaryOperator::Division,
+            lhs,
+            rhs,
+        } => {
+            matches!(**lhs, Node::Operand { value } if value == 1.0)
+                && matches!(**rhs, Node::Operand { value } if value == 2.0)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    ast::ast::Node,
+    render::{Annotations, children},
+};
+
+/// Draws the expression as an indented tree, one node per line.
+pub fn render(node: &Node, annotations: Annotations) -> String {
+    let mut tree = String::new();
+    // each node still to draw, with the prefix of its line and of the lines below it
+    let mut pending = vec![(node, annotations, String::new(), String::new())];
+    while let Some((node, annotations, prefix, child_prefix)) = pending.pop() {
+        tree.push_str(&prefix);
+        tree.push_str(&annotations.label(node).join(" "));
+        tree.push('\n');
+
+        let children = children(node);
+        let count = children.len();
+        for (i, child) in children.into_iter().enumerate().rev() {
+            let (branch, continuation) = match i + 1 == count {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            pending.push((
+                child,
+                annotations.child(i),
+                format!("{}{}", child_prefix, branch),
+                format!("{}{}", child_prefix, continuation),
+            ));
+        }
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{environment::Environment, parse::infix_to_spanned_ast},
+        utils::input::tokenize,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tree() {
+        let (node, _) = infix_to_spanned_ast(tokenize("3 + 4 * (1 - 5)").unwrap()).unwrap();
+        assert_eq!(
+            render(&node, Annotations::default()),
+            "\
++
+├── 3
+└── *
+    ├── 4
+    └── -
+        ├── 1
+        └── 5
+"
+        );
+    }
+
+    #[test]
+    fn test_annotated_tree() {
+        let (node, spans) = infix_to_spanned_ast(tokenize("2 * (1 + 3)").unwrap()).unwrap();
+        let env = Environment::new();
+        let annotations = Annotations {
+            spans: Some(&spans),
+            subtotals: Some(&env),
+        };
+        assert_eq!(
+            render(&node, annotations),
+            "\
+* [0..11] = 8
+├── 2 [0..1] = 2
+└── + [4..11] = 4
+    ├── 1 [5..6] = 1
+    └── 3 [9..10] = 3
+"
+        );
+    }
+}
@@ -1,12 +1,57 @@
+use crate::{
+    ast::{functions, statistics},
+    utils::{operators::NEGATION, table::format_table},
+};
+
+/// What one token did to the stack, and the stack it left behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackStep {
+    pub token: String,
+    pub popped: Vec<f64>,
+    pub pushed: f64,
+    pub stack: Vec<f64>,
+}
+
 pub fn perform_calculations(input: Vec<String>) -> Result<f64, String> {
+    evaluate(input, None)
+}
+
+/// Like [`perform_calculations`], but records every push and pop in `trace`.
+pub fn perform_calculations_traced(
+    input: Vec<String>,
+    trace: &mut Vec<StackStep>,
+) -> Result<f64, String> {
+    evaluate(input, Some(trace))
+}
+
+fn evaluate(input: Vec<String>, mut trace: Option<&mut Vec<StackStep>>) -> Result<f64, String> {
     if input.is_empty() {
         return Err("Input is empty".to_string());
     }
 
     let mut stack: Vec<f64> = vec![];
     for token in input {
-        match token.as_str() {
-            "+" | "-" | "/" | "^" | "*" => {
+        let popped = match (token.as_str(), call(&token)) {
+            (_, Some((name, count))) => {
+                if stack.len() < count {
+                    return Err(format!("Not enough values on stack: {}", stack.len()));
+                }
+                let args = stack.split_off(stack.len() - count);
+                let result = match functions::builtin(name) {
+                    Some(builtin) => (builtin.apply)(args[0]),
+                    None => statistics::summarise(name, &args)?,
+                };
+                stack.push(result);
+                args
+            }
+            (NEGATION, None) => {
+                let Some(value) = stack.pop() else {
+                    return Err(format!("Not enough values on stack: {}", stack.len()));
+                };
+                stack.push(0.0 - value);
+                vec![value]
+            }
+            ("+" | "-" | "/" | "^" | "*", None) => {
                 if stack.len() < 2 {
                     return Err(format!("Not enough values on stack: {}", stack.len()));
                 }
@@ -23,14 +68,23 @@ pub fn perform_calculations(input: Vec<String>) -> Result<f64, String> {
                     _ => unreachable!(),
                 };
                 stack.push(result);
+                vec![left, right]
             }
             _ => {
                 let value = token
                     .parse()
-                    .map_err(|e| format!("Invalid number: {}", e))
-                    .unwrap();
+                    .map_err(|e| format!("Invalid number: {}", e))?;
                 stack.push(value);
+                Vec::new()
             }
+        };
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.push(StackStep {
+                token,
+                popped,
+                pushed: *stack.last().unwrap(),
+                stack: stack.clone(),
+            });
         }
     }
     if stack.len() != 1 {
@@ -39,6 +93,37 @@ pub fn perform_calculations(input: Vec<String>) -> Result<f64, String> {
     Ok(stack.pop().unwrap())
 }
 
+/// The function a postfix token calls and how many values it takes, as written by
+/// [`call_token`](crate::rpn::parse::call_token).
+fn call(token: &str) -> Option<(&str, usize)> {
+    match token.split_once('/') {
+        Some((name, count)) if statistics::is_statistic(name) => Some((name, count.parse().ok()?)),
+        _ => functions::builtin(token).map(|builtin| (builtin.name, 1)),
+    }
+}
+
+/// Formats a trace as a table with one row per token.
+pub fn format_stack_table(trace: &[StackStep]) -> String {
+    let join = |values: &[f64]| {
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let rows: Vec<Vec<String>> = trace
+        .iter()
+        .map(|step| {
+            let action = match step.popped.is_empty() {
+                true => format!("push {}", step.pushed),
+                false => format!("pop {}, push {}", join(&step.popped), step.pushed),
+            };
+            vec![step.token.clone(), action, join(&step.stack)]
+        })
+        .collect();
+    format_table(&["token", "action", "stack"], &rows)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,6 +176,56 @@ mod tests {
         assert_eq!(perform_calculations(tokens(&["0", "5", "-"])), Ok(-5.0));
     }
 
+    #[test]
+    fn test_negation() {
+        assert_eq!(
+            perform_calculations(tokens(&["2", "3", NEGATION, "*"])),
+            Ok(-6.0)
+        );
+        assert_eq!(
+            perform_calculations(tokens(&[NEGATION])),
+            Err("Not enough values on stack: 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_number() {
+        assert_eq!(
+            perform_calculations(tokens(&["2", "x", "+"])),
+            Err("Invalid number: invalid float literal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stack_table() {
+        let mut trace = Vec::new();
+        assert_eq!(
+            perform_calculations_traced(tokens(&["4", "2", "3", "+", "*"]), &mut trace),
+            Ok(20.0)
+        );
+        assert_eq!(
+            format_stack_table(&trace),
+            "token  action            stack\n\
+             4      push 4            4\n\
+             2      push 2            4 2\n\
+             3      push 3            4 2 3\n\
+             +      pop 2 3, push 5   4 5\n\
+             *      pop 4 5, push 20  20\n"
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(
+            perform_calculations(tokens(&["1", "4", "9", "mean/3", "sqrt"])),
+            Ok(f64::sqrt(14.0 / 3.0))
+        );
+        assert_eq!(
+            perform_calculations(tokens(&["1", "max/2"])),
+            Err("Not enough values on stack: 1".to_string())
+        );
+    }
+
     #[test]
     fn test_foo() {
         assert_eq!(
@@ -1,22 +1,39 @@
-use crate::utils;
+use crate::utils::{self, error::CalcError, limits::EvalLimits};
 
 pub mod calculate;
 pub mod parse;
 
-pub fn calculate(mut input: String) -> Result<f64, String> {
-    let infix_input = match utils::input::get_normalized_input(&mut input) {
-        Ok(input) => input,
-        Err(error) => return Err(error),
-    };
-    let postfix_input = match parse::infix_to_postfix(infix_input) {
-        Ok(input) => input,
-        Err(error) => return Err(error),
-    };
-    let result = match calculate::perform_calculations(postfix_input) {
-        Ok(result) => result,
-        Err(error) => return Err(error),
-    };
-    Ok(result)
+pub fn calculate(input: String) -> Result<f64, String> {
+    calculate::perform_calculations(to_postfix(&input)?)
+}
+
+/// Like [`calculate`], within the token and depth limits of `limits`, which are all that apply as
+/// the work of a postfix calculation grows only with its length.
+pub fn calculate_within(input: &str, limits: &EvalLimits) -> Result<f64, CalcError> {
+    let infix_input = utils::input::get_normalized_input_within(input, limits)?;
+    Ok(calculate::perform_calculations(parse::infix_to_postfix(
+        infix_input,
+    )?)?)
+}
+
+pub fn calculate_traced(
+    input: String,
+    trace: &mut Vec<calculate::StackStep>,
+) -> Result<f64, String> {
+    calculate::perform_calculations_traced(to_postfix(&input)?, trace)
+}
+
+pub fn to_postfix(input: &str) -> Result<Vec<String>, String> {
+    let infix_input = utils::input::get_normalized_input(input)?;
+    parse::infix_to_postfix(infix_input)
+}
+
+pub fn to_postfix_traced(
+    input: &str,
+    trace: &mut Vec<parse::ShuntingStep>,
+) -> Result<Vec<String>, String> {
+    let infix_input = utils::input::get_normalized_input(input)?;
+    parse::infix_to_postfix_traced(infix_input, trace)
 }
 
 #[cfg(test)]
@@ -39,6 +56,34 @@ mod tests {
         assert_eq!(calculate(input), Ok(expected));
     }
 
+    #[test]
+    fn test_negation() {
+        assert_eq!(calculate(String::from("2 * -3")), Ok(-6.0));
+        assert_eq!(calculate(String::from("4 / -2")), Ok(-2.0));
+        assert_eq!(calculate(String::from("1 - -1")), Ok(2.0));
+        assert_eq!(calculate(String::from("-2^2 + 2^-1")), Ok(-3.5));
+    }
+
+    #[test]
+    fn test_calculate_within() {
+        let limits = EvalLimits {
+            max_tokens: Some(10),
+            max_depth: Some(2),
+            ..EvalLimits::default()
+        };
+        assert_eq!(calculate_within("(1 + (2)) * 3", &limits), Ok(9.0));
+        assert_eq!(
+            calculate_within("(((1)))", &limits).unwrap_err().message,
+            "The expression nests deeper than the limit of 2"
+        );
+        assert_eq!(
+            calculate_within("1+1+1+1+1+1", &limits)
+                .unwrap_err()
+                .message,
+            "The input is longer than the limit of 10 tokens"
+        );
+    }
+
     proptest! {
         #[test]
         fn doesnt_crash_random_strings(s in "\\PC*") {
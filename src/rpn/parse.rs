@@ -1,19 +1,138 @@
-use crate::utils::operators::{Associativity, get_operator_info};
+use std::fmt;
+
+use serde_json::json;
+
+use crate::{
+    ast::{functions, statistics},
+    utils::{
+        operators::{Associativity, EXPONENT_MINUS, NEGATION, get_operator_info, is_prefix},
+        table::format_table,
+    },
+};
+
+/// What the shunting-yard algorithm did with a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// A number goes straight to the output queue.
+    Output(String),
+    PushOperator(String),
+    /// An operator moves from the stack to the output, because of precedence, a `)` or the end of input.
+    PopOperator(String),
+    /// A `(` on the stack is matched by `)` and both are dropped.
+    DiscardParenthesis,
+    /// A `,` ends one argument of a call and starts the next.
+    NextArgument,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Output(token) => write!(f, "add {} to output", token),
+            Action::PushOperator(token) => write!(f, "push {} to stack", token),
+            Action::PopOperator(token) => write!(f, "pop {} to output", token),
+            Action::DiscardParenthesis => write!(f, "discard ( )"),
+            Action::NextArgument => write!(f, "start next argument"),
+        }
+    }
+}
+
+/// One action, with the token being read (`None` once the input is exhausted)
+/// and the output queue and operator stack it left behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShuntingStep {
+    pub token: Option<String>,
+    pub action: Action,
+    pub output: Vec<String>,
+    pub operators: Vec<String>,
+}
+
+struct Shunting<'a> {
+    output: Vec<String>,
+    operators: Vec<String>,
+    /// How many arguments have been started inside each `(` on the operator stack.
+    arguments: Vec<usize>,
+    trace: Option<&'a mut Vec<ShuntingStep>>,
+}
+
+impl Shunting<'_> {
+    fn record(&mut self, token: Option<&str>, action: Action) {
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.push(ShuntingStep {
+                token: token.map(str::to_string),
+                action,
+                output: self.output.clone(),
+                operators: self.operators.clone(),
+            });
+        }
+    }
+
+    fn pop_operator(&mut self, token: Option<&str>) {
+        let operator = self.operators.pop().unwrap();
+        // a sign in an exponent is a plain negation once it has been given its place
+        let operator = match operator.as_str() {
+            EXPONENT_MINUS => NEGATION.to_string(),
+            _ => operator,
+        };
+        self.output.push(operator.clone());
+        self.record(token, Action::PopOperator(operator));
+    }
+}
 
 pub fn infix_to_postfix(input: Vec<String>) -> Result<Vec<String>, String> {
-    let mut output: Vec<String> = Vec::new();
-    let mut operators: Vec<String> = Vec::new();
+    shunt(input, None)
+}
+
+/// Like [`infix_to_postfix`], but records every action in `trace`.
+pub fn infix_to_postfix_traced(
+    input: Vec<String>,
+    trace: &mut Vec<ShuntingStep>,
+) -> Result<Vec<String>, String> {
+    shunt(input, Some(trace))
+}
+
+/// Whether `name` is a function the RPN engine can call: a builtin or a statistic of its arguments.
+fn is_function(name: &str) -> bool {
+    functions::builtin(name).is_some() || statistics::is_statistic(name)
+}
+
+/// The postfix token for a call to `name` with `count` arguments. A statistic takes any number,
+/// so it is written with its count as `mean/3`.
+pub fn call_token(name: &str, count: usize) -> String {
+    match statistics::is_statistic(name) {
+        true => format!("{}/{}", name, count),
+        false => name.to_string(),
+    }
+}
+
+fn shunt(input: Vec<String>, trace: Option<&mut Vec<ShuntingStep>>) -> Result<Vec<String>, String> {
+    let mut state = Shunting {
+        output: Vec::new(),
+        operators: Vec::new(),
+        arguments: Vec::new(),
+        trace,
+    };
 
+    let mut previous: Option<String> = None;
     for token in input.into_iter() {
+        let current = Some(token.as_str());
+        if let Some(function) = previous.as_deref().filter(|previous| is_function(previous))
+            && token != "("
+        {
+            return Err(format!("Expected ( after {}", function));
+        }
+        let after_open = previous.as_deref() == Some("(");
+        previous = Some(token.clone());
         if token.parse::<f64>().is_ok() {
-            output.push(token);
+            state.output.push(token.clone());
+            state.record(current, Action::Output(token.clone()));
             continue;
         }
 
         match token.as_str() {
-            "+" | "-" | "/" | "^" | "*" => {
-                loop {
-                    let o2 = operators.last();
+            "+" | "-" | "/" | "^" | "*" | NEGATION | EXPONENT_MINUS => {
+                // a sign has nothing before it to take
+                while !is_prefix(&token) {
+                    let o2 = state.operators.last();
                     if let Some(o2) = o2 {
                         if o2 == "(" {
                             break;
@@ -25,7 +144,7 @@ pub fn infix_to_postfix(input: Vec<String>) -> Result<Vec<String>, String> {
                             || (o2_config.precedence == o1_config.precedence
                                 && o1_config.associativity == Associativity::Left)
                         {
-                            output.push(operators.pop().unwrap());
+                            state.pop_operator(current);
                         } else {
                             break;
                         }
@@ -34,42 +153,117 @@ pub fn infix_to_postfix(input: Vec<String>) -> Result<Vec<String>, String> {
                     }
                 }
 
-                operators.push(token.clone())
+                state.operators.push(token.clone());
+                state.record(current, Action::PushOperator(token.clone()));
             }
 
-            "(" => operators.push(token.clone()),
+            name if is_function(name) => {
+                state.operators.push(token.clone());
+                state.record(current, Action::PushOperator(token.clone()));
+            }
+
+            "(" => {
+                state.operators.push(token.clone());
+                state.arguments.push(1);
+                state.record(current, Action::PushOperator(token.clone()));
+            }
+
+            "," => {
+                while let Some(o) = state.operators.last()
+                    && o != "("
+                {
+                    state.pop_operator(current);
+                }
+                match state.arguments.last_mut() {
+                    Some(count) => *count += 1,
+                    None => return Err("Found , outside a function call".to_string()),
+                }
+                state.record(current, Action::NextArgument);
+            }
 
             ")" => {
                 loop {
-                    let o = operators.last();
+                    let o = state.operators.last();
                     match o {
                         Some(o) => {
                             if o == "(" {
                                 break;
                             }
 
-                            output.push(operators.pop().unwrap())
+                            state.pop_operator(current);
                         }
                         None => return Err("Mismatched parentheses found!".to_string()),
                     }
                 }
 
-                let o = operators.pop().unwrap();
+                let o = state.operators.pop().unwrap();
                 if o != "(" {
                     return Err("Expected left parenthesis".to_string());
                 }
+                // `mean()` has no arguments rather than one empty one
+                let count = match state.arguments.pop().unwrap() {
+                    _ if after_open => 0,
+                    count => count,
+                };
+                state.record(current, Action::DiscardParenthesis);
+
+                match state.operators.last() {
+                    Some(name) if is_function(name) => {
+                        let name = state.operators.pop().unwrap();
+                        functions::check_arity(&name, count)?;
+                        state.output.push(call_token(&name, count));
+                        state.record(current, Action::PopOperator(call_token(&name, count)));
+                    }
+                    _ if count > 1 => return Err("Found , outside a function call".to_string()),
+                    _ => {}
+                }
             }
             _ => return Err(format!("Found unsupported token: {}", token)),
         }
     }
 
-    while let Some(o) = operators.pop() {
+    if let Some(function) = previous.filter(|previous| is_function(previous)) {
+        return Err(format!("Expected ( after {}", function));
+    }
+    while let Some(o) = state.operators.last() {
         match o.as_str() {
             "(" => return Err("Mismatched parentheses found!".to_string()),
-            _ => output.push(o),
+            _ => state.pop_operator(None),
         }
     }
-    Ok(output)
+    Ok(state.output)
+}
+
+/// Formats a trace like the worked example on Wikipedia, one row per action.
+pub fn format_shunting_table(trace: &[ShuntingStep]) -> String {
+    let rows: Vec<Vec<String>> = trace
+        .iter()
+        .map(|step| {
+            vec![
+                step.token.clone().unwrap_or_else(|| "end".to_string()),
+                step.action.to_string(),
+                step.output.join(" "),
+                step.operators.join(" "),
+            ]
+        })
+        .collect();
+    format_table(&["token", "action", "output", "operator stack"], &rows)
+}
+
+/// Exports a trace as a JSON array with one object per action.
+pub fn shunting_json(trace: &[ShuntingStep]) -> String {
+    let steps: Vec<serde_json::Value> = trace
+        .iter()
+        .map(|step| {
+            json!({
+                "token": step.token,
+                "action": step.action.to_string(),
+                "output": step.output,
+                "operators": step.operators,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&steps).unwrap()
 }
 
 #[cfg(test)]
@@ -137,4 +331,84 @@ mod tests {
             Ok(tokens(&["4", "5", "+", "2", "5", "*", "-"]))
         )
     }
+
+    #[test]
+    fn test_negation() {
+        let input = tokens(&["2", "*", NEGATION, "3", "^", EXPONENT_MINUS, "1", "*", "4"]);
+        assert_eq!(
+            infix_to_postfix(input),
+            Ok(tokens(&[
+                "2", "3", "1", NEGATION, "^", "4", "*", NEGATION, "*"
+            ]))
+        )
+    }
+
+    #[test]
+    fn test_function_calls() {
+        let input = tokens(&[
+            "2", "*", "max", "(", "1", ",", "sin", "(", "0", ")", ",", "3", "+", "4", ")",
+        ]);
+        assert_eq!(
+            infix_to_postfix(input),
+            Ok(tokens(&["2", "1", "0", "sin", "3", "4", "+", "max/3", "*"]))
+        );
+        assert_eq!(
+            infix_to_postfix(tokens(&["sin", "(", "1", ",", "2", ")"])),
+            Err("sin expects 1 argument, found 2".to_string())
+        );
+        assert_eq!(
+            infix_to_postfix(tokens(&["mean", "(", ")"])),
+            Err("mean expects at least 1 argument, found 0".to_string())
+        );
+        assert_eq!(
+            infix_to_postfix(tokens(&["(", "1", ",", "2", ")"])),
+            Err("Found , outside a function call".to_string())
+        );
+        assert_eq!(
+            infix_to_postfix(tokens(&["sin", "1"])),
+            Err("Expected ( after sin".to_string())
+        );
+    }
+
+    #[test]
+    fn test_shunting_table() {
+        let mut trace = Vec::new();
+        let input = tokens(&["2", "*", "(", "1", "-", "5", ")", "+", "3"]);
+        assert_eq!(
+            infix_to_postfix_traced(input, &mut trace),
+            Ok(tokens(&["2", "1", "5", "-", "*", "3", "+"]))
+        );
+        assert_eq!(
+            format_shunting_table(&trace),
+            "token  action           output         operator stack\n\
+             2      add 2 to output  2\n\
+             *      push * to stack  2              *\n\
+             (      push ( to stack  2              * (\n\
+             1      add 1 to output  2 1            * (\n\
+             -      push - to stack  2 1            * ( -\n\
+             5      add 5 to output  2 1 5          * ( -\n\
+             )      pop - to output  2 1 5 -        * (\n\
+             )      discard ( )      2 1 5 -        *\n\
+             +      pop * to output  2 1 5 - *\n\
+             +      push + to stack  2 1 5 - *      +\n\
+             3      add 3 to output  2 1 5 - * 3    +\n\
+             end    pop + to output  2 1 5 - * 3 +\n"
+        );
+    }
+
+    #[test]
+    fn test_shunting_json() {
+        let mut trace = Vec::new();
+        infix_to_postfix_traced(tokens(&["4", "+", "5"]), &mut trace).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&shunting_json(&trace)).unwrap();
+        assert_eq!(
+            json[3],
+            json!({
+                "token": null,
+                "action": "pop + to output",
+                "output": ["4", "5", "+"],
+                "operators": [],
+            })
+        );
+    }
 }
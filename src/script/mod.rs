@@ -0,0 +1,186 @@
+use crate::{
+    ast::{ast::Statement, environment::Environment, parse, value::Value},
+    utils::{
+        error::CalcError,
+        input::tokenize,
+        span::{Span, line_col},
+    },
+};
+
+/// A single statement of a script, with its position in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementSource {
+    pub text: String,
+    pub span: Span,
+}
+
+/// Splits a script into statements separated by newlines or `;`, dropping `#` comments and blank statements.
+/// A `;` inside square brackets separates the rows of a matrix instead.
+pub fn split_statements(source: &str) -> Vec<StatementSource> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut in_comment = false;
+    let mut depth = 0usize;
+
+    for (i, ch) in source.chars().enumerate() {
+        match ch {
+            '\n' | ';' if !(ch == ';' && (in_comment || depth > 0)) => {
+                push_statement(&mut statements, &mut current, start);
+                in_comment = false;
+                depth = 0;
+                start = i + 1;
+            }
+            '#' => in_comment = true,
+            _ if in_comment => {}
+            '[' => {
+                depth += 1;
+                current.push(ch);
+            }
+            ']' => {
+                depth = depth.saturating_sub(1);
+                current.push(ch);
+            }
+            _ => current.push(ch),
+        }
+    }
+    push_statement(&mut statements, &mut current, start);
+
+    statements
+}
+
+fn push_statement(statements: &mut Vec<StatementSource>, current: &mut String, start: usize) {
+    let text = std::mem::take(current);
+    let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return;
+    }
+    let start = start + leading;
+    statements.push(StatementSource {
+        text: trimmed.to_string(),
+        span: Span::new(start, start + trimmed.chars().count()),
+    });
+}
+
+/// Runs every statement in `source`, handing each parsed statement and its result to `on_result`.
+/// Stops at the first error, which always carries a span into `source`.
+pub fn run(
+    source: &str,
+    env: &mut Environment,
+    mut on_result: impl FnMut(&Statement, Value),
+) -> Result<(), CalcError> {
+    for statement in split_statements(source) {
+        let (parsed, result) = run_statement(&statement, env).map_err(|error| CalcError {
+            span: Some(match error.span {
+                Some(span) => span.offset(statement.span.start),
+                None => statement.span,
+            }),
+            ..error
+        })?;
+        on_result(&parsed, result);
+    }
+    Ok(())
+}
+
+fn run_statement(
+    statement: &StatementSource,
+    env: &mut Environment,
+) -> Result<(Statement, Value), CalcError> {
+    let tokens = tokenize(&statement.text)?;
+    let parsed = parse::parse_statement(tokens)?;
+    let result = parsed.execute(env)?;
+    Ok((parsed, result))
+}
+
+/// Formats an error as `file:line:column: message`.
+pub fn format_error(file_name: &str, source: &str, error: &CalcError) -> String {
+    match error.span {
+        Some(span) => {
+            let (line, col) = line_col(source, span.start);
+            format!("{}:{}:{}: {}", file_name, line, col, error.message)
+        }
+        None => format!("{}: {}", file_name, error.message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn run_all(source: &str) -> Result<Vec<Value>, CalcError> {
+        let mut results = Vec::new();
+        run(source, &mut Environment::new(), |_, result| {
+            results.push(result)
+        })?;
+        Ok(results)
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let source = "x = 1; y = 2 # set up\n\n# a comment; still a comment\n x + y";
+        assert_eq!(
+            split_statements(source),
+            vec![
+                StatementSource {
+                    text: "x = 1".to_string(),
+                    span: Span::new(0, 5),
+                },
+                StatementSource {
+                    text: "y = 2".to_string(),
+                    span: Span::new(7, 12),
+                },
+                StatementSource {
+                    text: "x + y".to_string(),
+                    span: Span::new(53, 58),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semicolons_in_matrices() {
+        let source = "m = [1, 2; 3, 4]; det(m)";
+        assert_eq!(run_all(source).unwrap()[1], Value::Number(-2.0));
+    }
+
+    #[test]
+    fn test_assignments_are_visible_to_later_statements() {
+        let source = "width = 3\nheight = width * 2\nwidth * height";
+        assert_eq!(
+            run_all(source),
+            Ok(vec![3.0.into(), 6.0.into(), 18.0.into()])
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let source = "x = 1\ny = x + $";
+        let error = run_all(source).unwrap_err();
+        assert_eq!(
+            format_error("model.calc", source, &error),
+            "model.calc:2:9: Found unsupported token: $"
+        );
+    }
+
+    #[test]
+    fn test_unknown_variable_location() {
+        let source = "x = 1\n  x * rate";
+        let error = run_all(source).unwrap_err();
+        assert_eq!(
+            format_error("model.calc", source, &error),
+            "model.calc:2:7: Unknown variable or unit: rate"
+        );
+    }
+
+    #[test]
+    fn test_error_without_span_points_at_statement() {
+        let source = "1\n  2 3";
+        let error = run_all(source).unwrap_err();
+        assert_eq!(
+            format_error("model.calc", source, &error),
+            "model.calc:2:3: Invalid expression: 2 values remain"
+        );
+    }
+}
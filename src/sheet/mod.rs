@@ -0,0 +1,507 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use crate::{
+    ast::{ast::Node, environment::Environment, parse::infix_to_ast, value::Value},
+    utils::{
+        error::CalcError,
+        input::{Token, tokenize},
+        span::Span,
+    },
+};
+
+/// A cell from `A1` to `Z99`, ordered by row and then column as a sheet is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cell {
+    row: u8,
+    column: u8,
+}
+
+impl FromStr for Cell {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Cell, String> {
+        let mut chars = name.chars();
+        let column = chars.next().filter(char::is_ascii_uppercase);
+        let row = chars
+            .as_str()
+            .parse::<u8>()
+            .ok()
+            .filter(|row| (1..=99).contains(row) && !chars.as_str().starts_with('0'));
+        match (column, row) {
+            (Some(column), Some(row)) => Ok(Cell {
+                row,
+                column: column as u8 - b'A',
+            }),
+            _ => Err(format!("{} is not a cell from A1 to Z99", name)),
+        }
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'A' + self.column) as char, self.row)
+    }
+}
+
+/// A cell's formula, parsed, with the cells it refers to.
+#[derive(Debug, Clone)]
+struct Formula {
+    source: String,
+    expr: Node,
+    references: BTreeSet<Cell>,
+}
+
+/// Cells holding expressions that may refer to other cells, as in `=A1*B2` or `sum(A1:A10)`.
+///
+/// Setting a cell marks it and every cell that depends on it as dirty, and
+/// [`Sheet::recalculate`] works out only those, each after the cells it refers to. An empty cell
+/// counts as zero.
+#[derive(Debug, Clone, Default)]
+pub struct Sheet {
+    formulas: BTreeMap<Cell, Formula>,
+    /// The cells that refer to each cell.
+    dependents: BTreeMap<Cell, BTreeSet<Cell>>,
+    values: BTreeMap<Cell, Result<Value, CalcError>>,
+    dirty: BTreeSet<Cell>,
+    env: Environment,
+}
+
+impl Sheet {
+    pub fn new() -> Sheet {
+        Sheet::default()
+    }
+
+    /// A sheet whose formulas can also use the variables and exchange rates of `env`.
+    pub fn with_env(env: Environment) -> Sheet {
+        Sheet {
+            env,
+            ..Sheet::default()
+        }
+    }
+
+    /// Sets `cell` to `source`, an expression with an optional leading `=`. A formula that cannot
+    /// be parsed or that would make the cell depend on itself is refused, leaving the sheet as it was.
+    pub fn set(&mut self, cell: Cell, source: &str) -> Result<(), CalcError> {
+        let text = source.trim();
+        let skipped = source.chars().take_while(|c| c.is_whitespace()).count()
+            + usize::from(text.starts_with('='));
+        let tokens =
+            cell_tokens(text.strip_prefix('=').unwrap_or(text)).map_err(|error| CalcError {
+                span: error.span.map(|span| span.offset(skipped)),
+                ..error
+            })?;
+        let references: BTreeSet<Cell> = tokens
+            .iter()
+            .filter_map(|token| token.text.parse().ok())
+            .collect();
+        let expr = infix_to_ast(tokens).map_err(|error| CalcError {
+            span: error.span.map(|span| span.offset(skipped)),
+            ..error
+        })?;
+        if let Some(path) = references
+            .iter()
+            .find_map(|reference| self.path(*reference, cell))
+        {
+            let path: Vec<String> = path.iter().map(Cell::to_string).collect();
+            return Err(CalcError::new(format!(
+                "{} cannot refer to itself, but this would go {} -> {}",
+                cell,
+                cell,
+                path.join(" -> ")
+            )));
+        }
+
+        self.remove(cell);
+        for reference in &references {
+            self.dependents.entry(*reference).or_default().insert(cell);
+        }
+        self.formulas.insert(
+            cell,
+            Formula {
+                source: text.to_string(),
+                expr,
+                references,
+            },
+        );
+        self.mark_dirty(cell);
+        Ok(())
+    }
+
+    /// Empties `cell`, so that cells referring to it see zero.
+    pub fn clear(&mut self, cell: Cell) {
+        self.remove(cell);
+        self.values.remove(&cell);
+        self.mark_dirty(cell);
+    }
+
+    /// The formula in `cell`, as it was set.
+    pub fn formula(&self, cell: Cell) -> Option<&str> {
+        self.formulas
+            .get(&cell)
+            .map(|formula| formula.source.as_str())
+    }
+
+    /// The value of `cell` as of the last [`Sheet::recalculate`], or `None` if it is empty.
+    pub fn value(&self, cell: Cell) -> Option<&Result<Value, CalcError>> {
+        self.values.get(&cell)
+    }
+
+    /// Works out every dirty cell after the cells it refers to, returning them in that order.
+    pub fn recalculate(&mut self) -> Vec<Cell> {
+        let mut order = Vec::new();
+        let mut done = BTreeSet::new();
+        let dirty = std::mem::take(&mut self.dirty);
+        for cell in &dirty {
+            self.order(*cell, &dirty, &mut done, &mut order);
+        }
+        for cell in &order {
+            let Some(formula) = self.formulas.get(cell) else {
+                continue;
+            };
+            let mut env = self.env.clone();
+            let mut value = Ok(());
+            for reference in &formula.references {
+                match self.values.get(reference) {
+                    Some(Ok(referenced)) => env.set(reference.to_string(), referenced.clone()),
+                    Some(Err(error)) => {
+                        value = Err(CalcError::new(format!(
+                            "{} has an error: {}",
+                            reference, error.message
+                        )));
+                        break;
+                    }
+                    None => env.set(reference.to_string(), Value::Number(0.0)),
+                }
+            }
+            let value = value.and_then(|_| formula.expr.calculate(&env));
+            self.values.insert(*cell, value);
+        }
+        order
+    }
+
+    /// Adds `cell` to `order` after the dirty cells it refers to.
+    fn order(
+        &self,
+        cell: Cell,
+        dirty: &BTreeSet<Cell>,
+        done: &mut BTreeSet<Cell>,
+        order: &mut Vec<Cell>,
+    ) {
+        if !done.insert(cell) {
+            return;
+        }
+        if let Some(formula) = self.formulas.get(&cell) {
+            for reference in formula.references.intersection(dirty) {
+                self.order(*reference, dirty, done, order);
+            }
+        }
+        order.push(cell);
+    }
+
+    /// A path of references from `from` to `to`, if `from` depends on `to`.
+    fn path(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        let mut reached_from = BTreeMap::from([(from, from)]);
+        let mut pending = vec![from];
+        while let Some(cell) = pending.pop() {
+            if cell == to {
+                let mut path = vec![to];
+                while path[0] != from {
+                    path.insert(0, reached_from[&path[0]]);
+                }
+                return Some(path);
+            }
+            let Some(formula) = self.formulas.get(&cell) else {
+                continue;
+            };
+            for reference in &formula.references {
+                if !reached_from.contains_key(reference) {
+                    reached_from.insert(*reference, cell);
+                    pending.push(*reference);
+                }
+            }
+        }
+        None
+    }
+
+    fn mark_dirty(&mut self, cell: Cell) {
+        let mut pending = vec![cell];
+        while let Some(cell) = pending.pop() {
+            if self.dirty.insert(cell)
+                && let Some(dependents) = self.dependents.get(&cell)
+            {
+                pending.extend(dependents);
+            }
+        }
+    }
+
+    /// Takes the formula out of `cell`, and `cell` out of the dependents of what it referred to.
+    fn remove(&mut self, cell: Cell) {
+        if let Some(formula) = self.formulas.remove(&cell) {
+            for reference in &formula.references {
+                if let Some(dependents) = self.dependents.get_mut(reference) {
+                    dependents.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Reads a sheet saved by [`Sheet::save`]: one `A1: formula` per line, with `#` comments and
+    /// blank lines skipped. Errors carry a span into `source`.
+    pub fn load(source: &str, env: Environment) -> Result<Sheet, CalcError> {
+        let mut sheet = Sheet::with_env(env);
+        let mut start = 0;
+        for line in source.split_inclusive('\n') {
+            let line_start = start;
+            start += line.chars().count();
+            let content = line.split('#').next().unwrap_or_default();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let leading = content.chars().take_while(|c| c.is_whitespace()).count();
+            let Some((name, formula)) = content.split_once(':') else {
+                let span = Span::new(leading, content.trim_end().chars().count());
+                return Err(CalcError::at(
+                    "Expected a cell and its formula, as in A1: =B1*2",
+                    span.offset(line_start),
+                ));
+            };
+            let name_span = Span::new(leading, name.trim_end().chars().count());
+            let cell: Cell = name
+                .trim()
+                .parse()
+                .map_err(|message: String| CalcError::at(message, name_span.offset(line_start)))?;
+            let formula_start = line_start + name.chars().count() + 1;
+            let formula_leading = formula.chars().take_while(|c| c.is_whitespace()).count();
+            sheet.set(cell, formula).map_err(|error| CalcError {
+                span: Some(match error.span {
+                    Some(span) => span.offset(formula_start),
+                    None => Span::new(
+                        formula_start + formula_leading,
+                        line_start + content.trim_end().chars().count(),
+                    ),
+                }),
+                ..error
+            })?;
+        }
+        sheet.recalculate();
+        Ok(sheet)
+    }
+
+    /// Writes every cell that is not empty as `A1: formula`, one per line, in reading order.
+    pub fn save(&self) -> String {
+        self.formulas
+            .iter()
+            .map(|(cell, formula)| format!("{}: {}\n", cell, formula.source))
+            .collect()
+    }
+
+    /// Every cell that is not empty with its value, in reading order.
+    pub fn cells(&self) -> impl Iterator<Item = (Cell, &Result<Value, CalcError>)> {
+        self.values.iter().map(|(cell, value)| (*cell, value))
+    }
+}
+
+/// Tokenizes a formula, writing each range like `A1:B2` out as a list of its cells.
+fn cell_tokens(formula: &str) -> Result<Vec<Token>, CalcError> {
+    let colons: Vec<usize> = formula
+        .chars()
+        .enumerate()
+        .filter(|(_, ch)| *ch == ':')
+        .map(|(i, _)| i)
+        .collect();
+    let mut input = tokenize(&formula.replace(':', " "))?.into_iter().peekable();
+    let mut tokens = Vec::new();
+    let mut colons = colons.into_iter().peekable();
+    while let Some(token) = input.next() {
+        let Some(colon) = colons.next_if(|colon| {
+            *colon >= token.span.end && input.peek().is_none_or(|next| *colon < next.span.start)
+        }) else {
+            tokens.push(token);
+            continue;
+        };
+        let (Ok(first), Some(Ok(last))) = (
+            token.text.parse::<Cell>(),
+            input.peek().map(|last| last.text.parse::<Cell>()),
+        ) else {
+            return Err(CalcError::at(
+                "A : must be between two cells, as in A1:A10",
+                Span::new(colon, colon + 1),
+            ));
+        };
+        let span = Span::new(token.span.start, input.next().unwrap().span.end);
+        tokens.push(Token::new("{", span));
+        for row in first.row.min(last.row)..=first.row.max(last.row) {
+            for column in first.column.min(last.column)..=first.column.max(last.column) {
+                if tokens.last().is_some_and(|last: &Token| last.text != "{") {
+                    tokens.push(Token::new(",", span));
+                }
+                tokens.push(Token::new(Cell { row, column }.to_string(), span));
+            }
+        }
+        tokens.push(Token::new("}", span));
+    }
+    // a colon before every token
+    if let Some(colon) = colons.next() {
+        return Err(CalcError::at(
+            "A : must be between two cells, as in A1:A10",
+            Span::new(colon, colon + 1),
+        ));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn cell(name: &str) -> Cell {
+        name.parse().unwrap()
+    }
+
+    fn value(sheet: &Sheet, name: &str) -> String {
+        match sheet.value(cell(name)) {
+            Some(Ok(value)) => value.to_string(),
+            Some(Err(error)) => format!("Error: {}", error),
+            None => "empty".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cells() {
+        assert_eq!(
+            cell("Z99"),
+            Cell {
+                row: 99,
+                column: 25
+            }
+        );
+        assert_eq!(cell("B7").to_string(), "B7");
+        for name in ["A0", "A100", "a1", "AA1", "A01", "A"] {
+            assert_eq!(
+                name.parse::<Cell>(),
+                Err(format!("{} is not a cell from A1 to Z99", name))
+            );
+        }
+    }
+
+    #[test]
+    fn test_formulas() {
+        let mut sheet = Sheet::new();
+        sheet.set(cell("A1"), "2").unwrap();
+        sheet.set(cell("A2"), "3").unwrap();
+        sheet.set(cell("B2"), "=A1*A2 + C9").unwrap();
+        sheet.set(cell("B3"), "=sum(A1:A3) + mean(A1:B2)").unwrap();
+        sheet.recalculate();
+        assert_eq!(value(&sheet, "B2"), "6");
+        assert_eq!(value(&sheet, "B3"), "7.75");
+        assert_eq!(value(&sheet, "C9"), "empty");
+    }
+
+    #[test]
+    fn test_only_dirty_cells_are_recalculated() {
+        let mut sheet = Sheet::new();
+        sheet.set(cell("A1"), "1").unwrap();
+        sheet.set(cell("A2"), "=A1 + 1").unwrap();
+        sheet.set(cell("A3"), "=A2 * 10").unwrap();
+        sheet.set(cell("B1"), "=5").unwrap();
+        assert_eq!(
+            sheet.recalculate(),
+            vec![cell("A1"), cell("B1"), cell("A2"), cell("A3")]
+        );
+        assert_eq!(sheet.recalculate(), vec![]);
+
+        // A3 is set before the cell it refers to, so it must wait for it
+        sheet.set(cell("A3"), "=A2 * 100").unwrap();
+        sheet.set(cell("A1"), "4").unwrap();
+        assert_eq!(
+            sheet.recalculate(),
+            vec![cell("A1"), cell("A2"), cell("A3")]
+        );
+        assert_eq!(value(&sheet, "A3"), "500");
+        assert_eq!(value(&sheet, "B1"), "5");
+
+        sheet.clear(cell("A1"));
+        assert_eq!(
+            sheet.recalculate(),
+            vec![cell("A1"), cell("A2"), cell("A3")]
+        );
+        assert_eq!(value(&sheet, "A3"), "100");
+    }
+
+    #[test]
+    fn test_cycles_are_refused() {
+        let mut sheet = Sheet::new();
+        sheet.set(cell("A1"), "=B1 + 1").unwrap();
+        sheet.set(cell("B1"), "=C1").unwrap();
+        assert_eq!(
+            sheet.set(cell("C1"), "=sum(A1:A2)"),
+            Err(CalcError::new(
+                "C1 cannot refer to itself, but this would go C1 -> A1 -> B1 -> C1"
+            ))
+        );
+        assert_eq!(
+            sheet.set(cell("D4"), "=D4"),
+            Err(CalcError::new(
+                "D4 cannot refer to itself, but this would go D4 -> D4"
+            ))
+        );
+        assert_eq!(sheet.formula(cell("C1")), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut sheet = Sheet::new();
+        assert_eq!(
+            sheet.set(cell("A1"), " =A1:3"),
+            Err(CalcError::at(
+                "A : must be between two cells, as in A1:A10",
+                Span::new(4, 5)
+            ))
+        );
+        sheet.set(cell("A1"), "=1 m + 1 s").unwrap();
+        sheet.set(cell("A2"), "=A1 * 2").unwrap();
+        sheet.recalculate();
+        assert_eq!(
+            value(&sheet, "A2"),
+            "Error: A1 has an error: Dimension mismatch: cannot add 1 m and 1 s"
+        );
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let source = "# prices\nA1: 2.5\nB1: 4 # quantity\n\nC1: =A1 * B1\nA2:=sum(A1:C1)\n";
+        let sheet = Sheet::load(source, Environment::new()).unwrap();
+        assert_eq!(value(&sheet, "C1"), "10");
+        assert_eq!(value(&sheet, "A2"), "16.5");
+        let saved = sheet.save();
+        assert_eq!(saved, "A1: 2.5\nB1: 4\nC1: =A1 * B1\nA2: =sum(A1:C1)\n");
+        let reloaded = Sheet::load(&saved, Environment::new()).unwrap();
+        assert_eq!(value(&reloaded, "A2"), "16.5");
+
+        assert_eq!(
+            Sheet::load("A1: 1\nAA1: 2", Environment::new()).err(),
+            Some(CalcError::at(
+                "AA1 is not a cell from A1 to Z99",
+                Span::new(6, 9)
+            ))
+        );
+        assert_eq!(
+            Sheet::load("A1: =A1", Environment::new()).err(),
+            Some(CalcError::at(
+                "A1 cannot refer to itself, but this would go A1 -> A1",
+                Span::new(4, 7)
+            ))
+        );
+        assert_eq!(
+            Sheet::load("A1: 1 +", Environment::new())
+                .err()
+                .map(|error| error.span),
+            Some(Some(Span::new(6, 7)))
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    units::{
+        dimension::{BASE_CURRENCY, Dimension},
+        registry::{self, Unit},
+    },
+    utils::calendar,
+};
+
+/// Exchange rates read from a local file, as units of each currency per unit of the base currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrencyRates {
+    rates: HashMap<String, f64>,
+    /// The date the rates were published, as days since 1970-01-01.
+    as_of: i64,
+    /// Refuse to convert with rates older than this many days.
+    max_age: Option<i64>,
+    today: i64,
+}
+
+impl CurrencyRates {
+    /// Loads rates from a `.json` file shaped `{"base": "USD", "date": "2026-10-15", "rates": {"EUR": 0.92}}`,
+    /// or from a `.csv` file with a `currency,rate,date` header.
+    pub fn load(path: &Path, max_age: Option<i64>) -> Result<CurrencyRates, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read rates file {}: {}", path.display(), e))?;
+        let (rates, as_of) = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => parse_json(&text)?,
+            Some("csv") => parse_csv(&text)?,
+            _ => {
+                return Err(format!(
+                    "Rates file {} must be .json or .csv",
+                    path.display()
+                ));
+            }
+        };
+        Ok(CurrencyRates {
+            rates,
+            as_of,
+            max_age,
+            today: calendar::today(),
+        })
+    }
+
+    pub fn as_of(&self) -> String {
+        calendar::format_date(self.as_of)
+    }
+
+    /// Finds a currency by its code, or [`BASE_CURRENCY`], failing if the rates are too old to trust.
+    pub fn lookup(&self, code: &str) -> Option<Result<Unit, String>> {
+        let rate = match code {
+            BASE_CURRENCY => &1.0,
+            _ => self.rates.get(code)?,
+        };
+        let age = self.today - self.as_of;
+        if let Some(max_age) = self.max_age
+            && age > max_age
+        {
+            return Some(Err(format!(
+                "Exchange rates as of {} are {} days old, more than the limit of {} days",
+                self.as_of(),
+                age,
+                max_age
+            )));
+        }
+        Some(Ok(Unit {
+            factor: 1.0 / rate,
+            dimension: Dimension::CURRENCY,
+        }))
+    }
+}
+
+fn parse_rate(code: &str, rate: Option<f64>) -> Result<f64, String> {
+    match rate {
+        Some(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("Invalid exchange rate for {}", code)),
+    }
+}
+
+/// A currency code has to be three capital letters, like `EUR`, so that it cannot be mistaken
+/// for a unit.
+fn parse_code(code: &str) -> Result<String, String> {
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(format!(
+            "Invalid currency code {}: expected three capital letters",
+            code
+        ));
+    }
+    if registry::lookup(code).is_some() {
+        return Err(format!(
+            "Currency code {} is already the name of a unit",
+            code
+        ));
+    }
+    Ok(code.to_string())
+}
+
+fn parse_as_of(date: &str) -> Result<i64, String> {
+    calendar::parse_date(date).ok_or(format!("Invalid rates date: {}", date))
+}
+
+fn parse_json(text: &str) -> Result<(HashMap<String, f64>, i64), String> {
+    let json: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid rates file: {}", e))?;
+    let Some(date) = json["date"].as_str() else {
+        return Err("Rates file is missing a \"date\"".to_string());
+    };
+    let Some(entries) = json["rates"].as_object() else {
+        return Err("Rates file is missing \"rates\"".to_string());
+    };
+
+    let mut rates = HashMap::new();
+    if let Some(base) = json["base"].as_str() {
+        rates.insert(parse_code(base)?, 1.0);
+    }
+    for (code, rate) in entries {
+        rates.insert(parse_code(code)?, parse_rate(code, rate.as_f64())?);
+    }
+    Ok((rates, parse_as_of(date)?))
+}
+
+fn parse_csv(text: &str) -> Result<(HashMap<String, f64>, i64), String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    match lines.next().map(|header| header.replace(' ', "")) {
+        Some(header) if header == "currency,rate,date" => {}
+        _ => return Err("Rates file must start with a currency,rate,date header".to_string()),
+    }
+
+    let mut rates = HashMap::new();
+    let mut as_of: Option<i64> = None;
+    for line in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [code, rate, date] = fields[..] else {
+            return Err(format!("Expected currency,rate,date but found: {}", line));
+        };
+        rates.insert(parse_code(code)?, parse_rate(code, rate.parse().ok())?);
+        // the file is only as fresh as its oldest rate
+        let date = parse_as_of(date)?;
+        as_of = Some(as_of.map_or(date, |as_of| as_of.min(date)));
+    }
+    match as_of {
+        Some(as_of) => Ok((rates, as_of)),
+        None => Err("Rates file has no rates".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn rates(max_age: Option<i64>, today: i64) -> CurrencyRates {
+        let (rates, as_of) =
+            parse_json(r#"{"base": "USD", "date": "2026-10-15", "rates": {"EUR": 0.8}}"#).unwrap();
+        CurrencyRates {
+            rates,
+            as_of,
+            max_age,
+            today,
+        }
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let rates = rates(None, 0);
+        assert_eq!(rates.as_of(), "2026-10-15");
+        assert_eq!(rates.lookup("USD").unwrap().unwrap().factor, 1.0);
+        assert_eq!(rates.lookup("EUR").unwrap().unwrap().factor, 1.25);
+        assert_eq!(rates.lookup("GBP"), None);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let text = "currency,rate,date\nUSD,1,2026-10-15\nEUR,0.8,2026-10-14\n";
+        let (rates, as_of) = parse_csv(text).unwrap();
+        assert_eq!(rates.get("EUR"), Some(&0.8));
+        assert_eq!(calendar::format_date(as_of), "2026-10-14");
+    }
+
+    #[test]
+    fn test_invalid_rate() {
+        let text = "currency,rate,date\nEUR,0,2026-10-14\n";
+        assert_eq!(
+            parse_csv(text),
+            Err("Invalid exchange rate for EUR".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_code() {
+        assert_eq!(
+            parse_json(r#"{"base": "m", "date": "2026-10-15", "rates": {"kg": 2}}"#),
+            Err("Invalid currency code m: expected three capital letters".to_string())
+        );
+        assert_eq!(
+            parse_csv("currency,rate,date\nUSD,1,2026-10-15\neur,0.8,2026-10-15\n"),
+            Err("Invalid currency code eur: expected three capital letters".to_string())
+        );
+        assert_eq!(parse_code("EUR"), Ok("EUR".to_string()));
+    }
+
+    #[test]
+    fn test_stale_rates() {
+        let as_of = calendar::parse_date("2026-10-15").unwrap();
+        assert!(rates(Some(7), as_of + 7).lookup("EUR").unwrap().is_ok());
+        assert_eq!(
+            rates(Some(7), as_of + 8).lookup("EUR"),
+            Some(Err(
+                "Exchange rates as of 2026-10-15 are 8 days old, more than the limit of 7 days"
+                    .to_string()
+            ))
+        );
+    }
+}
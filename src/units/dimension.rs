@@ -0,0 +1,118 @@
+use std::fmt;
+
+/// The unit of money in the base currency of the loaded exchange rates, whatever its code.
+pub const BASE_CURRENCY: &str = "¤";
+
+/// The SI base units, followed by [`BASE_CURRENCY`].
+pub const BASE_UNITS: [&str; 8] = ["kg", "m", "s", "A", "K", "mol", "cd", BASE_CURRENCY];
+
+/// Exponents of the base units, in the order of [`BASE_UNITS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dimension(pub [i8; 8]);
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0; 8]);
+    pub const LENGTH: Dimension = Dimension([0, 1, 0, 0, 0, 0, 0, 0]);
+    pub const TIME: Dimension = Dimension([0, 0, 1, 0, 0, 0, 0, 0]);
+    pub const CURRENCY: Dimension = Dimension([0, 0, 0, 0, 0, 0, 0, 1]);
+
+    pub fn is_dimensionless(&self) -> bool {
+        *self == Dimension::NONE
+    }
+
+    pub fn involves_currency(&self) -> bool {
+        self.0[7] != 0
+    }
+
+    /// Raises the dimension to a power, which may be fractional as long as every exponent stays whole.
+    pub fn pow(self, exponent: f64) -> Option<Dimension> {
+        let mut exponents = [0; 8];
+        for (e, base) in exponents.iter_mut().zip(self.0) {
+            let raised = f64::from(base) * exponent;
+            if raised.fract() != 0.0 || raised.abs() > f64::from(i8::MAX) {
+                return None;
+            }
+            *e = raised as i8;
+        }
+        Some(Dimension(exponents))
+    }
+
+    pub fn checked_mul(self, rhs: Dimension) -> Option<Dimension> {
+        let mut exponents = self.0;
+        for (e, r) in exponents.iter_mut().zip(rhs.0) {
+            *e = e.checked_add(r)?;
+        }
+        Some(Dimension(exponents))
+    }
+
+    pub fn checked_div(self, rhs: Dimension) -> Option<Dimension> {
+        self.checked_mul(rhs.pow(-1.0)?)
+    }
+}
+
+/// Writes the dimension as SI base units, e.g. `kg m/s^2`, in a form the parser accepts back.
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format_unit = |unit: &str, exponent: i8| match exponent {
+            1 => unit.to_string(),
+            _ => format!("{}^{}", unit, exponent),
+        };
+        let numerator: Vec<String> = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, e)| *e > 0)
+            .map(|(unit, e)| format_unit(unit, e))
+            .collect();
+        let denominator: Vec<String> = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, e)| *e < 0)
+            .map(|(unit, e)| format_unit(unit, -e))
+            .collect();
+
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (_, true) => write!(f, "{}", numerator.join(" ")),
+            (false, false) => write!(f, "{}/{}", numerator.join(" "), denominator.join(" ")),
+            (true, false) => {
+                let inverse: Vec<String> = BASE_UNITS
+                    .iter()
+                    .zip(self.0)
+                    .filter(|(_, e)| *e < 0)
+                    .map(|(unit, e)| format_unit(unit, e))
+                    .collect();
+                write!(f, "{}", inverse.join(" "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_display() {
+        let force = Dimension([1, 1, -2, 0, 0, 0, 0, 0]);
+        assert_eq!(force.to_string(), "kg m/s^2");
+        assert_eq!(Dimension([0, 1, -1, 0, 0, 0, 0, 0]).to_string(), "m/s");
+        assert_eq!(Dimension([0, 0, -1, 0, 0, 0, 0, 0]).to_string(), "s^-1");
+        assert_eq!(Dimension::NONE.to_string(), "");
+    }
+
+    #[test]
+    fn test_pow() {
+        let area = Dimension([0, 2, 0, 0, 0, 0, 0, 0]);
+        let length = Dimension([0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(area.pow(0.5), Some(length));
+        assert_eq!(length.pow(0.5), None);
+        assert_eq!(length.pow(1000.0), None);
+    }
+
+    #[test]
+    fn test_mul_overflow() {
+        let big = Dimension([100, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(big.checked_mul(big), None);
+        assert_eq!(big.checked_div(big), Some(Dimension::NONE));
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{
+    ast::{
+        ast::Node,
+        display::{Part, write_parts},
+    },
+    utils::operators::BinaryOperator,
+};
+
+pub mod currency;
+pub mod dimension;
+pub mod quantity;
+pub mod registry;
+
+/// Renders the unit expression on the right of a conversion, e.g. `km/h`, for labelling the result.
+pub fn label(node: &Node) -> String {
+    let mut label = String::new();
+    write_parts(&mut label, node, |node| match node {
+        Node::Operand { value } => vec![Part::text(value.to_string())],
+        Node::Variable { name, .. } => vec![Part::text(name.as_str())],
+        Node::Date { value } => vec![Part::text(value.to_string())],
+        Node::BinaryExpr {
+            operation,
+            lhs,
+            rhs,
+        } => {
+            let symbol = match operation {
+                BinaryOperator::Multiplication => " ",
+                BinaryOperator::Division => "/",
+                BinaryOperator::Index => "^",
+                BinaryOperator::Addition => " + ",
+                BinaryOperator::Subtraction => " - ",
+                BinaryOperator::Conversion => " to ",
+                BinaryOperator::Range => "..",
+                BinaryOperator::Equation => " = ",
+            };
+            vec![Part::Node(lhs), Part::text(symbol), Part::Node(rhs)]
+        }
+        Node::Negation { operand } => vec![Part::text("-"), Part::Node(operand)],
+        Node::Function { .. } | Node::List { .. } | Node::Matrix { .. } | Node::Lambda { .. } => {
+            vec![Part::text(node.to_string())]
+        }
+    })
+    .unwrap();
+    label
+}
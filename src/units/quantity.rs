@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::units::{dimension::Dimension, registry::Unit};
+
+/// The unit a quantity was written in, kept so results read back in the same terms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayUnit {
+    pub label: String,
+    pub factor: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    /// The magnitude in SI base units.
+    pub value: f64,
+    pub dimension: Dimension,
+    pub display: Option<DisplayUnit>,
+}
+
+impl Quantity {
+    pub fn from_unit(name: &str, unit: Unit) -> Quantity {
+        Quantity {
+            value: unit.factor,
+            dimension: unit.dimension,
+            display: Some(DisplayUnit {
+                label: name.to_string(),
+                factor: unit.factor,
+            }),
+        }
+    }
+
+    pub fn dimensionless(value: f64) -> Quantity {
+        Quantity {
+            value,
+            dimension: Dimension::NONE,
+            display: None,
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.display {
+            Some(display) => write!(
+                f,
+                "{} {}",
+                round_for_display(self.value / display.factor),
+                display.label
+            ),
+            None if self.dimension.is_dimensionless() => write!(f, "{}", self.value),
+            None => write!(f, "{} {}", round_for_display(self.value), self.dimension),
+        }
+    }
+}
+
+/// Rounds to 12 significant figures, hiding the noise left behind by unit conversion factors.
+fn round_for_display(value: f64) -> f64 {
+    format!("{:.11e}", value).parse().unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_display_hides_conversion_noise() {
+        let speed = Quantity {
+            value: 6.0,
+            dimension: Dimension([0, 1, -1, 0, 0, 0, 0, 0]),
+            display: Some(DisplayUnit {
+                label: "km/h".to_string(),
+                factor: 1000.0 / 3600.0,
+            }),
+        };
+        assert_eq!(speed.to_string(), "21.6 km/h");
+    }
+}
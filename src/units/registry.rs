@@ -0,0 +1,157 @@
+use crate::units::dimension::Dimension;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    /// How many of the SI base unit one of this unit is worth.
+    pub factor: f64,
+    pub dimension: Dimension,
+}
+
+struct UnitDefinition {
+    name: &'static str,
+    factor: f64,
+    // exponents of kg, m, s, A, K, mol, cd, currency
+    dimension: [i8; 8],
+    prefixable: bool,
+}
+
+const UNITS: &[UnitDefinition] = &[
+    // SI base units, with the gram standing in for the kilogram so that prefixes work
+    unit("g", 1e-3, [1, 0, 0, 0, 0, 0, 0, 0], true),
+    unit("m", 1.0, [0, 1, 0, 0, 0, 0, 0, 0], true),
+    unit("s", 1.0, [0, 0, 1, 0, 0, 0, 0, 0], true),
+    unit("A", 1.0, [0, 0, 0, 1, 0, 0, 0, 0], true),
+    unit("K", 1.0, [0, 0, 0, 0, 1, 0, 0, 0], true),
+    unit("mol", 1.0, [0, 0, 0, 0, 0, 1, 0, 0], true),
+    unit("cd", 1.0, [0, 0, 0, 0, 0, 0, 1, 0], true),
+    // SI derived units
+    unit("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0, 0], true),
+    unit("N", 1.0, [1, 1, -2, 0, 0, 0, 0, 0], true),
+    unit("Pa", 1.0, [1, -1, -2, 0, 0, 0, 0, 0], true),
+    unit("J", 1.0, [1, 2, -2, 0, 0, 0, 0, 0], true),
+    unit("W", 1.0, [1, 2, -3, 0, 0, 0, 0, 0], true),
+    unit("C", 1.0, [0, 0, 1, 1, 0, 0, 0, 0], true),
+    unit("V", 1.0, [1, 2, -3, -1, 0, 0, 0, 0], true),
+    unit("ohm", 1.0, [1, 2, -3, -2, 0, 0, 0, 0], true),
+    unit("L", 1e-3, [0, 3, 0, 0, 0, 0, 0, 0], true),
+    unit("t", 1e3, [1, 0, 0, 0, 0, 0, 0, 0], false),
+    // time, with long names so that `90 days` and `3 hours` read naturally
+    unit("min", 60.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("h", 3600.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("day", 86400.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("week", 604800.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("month", 2629800.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("year", 31557600.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("second", 1.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("minute", 60.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    unit("hour", 3600.0, [0, 0, 1, 0, 0, 0, 0, 0], false),
+    // imperial, spelling out inch as `in` is the conversion operator
+    unit("inch", 0.0254, [0, 1, 0, 0, 0, 0, 0, 0], false),
+    unit("ft", 0.3048, [0, 1, 0, 0, 0, 0, 0, 0], false),
+    unit("yd", 0.9144, [0, 1, 0, 0, 0, 0, 0, 0], false),
+    unit("mi", 1609.344, [0, 1, 0, 0, 0, 0, 0, 0], false),
+    unit("acre", 4046.8564224, [0, 2, 0, 0, 0, 0, 0, 0], false),
+    unit("gal", 3.785411784e-3, [0, 3, 0, 0, 0, 0, 0, 0], false),
+    unit("lb", 0.45359237, [1, 0, 0, 0, 0, 0, 0, 0], false),
+    unit("oz", 0.028349523125, [1, 0, 0, 0, 0, 0, 0, 0], false),
+    unit("mph", 0.44704, [0, 1, -1, 0, 0, 0, 0, 0], false),
+    unit("psi", 6894.757293168, [1, -1, -2, 0, 0, 0, 0, 0], false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("µ", 1e-6),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+];
+
+const fn unit(
+    name: &'static str,
+    factor: f64,
+    dimension: [i8; 8],
+    prefixable: bool,
+) -> UnitDefinition {
+    UnitDefinition {
+        name,
+        factor,
+        dimension,
+        prefixable,
+    }
+}
+
+/// Finds a unit by name, allowing an SI prefix in front of the units that take one.
+pub fn lookup(name: &str) -> Option<Unit> {
+    // long unit names may be plural
+    let singular = name.strip_suffix('s').filter(|s| s.len() > 2);
+    if let Some(definition) = UNITS
+        .iter()
+        .find(|u| u.name == name || (u.name.len() > 2 && Some(u.name) == singular))
+    {
+        return Some(Unit {
+            factor: definition.factor,
+            dimension: Dimension(definition.dimension),
+        });
+    }
+
+    PREFIXES.iter().find_map(|(prefix, scale)| {
+        let rest = name.strip_prefix(prefix)?;
+        let definition = UNITS.iter().find(|u| u.prefixable && u.name == rest)?;
+        Some(Unit {
+            factor: scale * definition.factor,
+            dimension: Dimension(definition.dimension),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_base_unit() {
+        assert_eq!(
+            lookup("m"),
+            Some(Unit {
+                factor: 1.0,
+                dimension: Dimension([0, 1, 0, 0, 0, 0, 0, 0]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_prefixed_unit() {
+        assert_eq!(
+            lookup("kg"),
+            Some(Unit {
+                factor: 1.0,
+                dimension: Dimension([1, 0, 0, 0, 0, 0, 0, 0]),
+            })
+        );
+        assert_eq!(lookup("ms").map(|u| u.factor), Some(1e-3));
+    }
+
+    #[test]
+    fn test_exact_name_beats_prefix() {
+        assert_eq!(lookup("min").map(|u| u.factor), Some(60.0));
+    }
+
+    #[test]
+    fn test_plural_unit() {
+        assert_eq!(lookup("days").map(|u| u.factor), Some(86400.0));
+        assert_eq!(lookup("hours").map(|u| u.factor), Some(3600.0));
+    }
+
+    #[test]
+    fn test_unprefixable_unit() {
+        assert_eq!(lookup("kft"), None);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::utils::{limits::LimitError, span::Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalcError {
+    pub message: String,
+    pub span: Option<Span>,
+    /// The limit that was reached, if that is what went wrong.
+    pub limit: Option<LimitError>,
+}
+
+impl CalcError {
+    pub fn new(message: impl Into<String>) -> CalcError {
+        CalcError {
+            message: message.into(),
+            span: None,
+            limit: None,
+        }
+    }
+
+    pub fn at(message: impl Into<String>, span: Span) -> CalcError {
+        CalcError {
+            message: message.into(),
+            span: Some(span),
+            limit: None,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<LimitError> for CalcError {
+    fn from(limit: LimitError) -> CalcError {
+        CalcError {
+            message: limit.to_string(),
+            span: None,
+            limit: Some(limit),
+        }
+    }
+}
+
+impl From<String> for CalcError {
+    fn from(message: String) -> CalcError {
+        CalcError::new(message)
+    }
+}
@@ -1,5 +1,47 @@
 use std::io;
 
+use crate::{
+    units::dimension::BASE_CURRENCY,
+    utils::{
+        error::CalcError,
+        limits::EvalLimits,
+        operators::{EXPONENT_MINUS, NEGATION},
+        span::Span,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(text: impl Into<String>, span: Span) -> Token {
+        Token {
+            text: text.into(),
+            span,
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.text.parse::<f64>().is_ok()
+    }
+
+    pub fn is_identifier(&self) -> bool {
+        is_identifier(&self.text)
+    }
+}
+
+pub fn is_identifier(text: &str) -> bool {
+    if text == BASE_CURRENCY {
+        return true;
+    }
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
 pub fn get_input() -> String {
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
@@ -7,66 +49,320 @@ pub fn get_input() -> String {
 }
 
 pub fn get_normalized_input(input: &str) -> Result<Vec<String>, String> {
-    let normalized = input
-        .chars()
-        .filter(|c| !c.is_whitespace())
-        .collect::<String>()
-        .replace('÷', "/");
-
-    let mut parts = Vec::new();
-    // to hold a number that is split across multiple chars
+    get_normalized_input_within(input, &EvalLimits::default()).map_err(|error| error.message)
+}
+
+/// Like [`get_normalized_input`], giving up on input with too many tokens or brackets nested
+/// too deeply for `limits`.
+pub fn get_normalized_input_within(
+    input: &str,
+    limits: &EvalLimits,
+) -> Result<Vec<String>, CalcError> {
+    let tokens = tokenize_within(input, limits)?;
+    Ok(tokens.into_iter().map(|token| token.text).collect())
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    tokenize_within(input, &EvalLimits::default())
+}
+
+/// Like [`tokenize`], stopping as soon as there are more tokens than `limits` allow and then
+/// checking how deeply brackets nest.
+pub fn tokenize_within(input: &str, limits: &EvalLimits) -> Result<Vec<Token>, CalcError> {
+    let mut parts: Vec<Token> = Vec::new();
+    // to hold a number or identifier that is split across multiple chars
     let mut accumulated = String::new();
+    let mut accumulated_start = 0;
 
-    for ch in normalized.chars() {
-        match ch {
-            '+' | '-' | '/' | '*' | '^' | '(' | ')' => {
-                if !accumulated.is_empty() {
-                    parts.push(accumulated);
-                    accumulated = String::new();
-                }
+    let chars: Vec<char> = input.chars().collect();
+    // the end of a date literal being skipped over
+    let mut skip_until = 0;
+    for (i, &ch) in chars.iter().enumerate() {
+        check_count(&parts, limits)?;
+        if i < skip_until {
+            continue;
+        }
+        let ch = if ch == '÷' { '/' } else { ch };
 
-                // + and - are also unary operators
-                // if we find one that has come after an operator, then push in an extra 0 so we can pretend it is binary
-                if ch == '+' || ch == '-' {
-                    let last_part = parts.last();
-                    if last_part.is_none()
-                        || last_part.is_some_and(|x: &String| x.parse::<f64>().is_err())
-                    {
-                        parts.push("0".to_string());
+        if accumulated.is_empty()
+            && let Some(len) = date_literal_len(&chars[i..])
+        {
+            let text: String = chars[i..i + len].iter().collect();
+            parts.push(Token::new(text, Span::new(i, i + len)));
+            skip_until = i + len;
+            continue;
+        }
+
+        // `..` makes a range, so `1..10` is not read as the number `1.` followed by `.10`
+        if ch == '.' && chars.get(i + 1) == Some(&'.') {
+            flush(&mut parts, &mut accumulated, accumulated_start, i);
+            parts.push(Token::new("..", Span::new(i, i + 2)));
+            skip_until = i + 2;
+            continue;
+        }
+
+        if ch.is_ascii_digit() || ch == '.' || ch.is_alphabetic() || ch == '_' {
+            // a letter straight after a number starts a new token, so `2x` is two tokens
+            let starts_identifier = ch.is_alphabetic() || ch == '_';
+            if starts_identifier && !accumulated.is_empty() && !is_identifier(&accumulated) {
+                flush(&mut parts, &mut accumulated, accumulated_start, i);
+            }
+            if accumulated.is_empty() {
+                accumulated_start = i;
+            }
+            accumulated.push(ch);
+            continue;
+        }
+
+        flush(&mut parts, &mut accumulated, accumulated_start, i);
+
+        match ch {
+            '-' if chars.get(i + 1) == Some(&'>') => {
+                parts.push(Token::new("->", Span::new(i, i + 2)));
+                skip_until = i + 2;
+            }
+            '+' | '-' | '/' | '*' | '^' | '(' | ')' | '{' | '}' | '[' | ']' | ';' | '=' | ',' => {
+                // + and - are also unary operators when no value comes before them: a + changes
+                // nothing, and a - negates what follows, as far as a - straight after ^ reaches
+                // within the exponent
+                if (ch == '+' || ch == '-') && parts.last().is_none_or(is_operator_or_open) {
+                    if ch == '-' {
+                        let sign = match parts.last().is_some_and(|t| t.text == "^") {
+                            true => EXPONENT_MINUS,
+                            false => NEGATION,
+                        };
+                        parts.push(Token::new(sign, Span::new(i, i + 1)));
                     }
+                    continue;
                 }
 
-                parts.push(ch.to_string());
+                parts.push(Token::new(ch, Span::new(i, i + 1)));
             }
+            // results in the base currency are written with it, so it reads back as a unit
+            '¤' => parts.push(Token::new(BASE_CURRENCY, Span::new(i, i + 1))),
+            _ if ch.is_whitespace() => {}
             _ => {
-                if ch.is_ascii_digit() || ch == '.' {
-                    accumulated.push(ch)
+                return Err(CalcError::at(
+                    format!("Found unsupported token: {}", ch),
+                    Span::new(i, i + 1),
+                ));
+            }
+        }
+    }
+
+    flush(&mut parts, &mut accumulated, accumulated_start, chars.len());
+    check_count(&parts, limits)?;
+    limits.check_depth(&parts)?;
+
+    Ok(parts)
+}
+
+/// Fails once there are more tokens than `limits` allow, pointing at the last of them.
+fn check_count(parts: &[Token], limits: &EvalLimits) -> Result<(), CalcError> {
+    limits.check_tokens(parts.len()).map_err(|limit| CalcError {
+        span: parts.last().map(|token| token.span),
+        ..limit.into()
+    })
+}
+
+/// The length of the `YYYY-MM-DD[THH:MM[:SS[.fff]]][Z]` date literal at the start of `chars`, if there is one.
+fn date_literal_len(chars: &[char]) -> Option<usize> {
+    let matches = |pattern: &str, at: usize| {
+        pattern.chars().enumerate().all(|(j, p)| {
+            chars.get(at + j).is_some_and(|c| {
+                if p == '0' {
+                    c.is_ascii_digit()
                 } else {
-                    return Err(format!("Found unsupported token: {}", ch));
+                    *c == p
+                }
+            })
+        })
+    };
+
+    if !matches("0000-00-00", 0) {
+        return None;
+    }
+    let mut len = 10;
+    if matches("T00:00", len) {
+        len += 6;
+        if matches(":00", len) {
+            len += 3;
+            if matches(".0", len) {
+                len += 1;
+                while chars.get(len).is_some_and(|c| c.is_ascii_digit()) {
+                    len += 1;
                 }
             }
         }
     }
+    if chars.get(len) == Some(&'Z') {
+        len += 1;
+    }
+    Some(len)
+}
 
+fn flush(parts: &mut Vec<Token>, accumulated: &mut String, start: usize, end: usize) {
     if !accumulated.is_empty() {
-        parts.push(accumulated);
+        parts.push(Token::new(
+            std::mem::take(accumulated),
+            Span::new(start, end),
+        ));
     }
+}
 
-    Ok(parts)
+fn is_operator_or_open(token: &Token) -> bool {
+    matches!(
+        token.text.as_str(),
+        "+" | "-"
+            | "/"
+            | "*"
+            | "^"
+            | "("
+            | "{"
+            | "["
+            | ";"
+            | "="
+            | ","
+            | ".."
+            | "->"
+            | NEGATION
+            | EXPONENT_MINUS
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::test::tokens;
     use pretty_assertions::assert_eq;
 
     #[test]
     fn test_unary_operators() {
         let result = get_normalized_input("4+-5");
-        let expected: Vec<String> = vec!["4", "+", "0", "-", "5"]
-            .iter()
-            .map(|x| x.to_string())
-            .collect();
+        let expected = tokens(&["4", "+", NEGATION, "5"]);
+        assert_eq!(result, Ok(expected));
+        let result = get_normalized_input("-x - -+2");
+        let expected = tokens(&[NEGATION, "x", "-", NEGATION, "2"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_binary_minus_after_parenthesis() {
+        let result = get_normalized_input("(1+2)-x");
+        let expected = tokens(&["(", "1", "+", "2", ")", "-", "x"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let result = get_normalized_input("rate_2 * 2x");
+        let expected = tokens(&["rate_2", "*", "2", "x"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_signed_exponent() {
+        let result = get_normalized_input("2^-1.5 - 1");
+        let expected = tokens(&["2", "^", EXPONENT_MINUS, "1.5", "-", "1"]);
+        assert_eq!(result, Ok(expected));
+        let result = get_normalized_input("2^-x + 2^-(1)");
+        let expected = tokens(&[
+            "2",
+            "^",
+            EXPONENT_MINUS,
+            "x",
+            "+",
+            "2",
+            "^",
+            EXPONENT_MINUS,
+            "(",
+            "1",
+            ")",
+        ]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_function_arguments() {
+        let result = get_normalized_input("diff(-x, x)");
+        let expected = tokens(&["diff", "(", NEGATION, "x", ",", "x", ")"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_braces() {
+        let result = get_normalized_input("{-x, 1}");
+        let expected = tokens(&["{", NEGATION, "x", ",", "1", "}"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_ranges() {
+        let result = get_normalized_input("1..10 + -2.5..x");
+        let expected = tokens(&["1", "..", "10", "+", NEGATION, "2.5", "..", "x"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let result = get_normalized_input("map(x->-x, 1..3)");
+        let expected = tokens(&[
+            "map", "(", "x", "->", NEGATION, "x", ",", "1", "..", "3", ")",
+        ]);
         assert_eq!(result, Ok(expected))
     }
+
+    #[test]
+    fn test_date_literals() {
+        let result = get_normalized_input("2026-12-25 - 2026-10-18T14:30:00Z");
+        let expected = tokens(&["2026-12-25", "-", "2026-10-18T14:30:00Z"]);
+        assert_eq!(result, Ok(expected))
+    }
+
+    #[test]
+    fn test_spans() {
+        let result = tokenize("12 ÷ x").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::new("12", Span::new(0, 2)),
+                Token::new("/", Span::new(3, 4)),
+                Token::new("x", Span::new(5, 6)),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_unsupported_token_span() {
+        let result = tokenize("1 + $");
+        assert_eq!(
+            result,
+            Err(CalcError::at("Found unsupported token: $", Span::new(4, 5)))
+        )
+    }
+
+    #[test]
+    fn test_tokenize_within() {
+        use crate::utils::limits::LimitError;
+
+        let limits = EvalLimits {
+            max_tokens: Some(3),
+            ..EvalLimits::default()
+        };
+        assert_eq!(tokenize_within("1 + 2", &limits).map(|t| t.len()), Ok(3));
+        assert_eq!(
+            tokenize_within("1 + 2 * 3", &limits),
+            Err(CalcError {
+                message: "The input is longer than the limit of 3 tokens".to_string(),
+                span: Some(Span::new(6, 7)),
+                limit: Some(LimitError::TooManyTokens { limit: 3 }),
+            })
+        );
+        // the rest of a long input is never looked at
+        let long = format!("1 + 2 * {}", "$".repeat(1_000_000));
+        assert_eq!(
+            tokenize_within(&long, &limits).map_err(|error| error.limit),
+            Err(Some(LimitError::TooManyTokens { limit: 3 }))
+        );
+    }
 }
@@ -0,0 +1,248 @@
+use std::{
+    fmt,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::utils::{error::CalcError, input::Token};
+
+/// How much work one calculation may take, for input from people who cannot be trusted not to
+/// ask for too much. Each limit is off when it is `None`, as they all are by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalLimits {
+    /// How many tokens the input may have.
+    pub max_tokens: Option<usize>,
+    /// How deeply brackets of any kind may nest, and how many levels of operations and calls
+    /// the parsed expression may have, so that `1 + 2 * 3` is two deep.
+    pub max_depth: Option<usize>,
+    /// How many nodes may be evaluated, counting each time a function like `map` or `nsum`
+    /// evaluates its body, each entry of a list or matrix an operation makes or a function is
+    /// given, and each node of a derivative `diff` builds and simplifies.
+    pub max_operations: Option<u64>,
+    /// How large the exponent of a `^` may be. Numbers are floats, so a large power of a number
+    /// is cheap, but the limit also keeps matrix powers and quantities within bounds.
+    pub max_exponent: Option<f64>,
+    /// How long evaluating may take, from when it starts.
+    pub timeout: Option<Duration>,
+}
+
+/// The limit of [`EvalLimits`] that a calculation went over.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    TooManyTokens { limit: usize },
+    TooDeep { limit: usize },
+    TooManyOperations { limit: u64 },
+    ExponentTooLarge { limit: f64, found: f64 },
+    TimedOut { limit: Duration },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::TooManyTokens { limit } => {
+                write!(f, "The input is longer than the limit of {} tokens", limit)
+            }
+            LimitError::TooDeep { limit } => {
+                write!(f, "The expression nests deeper than the limit of {}", limit)
+            }
+            LimitError::TooManyOperations { limit } => write!(
+                f,
+                "The calculation took more than the limit of {} operations",
+                limit
+            ),
+            LimitError::ExponentTooLarge { limit, found } => write!(
+                f,
+                "The exponent {} is larger than the limit of {}",
+                found, limit
+            ),
+            LimitError::TimedOut { limit } => {
+                write!(
+                    f,
+                    "The calculation took longer than the limit of {:?}",
+                    limit
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl EvalLimits {
+    /// Whether `count` tokens are within [`EvalLimits::max_tokens`].
+    pub fn check_tokens(&self, count: usize) -> Result<(), LimitError> {
+        match self.max_tokens {
+            Some(limit) if count > limit => Err(LimitError::TooManyTokens { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether brackets in `tokens` nest within [`EvalLimits::max_depth`], pointing at the first
+    /// one that does not.
+    pub fn check_depth(&self, tokens: &[Token]) -> Result<(), CalcError> {
+        let Some(limit) = self.max_depth else {
+            return Ok(());
+        };
+        let mut depth = 0usize;
+        for token in tokens {
+            match token.text.as_str() {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth = depth.saturating_sub(1),
+                _ => continue,
+            }
+            if depth > limit {
+                return Err(CalcError {
+                    span: Some(token.span),
+                    ..LimitError::TooDeep { limit }.into()
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How much of its [`EvalLimits`] a calculation has used. It is shared by everything evaluated
+/// for the calculation, including the bodies of closures and the expressions `nsum` and
+/// `integrate` work through.
+#[derive(Debug)]
+pub struct Budget {
+    limits: EvalLimits,
+    deadline: Option<Instant>,
+    operations: AtomicU64,
+    exceeded: OnceLock<LimitError>,
+}
+
+impl Budget {
+    /// A budget whose time starts now.
+    pub fn new(limits: EvalLimits) -> Budget {
+        Budget {
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            limits,
+            operations: AtomicU64::new(0),
+            exceeded: OnceLock::new(),
+        }
+    }
+
+    /// Counts one operation. Once the operations or the time have run out every later one fails
+    /// too, so a limit is not lost to code that tries something else after an error.
+    pub fn step(&self) -> Result<(), LimitError> {
+        self.charge(1)
+    }
+
+    /// Counts `operations` at once, for work like building a long list that is done in one go.
+    pub fn charge(&self, operations: u64) -> Result<(), LimitError> {
+        if let Some(error) = self.exceeded.get() {
+            return Err(error.clone());
+        }
+        let count = self.operations.fetch_add(operations, Ordering::Relaxed) + operations;
+        if let Some(limit) = self.limits.max_operations
+            && count > limit
+        {
+            return Err(self.exceed(LimitError::TooManyOperations { limit }));
+        }
+        // the clock is read every time, as one operation can take far longer than another
+        if let (Some(deadline), Some(limit)) = (self.deadline, self.limits.timeout)
+            && Instant::now() >= deadline
+        {
+            return Err(self.exceed(LimitError::TimedOut { limit }));
+        }
+        Ok(())
+    }
+
+    pub fn check_exponent(&self, exponent: f64) -> Result<(), LimitError> {
+        match self.limits.max_exponent {
+            Some(limit) if exponent.abs() > limit => Err(LimitError::ExponentTooLarge {
+                limit,
+                found: exponent,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The limit that ran out, if one has.
+    pub fn exceeded(&self) -> Option<&LimitError> {
+        self.exceeded.get()
+    }
+
+    fn exceed(&self, error: LimitError) -> LimitError {
+        self.exceeded.get_or_init(|| error).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{span::Span, test::spanned_tokens};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_check_depth() {
+        let limits = EvalLimits {
+            max_depth: Some(2),
+            ..EvalLimits::default()
+        };
+        let tokens = spanned_tokens(&["(", "(", "1", ")", "+", "{", "2", "}", ")"]);
+        assert_eq!(limits.check_depth(&tokens), Ok(()));
+        let tokens = spanned_tokens(&["(", "{", "[", "2", "]", "}", ")"]);
+        assert_eq!(
+            limits.check_depth(&tokens),
+            Err(CalcError {
+                message: "The expression nests deeper than the limit of 2".to_string(),
+                span: Some(Span::new(4, 5)),
+                limit: Some(LimitError::TooDeep { limit: 2 }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(EvalLimits {
+            max_operations: Some(3),
+            max_exponent: Some(100.0),
+            ..EvalLimits::default()
+        });
+        for _ in 0..3 {
+            assert_eq!(budget.step(), Ok(()));
+        }
+        let exceeded = Err(LimitError::TooManyOperations { limit: 3 });
+        assert_eq!(budget.step(), exceeded);
+        assert_eq!(budget.charge(0), exceeded);
+        assert_eq!(budget.step(), exceeded);
+        assert_eq!(
+            budget.exceeded(),
+            Some(&LimitError::TooManyOperations { limit: 3 })
+        );
+        assert_eq!(budget.check_exponent(-100.0), Ok(()));
+        assert_eq!(
+            budget.check_exponent(1e6),
+            Err(LimitError::ExponentTooLarge {
+                limit: 100.0,
+                found: 1e6
+            })
+        );
+
+        let budget = Budget::new(EvalLimits {
+            max_operations: Some(10),
+            ..EvalLimits::default()
+        });
+        assert_eq!(budget.charge(10), Ok(()));
+        assert_eq!(
+            budget.charge(1),
+            Err(LimitError::TooManyOperations { limit: 10 })
+        );
+
+        let budget = Budget::new(EvalLimits {
+            timeout: Some(Duration::ZERO),
+            ..EvalLimits::default()
+        });
+        assert_eq!(
+            budget.step(),
+            Err(LimitError::TimedOut {
+                limit: Duration::ZERO
+            })
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod calendar;
+pub mod error;
 pub mod input;
+pub mod limits;
 pub mod operators;
-pub mod test;
\ No newline at end of file
+pub mod span;
+pub mod table;
+pub mod test;
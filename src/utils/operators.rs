@@ -5,6 +5,41 @@ pub enum BinaryOperator {
     Multiplication,
     Division,
     Index,
+    Conversion,
+    /// Every whole step from one number up to another, as in `1..10`.
+    Range,
+    /// Not an operation but the relation between the two sides of an equation.
+    Equation,
+}
+
+impl BinaryOperator {
+    /// The symbol the operator is written with, which [`get_operator_info`] accepts back.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Addition => "+",
+            BinaryOperator::Subtraction => "-",
+            BinaryOperator::Multiplication => "*",
+            BinaryOperator::Division => "/",
+            BinaryOperator::Index => "^",
+            BinaryOperator::Conversion => "to",
+            BinaryOperator::Range => "..",
+            BinaryOperator::Equation => "=",
+        }
+    }
+
+    /// The verb for the operation, for use in error messages.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            BinaryOperator::Addition => "add",
+            BinaryOperator::Subtraction => "subtract",
+            BinaryOperator::Multiplication => "multiply",
+            BinaryOperator::Division => "divide",
+            BinaryOperator::Index => "exponentiate",
+            BinaryOperator::Conversion => "convert",
+            BinaryOperator::Range => "make a range from",
+            BinaryOperator::Equation => "equate",
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
@@ -20,33 +55,86 @@ pub struct OperatorInfo {
     pub associativity: Associativity,
 }
 
+/// Inserted by the parser between two values written side by side, as in `3 m`.
+/// It binds tighter than `*` and `/` so that `1 m/2 s` is `(1 m)/(2 s)`.
+pub const IMPLICIT_MULTIPLICATION: &str = "implicit *";
+
+/// Inserted by the parser between a number with a unit of time or length and the number after it,
+/// as in `3 h 20 min` or `5 ft 3 inch`.
+/// It binds tighter than `*` so that `3 h 20 min * 4` scales the whole duration.
+pub const IMPLICIT_ADDITION: &str = "implicit +";
+
+/// Made by the tokenizer of a `-` with no value before it, which negates the value after it.
+/// It binds as loosely as subtraction, so that `-x^2` is `-(x^2)` and `-3 h 20 min` is the whole
+/// duration negated.
+pub const NEGATION: &str = "unary -";
+
+/// Made by the tokenizer of a `-` straight after `^`. It binds as tightly as `^`, so that `2^-x`
+/// is `2^(-x)` and `2^-x^2` is `2^-(x^2)`.
+pub const EXPONENT_MINUS: &str = "exponent -";
+
+/// Whether `symbol` is a sign written before its only operand rather than between two.
+pub fn is_prefix(symbol: &str) -> bool {
+    matches!(symbol, NEGATION | EXPONENT_MINUS)
+}
+
 pub fn get_operator_info(symbol: &str) -> Option<&'static OperatorInfo> {
     match symbol {
         "^" => Some(&OperatorInfo {
             operation: BinaryOperator::Index,
-            precedence: 4,
+            precedence: 8,
+            associativity: Associativity::Right,
+        }),
+        EXPONENT_MINUS => Some(&OperatorInfo {
+            operation: BinaryOperator::Subtraction,
+            precedence: 8,
             associativity: Associativity::Right,
         }),
+        IMPLICIT_MULTIPLICATION => Some(&OperatorInfo {
+            operation: BinaryOperator::Multiplication,
+            precedence: 7,
+            associativity: Associativity::Left,
+        }),
+        IMPLICIT_ADDITION => Some(&OperatorInfo {
+            operation: BinaryOperator::Addition,
+            precedence: 6,
+            associativity: Associativity::Left,
+        }),
         "*" => Some(&OperatorInfo {
             operation: BinaryOperator::Multiplication,
-            precedence: 3,
+            precedence: 5,
             associativity: Associativity::Left,
         }),
         "/" => Some(&OperatorInfo {
             operation: BinaryOperator::Division,
-            precedence: 3,
+            precedence: 5,
             associativity: Associativity::Left,
         }),
         "+" => Some(&OperatorInfo {
             operation: BinaryOperator::Addition,
-            precedence: 2,
+            precedence: 4,
             associativity: Associativity::Left,
         }),
-        "-" => Some(&OperatorInfo {
+        "-" | NEGATION => Some(&OperatorInfo {
             operation: BinaryOperator::Subtraction,
+            precedence: 4,
+            associativity: Associativity::Left,
+        }),
+        ".." => Some(&OperatorInfo {
+            operation: BinaryOperator::Range,
+            precedence: 3,
+            associativity: Associativity::Left,
+        }),
+        "to" | "in" => Some(&OperatorInfo {
+            operation: BinaryOperator::Conversion,
             precedence: 2,
             associativity: Associativity::Left,
         }),
+        "=" => Some(&OperatorInfo {
+            operation: BinaryOperator::Equation,
+            precedence: 1,
+            associativity: Associativity::Left,
+        }),
         _ => None,
     }
 }
@@ -0,0 +1,43 @@
+/// Lays `rows` out under `headers` in left-aligned columns separated by two spaces.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    for row in std::iter::once(&headers).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_columns_fit_widest_cell() {
+        let rows = vec![
+            vec!["3".to_string(), "push 3".to_string()],
+            vec!["+".to_string(), "pop 3 4, push 7".to_string()],
+        ];
+        assert_eq!(
+            format_table(&["token", "action"], &rows),
+            "token  action\n3      push 3\n+      pop 3 4, push 7\n"
+        );
+    }
+}
@@ -1,5 +1,36 @@
+use crate::utils::{input::Token, span::Span};
+
 // used in tests
 #[allow(dead_code)]
 pub fn tokens(s: &[&str]) -> Vec<String> {
     s.iter().map(|s| s.to_string()).collect()
 }
+
+// used in tests, lays the tokens out as if separated by single spaces
+#[allow(dead_code)]
+pub fn spanned_tokens(s: &[&str]) -> Vec<Token> {
+    let mut start = 0;
+    s.iter()
+        .map(|s| {
+            let len = s.chars().count();
+            let token = Token::new(*s, Span::new(start, start + len));
+            start += len + 1;
+            token
+        })
+        .collect()
+}
+
+// used in tests, writes `contents` to a file of its own so tests running at once do not share it
+#[cfg(test)]
+pub fn temp_file(extension: &str, contents: &str) -> std::path::PathBuf {
+    static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "calculator-test-{}-{}.{}",
+        std::process::id(),
+        count,
+        extension
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
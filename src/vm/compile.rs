@@ -0,0 +1,377 @@
+use std::convert::Infallible;
+
+use crate::{
+    ast::{
+        ast::Node,
+        derivative::contains,
+        environment::Environment,
+        functions, statistics,
+        value::Value,
+        visit::{Visitor, visit},
+    },
+    utils::{error::CalcError, operators::BinaryOperator},
+    vm::{CompiledExpr, Instruction},
+};
+
+/// What is left to do in compiling, in the order the instructions are to come out.
+enum Task<'e> {
+    /// Compiles a node, with the index of its [`Facts`] and whether it is already known to be
+    /// arithmetic.
+    Node(&'e Node, usize, bool),
+    /// Adds an instruction that takes this many values off the stack.
+    Emit(Instruction, usize),
+}
+
+/// What the compiler needs to know of a node, worked out for the whole tree before compiling so
+/// that no part of it is walked more than once.
+#[derive(Debug, Clone, Copy, Default)]
+struct Facts {
+    /// Whether the node is made only of numbers and what the compiler compiles as written.
+    arithmetic: bool,
+    /// Whether the node mentions any of the variables.
+    depends: bool,
+    /// How many nodes the node is made of, itself included.
+    size: usize,
+}
+
+/// Works out the [`Facts`] of every node, in the order the nodes are entered, so that a node's
+/// first child comes straight after it and each other child after the one before it.
+struct Survey<'a> {
+    variables: &'a [&'a str],
+    facts: Vec<Facts>,
+    entered: Vec<usize>,
+}
+
+impl<'a> Visitor<'a> for Survey<'_> {
+    type Output = Facts;
+    type Error = Infallible;
+
+    fn enter(&mut self, _node: &'a Node, _parent: Option<(&'a Node, usize)>) {
+        self.entered.push(self.facts.len());
+        self.facts.push(Facts::default());
+    }
+
+    fn visit_node(&mut self, node: &'a Node, outputs: Vec<Facts>) -> Result<Facts, Infallible> {
+        let index = self.entered.pop().unwrap();
+        let is_list = |node: &Node| matches!(node, Node::List { .. });
+        // a list counts by its items, which only a call looks at
+        let arithmetic = outputs.iter().all(|facts| facts.arithmetic)
+            && match node {
+                Node::Operand { .. } | Node::List { .. } => true,
+                Node::BinaryExpr {
+                    operation:
+                        BinaryOperator::Addition
+                        | BinaryOperator::Subtraction
+                        | BinaryOperator::Multiplication
+                        | BinaryOperator::Division
+                        | BinaryOperator::Index,
+                    lhs,
+                    rhs,
+                } => !is_list(lhs) && !is_list(rhs),
+                Node::Negation { operand } => !is_list(operand),
+                Node::Function { name, .. } => {
+                    functions::builtin(name).is_some() || statistics::is_statistic(name)
+                }
+                _ => false,
+            };
+        let depends = match node {
+            Node::Variable { name, .. } => self.variables.contains(&name.as_str()),
+            // a parameter of the same name hides the variable
+            Node::Lambda { .. } => self.variables.iter().any(|name| contains(node, name)),
+            _ => outputs.iter().any(|facts| facts.depends),
+        };
+        let facts = Facts {
+            arithmetic,
+            depends,
+            size: self.facts.len() - index,
+        };
+        self.facts[index] = facts;
+        Ok(facts)
+    }
+}
+
+struct Compiler<'a> {
+    variables: &'a [&'a str],
+    env: &'a Environment,
+    facts: Vec<Facts>,
+    instructions: Vec<Instruction>,
+    depth: usize,
+    max_depth: usize,
+}
+
+/// Compiles `expr` into instructions that take a value for each of `variables` when run.
+///
+/// Arithmetic, builtins and statistics are compiled as they are written. Parts that use anything
+/// else, including names that are not among `variables`, must not depend on `variables`, and are
+/// worked out now in `env` and kept as the number they come to.
+pub fn compile(
+    expr: &Node,
+    variables: &[&str],
+    env: &Environment,
+) -> Result<CompiledExpr, CalcError> {
+    let mut survey = Survey {
+        variables,
+        facts: Vec::new(),
+        entered: Vec::new(),
+    };
+    let Ok(_) = visit(&mut survey, expr);
+    let mut compiler = Compiler {
+        variables,
+        env,
+        facts: survey.facts,
+        instructions: Vec::new(),
+        depth: 0,
+        max_depth: 0,
+    };
+    compiler.node(expr)?;
+    Ok(CompiledExpr {
+        instructions: compiler.instructions,
+        variables: variables.iter().map(|name| name.to_string()).collect(),
+        depth: compiler.max_depth,
+    })
+}
+
+impl Compiler<'_> {
+    /// Adds an instruction that takes `pops` values off the stack and pushes one.
+    fn emit(&mut self, instruction: Instruction, pops: usize) {
+        self.depth = self.depth - pops + 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        self.instructions.push(instruction);
+    }
+
+    /// Compiles `expr` with a stack of what is left to do rather than by recursion, so that
+    /// however deeply it nests it cannot overflow.
+    fn node<'e>(&mut self, expr: &'e Node) -> Result<(), CalcError> {
+        let mut tasks = vec![Task::Node(expr, 0, self.facts[0].arithmetic)];
+        while let Some(task) = tasks.pop() {
+            let (expr, index, arithmetic) = match task {
+                Task::Node(expr, index, arithmetic) => (expr, index, arithmetic),
+                Task::Emit(instruction, pops) => {
+                    self.emit(instruction, pops);
+                    continue;
+                }
+            };
+            if !arithmetic && !self.facts[index].depends {
+                self.constant(expr, index)?;
+                continue;
+            }
+            // everything in arithmetic is arithmetic too
+            let operand = |node: &'e Node, index: usize| {
+                Task::Node(node, index, arithmetic || self.facts[index].arithmetic)
+            };
+            match expr {
+                Node::Operand { value } => self.emit(Instruction::Constant(*value), 0),
+                Node::Variable { name, .. } => {
+                    match self.variables.iter().position(|variable| variable == name) {
+                        Some(slot) => self.emit(Instruction::Load(slot), 0),
+                        None => self.constant(expr, index)?,
+                    }
+                }
+                Node::BinaryExpr {
+                    operation,
+                    lhs,
+                    rhs,
+                } => {
+                    let instruction = match operation {
+                        BinaryOperator::Addition => Instruction::Add,
+                        BinaryOperator::Subtraction => Instruction::Subtract,
+                        BinaryOperator::Multiplication => Instruction::Multiply,
+                        BinaryOperator::Division => Instruction::Divide,
+                        BinaryOperator::Index => Instruction::Power,
+                        _ => {
+                            self.constant(expr, index)?;
+                            continue;
+                        }
+                    };
+                    let mut children = self.children(index);
+                    let (lhs_index, rhs_index) =
+                        (children.next().unwrap(), children.next().unwrap());
+                    tasks.push(Task::Emit(instruction, 2));
+                    tasks.push(operand(rhs, rhs_index));
+                    tasks.push(operand(lhs, lhs_index));
+                }
+                Node::Negation { operand: inner } => {
+                    tasks.push(Task::Emit(Instruction::Negate, 1));
+                    tasks.push(operand(inner, index + 1));
+                }
+                Node::Function { name, args, .. } => {
+                    if let Some(builtin) = functions::builtin(name) {
+                        let instruction = Instruction::Builtin {
+                            name: builtin.name,
+                            apply: builtin.apply,
+                        };
+                        tasks.push(Task::Emit(instruction, 1));
+                        tasks.push(operand(&args[0], index + 1));
+                    } else if let Some((name, _)) = statistics::FUNCTIONS
+                        .iter()
+                        .find(|(function, _)| *function == name.as_str())
+                    {
+                        let mut values = Vec::new();
+                        for (arg, index) in args.iter().zip(self.children(index)) {
+                            values.extend(self.argument(arg, index, arithmetic));
+                        }
+                        let count = values.len();
+                        tasks.push(Task::Emit(Instruction::Statistic { name, count }, count));
+                        tasks.extend(values.into_iter().rev());
+                    } else {
+                        self.constant(expr, index)?;
+                    }
+                }
+                _ => self.constant(expr, index)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The indices of the [`Facts`] of the children of the node at `index`, in order.
+    fn children(&self, index: usize) -> impl Iterator<Item = usize> + use<'_> {
+        let end = index + self.facts[index].size;
+        std::iter::successors(Some(index + 1), |child| {
+            Some(child + self.facts[*child].size)
+        })
+        .take_while(move |child| *child < end)
+    }
+
+    /// What to do for an argument to a statistic, which may be a list of values, each task
+    /// leaving one value on the stack.
+    fn argument<'e>(&self, arg: &'e Node, index: usize, arithmetic: bool) -> Vec<Task<'e>> {
+        if let Node::List { items, .. } = arg {
+            return items
+                .iter()
+                .zip(self.children(index))
+                .map(|(item, index)| {
+                    Task::Node(item, index, arithmetic || self.facts[index].arithmetic)
+                })
+                .collect();
+        }
+        // a range or other list that does not depend on the variables is known already
+        if !self.facts[index].depends
+            && let Ok(Value::List(items)) = arg.calculate(self.env)
+        {
+            return items
+                .into_iter()
+                .map(|item| Task::Emit(Instruction::Constant(item), 0))
+                .collect();
+        }
+        vec![Task::Node(
+            arg,
+            index,
+            arithmetic || self.facts[index].arithmetic,
+        )]
+    }
+
+    /// Works out an expression that does not depend on the variables, keeping its value.
+    fn constant(&mut self, expr: &Node, index: usize) -> Result<(), CalcError> {
+        if self.facts[index].depends {
+            return Err(CalcError {
+                message: format!(
+                    "Cannot compile {}: only arithmetic, builtins and statistics of {} can be compiled",
+                    expr,
+                    self.variables.join(", ")
+                ),
+                span: expr.span(),
+                limit: None,
+            });
+        }
+        let value = number(expr, expr.calculate(self.env)?)?;
+        self.emit(Instruction::Constant(value), 0);
+        Ok(())
+    }
+}
+
+fn number(expr: &Node, value: Value) -> Result<f64, CalcError> {
+    match value {
+        Value::Number(value) => Ok(value),
+        other => Err(CalcError {
+            message: format!(
+                "Cannot compile {}, which is {} rather than a plain number",
+                expr, other
+            ),
+            span: expr.span(),
+            limit: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rpn, utils::span::Span, vm::compile_expression};
+    use pretty_assertions::assert_eq;
+
+    fn postfix(input: &str, variables: &[&str]) -> Result<String, CalcError> {
+        let mut env = Environment::new();
+        env.set("rate", Value::Number(0.5));
+        compile_expression(input, variables, &env).map(|compiled| compiled.to_string())
+    }
+
+    #[test]
+    fn test_same_order_as_rpn() {
+        for input in [
+            "3 + 4 * 2 ÷ ( 1 - 5 ) ^ 2 ^ 3",
+            "-2^2 + 1",
+            "max(1, sin(0), 3 + 4) * 2",
+        ] {
+            assert_eq!(
+                postfix(input, &[]),
+                Ok(rpn::to_postfix(input).unwrap().join(" "))
+            );
+        }
+    }
+
+    #[test]
+    fn test_variables_and_constants() {
+        assert_eq!(
+            postfix("x^2 * rate + y", &["x", "y"]),
+            Ok("x 2 ^ 0.5 * y +".to_string())
+        );
+        assert_eq!(
+            postfix("mean({x, 1}, 2..3) + 1 km/m", &["x"]),
+            Ok("x 1 2 3 mean/4 1000 +".to_string())
+        );
+        assert_eq!(
+            postfix("x + z", &["x"]),
+            Err(CalcError::at(
+                "Unknown variable or unit: z",
+                Span::new(4, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deep_expressions() {
+        // what is known of each part is worked out once, so these compile in linear time
+        let depth = 50_000;
+        let sum = format!("{}x{}", "(1+".repeat(depth), ")".repeat(depth));
+        let compiled = compile_expression(&sum, &["x"], &Environment::new()).unwrap();
+        assert_eq!(compiled.eval(&[0.5]), Ok(depth as f64 + 0.5));
+        let sines = format!("{}x{}", "sin(".repeat(depth), ")".repeat(depth));
+        let compiled = compile_expression(&sines, &["x"], &Environment::new()).unwrap();
+        let expected = (0..depth).fold(0.5_f64, |x, _| x.sin());
+        assert_eq!(compiled.eval(&[0.5]), Ok(expected));
+        // a name with a value deep down is still worked out once
+        let mut env = Environment::new();
+        env.set("a", Value::Number(2.0));
+        let sum = format!("{}(x+a){}", "(1+".repeat(depth), ")".repeat(depth));
+        let compiled = compile_expression(&sum, &["x"], &env).unwrap();
+        assert_eq!(compiled.eval(&[0.5]), Ok(depth as f64 + 2.5));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            postfix("1 + diff(x^3, x)", &["x"]),
+            Err(CalcError::at(
+                "Cannot compile diff(x^3, x): only arithmetic, builtins and statistics of x can be compiled",
+                Span::new(4, 16)
+            ))
+        );
+        assert_eq!(
+            postfix("x + 2 m", &["x"]),
+            Err(CalcError::at(
+                "Cannot compile 2*m, which is 2 m rather than a plain number",
+                Span::new(6, 7)
+            ))
+        );
+    }
+}
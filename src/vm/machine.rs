@@ -0,0 +1,307 @@
+use crate::{
+    ast::statistics,
+    utils::error::CalcError,
+    vm::{CompiledExpr, Instruction},
+};
+
+/// How many rows [`CompiledExpr::eval_batch`] works on at once, few enough to stay in cache.
+pub const CHUNK: usize = 1024;
+
+impl CompiledExpr {
+    /// Runs the expression with `values` for its variables, in the order of [`CompiledExpr::variables`].
+    pub fn eval(&self, values: &[f64]) -> Result<f64, CalcError> {
+        if values.len() != self.variables.len() {
+            return Err(CalcError::new(format!(
+                "Expected {} values for {}, found {}",
+                self.variables.len(),
+                self.variables.join(", "),
+                values.len()
+            )));
+        }
+        let mut stack: Vec<f64> = Vec::with_capacity(self.depth);
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Constant(value) => stack.push(value),
+                Instruction::Load(slot) => stack.push(values[slot]),
+                Instruction::Negate => {
+                    let top = stack.last_mut().unwrap();
+                    *top = 0.0 - *top;
+                }
+                Instruction::Builtin { apply, .. } => {
+                    let top = stack.last_mut().unwrap();
+                    *top = apply(*top);
+                }
+                Instruction::Statistic { name, count } => {
+                    let start = stack.len() - count;
+                    let value = statistics::summarise(name, &stack[start..])?;
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+                operation => {
+                    let rhs = stack.pop().unwrap();
+                    let lhs = stack.last_mut().unwrap();
+                    *lhs = match operation {
+                        Instruction::Add => *lhs + rhs,
+                        Instruction::Subtract => *lhs - rhs,
+                        Instruction::Multiply => *lhs * rhs,
+                        Instruction::Divide => *lhs / rhs,
+                        Instruction::Power => lhs.powf(rhs),
+                        _ => unreachable!(),
+                    };
+                }
+            }
+        }
+        Ok(stack[0])
+    }
+
+    /// Runs the expression once for each row of `columns`, which hold the values of each of
+    /// [`CompiledExpr::variables`] in turn, all of the same length.
+    ///
+    /// Rows are taken [`CHUNK`] at a time, and each instruction is one loop over the chunk. A row
+    /// where a statistic has no value, like the `stdev` of one number, comes to NaN. With no
+    /// variables there is one row.
+    pub fn eval_batch(&self, columns: &[&[f64]]) -> Result<Vec<f64>, CalcError> {
+        if columns.len() != self.variables.len() {
+            return Err(CalcError::new(format!(
+                "Expected {} columns for {}, found {}",
+                self.variables.len(),
+                self.variables.join(", "),
+                columns.len()
+            )));
+        }
+        let rows = columns.first().map_or(1, |column| column.len());
+        if let Some(column) = columns.iter().find(|column| column.len() != rows) {
+            return Err(CalcError::new(format!(
+                "Expected every column to have {} rows, found {}",
+                rows,
+                column.len()
+            )));
+        }
+
+        let mut stack = vec![vec![0.0; CHUNK]; self.depth];
+        let mut results = Vec::with_capacity(rows);
+        let mut row = Vec::new();
+        for start in (0..rows).step_by(CHUNK) {
+            let len = CHUNK.min(rows - start);
+            let mut top = 0;
+            for instruction in &self.instructions {
+                match *instruction {
+                    Instruction::Constant(value) => {
+                        stack[top][..len].fill(value);
+                        top += 1;
+                    }
+                    Instruction::Load(slot) => {
+                        stack[top][..len].copy_from_slice(&columns[slot][start..start + len]);
+                        top += 1;
+                    }
+                    Instruction::Negate => {
+                        for value in &mut stack[top - 1][..len] {
+                            *value = 0.0 - *value;
+                        }
+                    }
+                    Instruction::Builtin { apply, .. } => {
+                        for value in &mut stack[top - 1][..len] {
+                            *value = apply(*value);
+                        }
+                    }
+                    Instruction::Statistic { name, count } => {
+                        let first = top - count;
+                        for i in 0..len {
+                            row.clear();
+                            row.extend(stack[first..top].iter().map(|values| values[i]));
+                            stack[first][i] = statistics::summarise(name, &row).unwrap_or(f64::NAN);
+                        }
+                        top = first + 1;
+                    }
+                    operation => {
+                        let (below, above) = stack[..top].split_at_mut(top - 1);
+                        let lhs = &mut below[top - 2][..len];
+                        let rhs = &above[0][..len];
+                        let pairs = lhs.iter_mut().zip(rhs);
+                        match operation {
+                            Instruction::Add => pairs.for_each(|(lhs, rhs)| *lhs += rhs),
+                            Instruction::Subtract => pairs.for_each(|(lhs, rhs)| *lhs -= rhs),
+                            Instruction::Multiply => pairs.for_each(|(lhs, rhs)| *lhs *= rhs),
+                            Instruction::Divide => pairs.for_each(|(lhs, rhs)| *lhs /= rhs),
+                            Instruction::Power => {
+                                pairs.for_each(|(lhs, rhs)| *lhs = lhs.powf(*rhs))
+                            }
+                            _ => unreachable!(),
+                        }
+                        top -= 1;
+                    }
+                }
+            }
+            results.extend_from_slice(&stack[0][..len]);
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{ast::Node, environment::Environment, parse_expression, value::Value},
+        utils::operators::BinaryOperator,
+        vm::{compile::compile, compile_expression},
+    };
+    use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_eval() {
+        let compiled = compile_expression(
+            "x^2 + 3*x*y - sin(y) / (1 + x)",
+            &["x", "y"],
+            &Environment::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            compiled.eval(&[2.0, 0.0]),
+            Ok(2.0_f64.powf(2.0) + 3.0 * 2.0 * 0.0 - 0.0_f64.sin() / 3.0)
+        );
+        assert_eq!(
+            compiled.eval(&[1.0, 1.0]).unwrap(),
+            4.0 - 1.0_f64.sin() / 2.0
+        );
+        assert_eq!(
+            compiled.eval(&[1.0]),
+            Err(CalcError::new("Expected 2 values for x, y, found 1"))
+        );
+    }
+
+    #[test]
+    fn test_statistics() {
+        let compiled =
+            compile_expression("stdev(x, y, 4)", &["x", "y"], &Environment::new()).unwrap();
+        assert_eq!(compiled.eval(&[2.0, 3.0]), Ok(1.0));
+        let compiled = compile_expression("stdev(x)", &["x"], &Environment::new()).unwrap();
+        assert_eq!(
+            compiled.eval(&[2.0]),
+            Err(CalcError::new("stdev needs at least 2 values, found 1"))
+        );
+    }
+
+    #[test]
+    fn test_eval_batch() {
+        let compiled = compile_expression(
+            "x^2 + 3*x*y - sin(y) / (1 + x)",
+            &["x", "y"],
+            &Environment::new(),
+        )
+        .unwrap();
+        let xs: Vec<f64> = (0..2500).map(|i| f64::from(i) / 100.0).collect();
+        let ys: Vec<f64> = (0..2500).map(|i| f64::from(i % 7)).collect();
+        let expected: Vec<f64> = xs
+            .iter()
+            .zip(&ys)
+            .map(|(x, y)| compiled.eval(&[*x, *y]).unwrap())
+            .collect();
+        assert_eq!(compiled.eval_batch(&[&xs, &ys]), Ok(expected));
+        assert_eq!(compiled.eval_batch(&[&[], &[]]), Ok(vec![]));
+
+        let compiled = compile_expression("2 * 3", &[], &Environment::new()).unwrap();
+        assert_eq!(compiled.eval_batch(&[]), Ok(vec![6.0]));
+    }
+
+    #[test]
+    fn test_eval_batch_statistics() {
+        let compiled =
+            compile_expression("stdev(x, y) + mean(x)", &["x", "y"], &Environment::new()).unwrap();
+        assert_eq!(
+            compiled.eval_batch(&[&[1.0, 2.0], &[3.0, 2.0]]),
+            Ok(vec![2.0_f64.sqrt() + 1.0, 2.0])
+        );
+        let compiled = compile_expression("stdev(x)", &["x"], &Environment::new()).unwrap();
+        assert!(compiled.eval_batch(&[&[1.0]]).unwrap()[0].is_nan());
+    }
+
+    #[test]
+    fn test_eval_batch_uneven_columns() {
+        let compiled = compile_expression("x + y", &["x", "y"], &Environment::new()).unwrap();
+        assert_eq!(
+            compiled.eval_batch(&[&[1.0, 2.0], &[3.0]]),
+            Err(CalcError::new(
+                "Expected every column to have 2 rows, found 1"
+            ))
+        );
+        assert_eq!(
+            compiled.eval_batch(&[&[1.0, 2.0]]),
+            Err(CalcError::new("Expected 2 columns for x, y, found 1"))
+        );
+    }
+
+    fn arbitrary_node() -> impl Strategy<Value = Node> {
+        let leaf = prop_oneof![
+            (0u32..10).prop_map(|value| Node::Operand {
+                value: f64::from(value),
+            }),
+            Just(Node::Variable {
+                name: "x".to_string(),
+                span: Default::default(),
+            }),
+        ];
+        leaf.prop_recursive(6, 64, 2, |inner| {
+            prop_oneof![
+                (
+                    prop_oneof![
+                        Just(BinaryOperator::Addition),
+                        Just(BinaryOperator::Subtraction),
+                        Just(BinaryOperator::Multiplication),
+                        Just(BinaryOperator::Division),
+                        Just(BinaryOperator::Index),
+                    ],
+                    inner.clone(),
+                    inner.clone(),
+                )
+                    .prop_map(|(operation, lhs, rhs)| Node::binary(operation, lhs, rhs)),
+                inner.clone().prop_map(Node::negation),
+                inner.prop_map(|arg| Node::call("sin", vec![arg])),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn agrees_with_the_tree(node in arbitrary_node(), x in -10.0..10.0f64) {
+            let mut env = Environment::new();
+            env.set("x", Value::Number(x));
+            let expected = match node.calculate(&env) {
+                Ok(Value::Number(value)) => value,
+                other => panic!("{} gave {:?}", node, other),
+            };
+            let actual = compile(&node, &["x"], &Environment::new()).unwrap().eval(&[x]).unwrap();
+            prop_assert!(actual.to_bits() == expected.to_bits() || (actual.is_nan() && expected.is_nan()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn batch_agrees_with_eval(node in arbitrary_node(), xs in prop::collection::vec(-10.0..10.0f64, 0..50)) {
+            let compiled = compile(&node, &["x"], &Environment::new()).unwrap();
+            let batch = compiled.eval_batch(&[&xs]).unwrap();
+            prop_assert_eq!(batch.len(), xs.len());
+            for (actual, x) in batch.iter().zip(&xs) {
+                let expected = compiled.eval(&[*x]).unwrap();
+                prop_assert!(actual.to_bits() == expected.to_bits() || (actual.is_nan() && expected.is_nan()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsed_expression_agrees() {
+        let node = parse_expression("mean(x, 2x, 3) * 2^-x").unwrap();
+        let mut env = Environment::new();
+        env.set("x", Value::Number(1.5));
+        assert_eq!(
+            compile(&node, &["x"], &Environment::new())
+                .unwrap()
+                .eval(&[1.5]),
+            node.calculate(&env).map(|value| match value {
+                Value::Number(value) => value,
+                _ => unreachable!(),
+            })
+        );
+    }
+}
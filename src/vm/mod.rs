@@ -0,0 +1,92 @@
+use std::fmt;
+
+use crate::{
+    ast::{environment::Environment, parse_expression},
+    rpn::parse::call_token,
+    utils::{error::CalcError, operators::NEGATION},
+};
+
+pub mod compile;
+pub mod machine;
+
+/// One step of a compiled expression, which works on a stack of numbers.
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction {
+    Constant(f64),
+    /// Pushes the value given for a variable, by its place in [`CompiledExpr::variables`].
+    Load(usize),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    /// Flips the sign of the top of the stack.
+    Negate,
+    /// Replaces the top of the stack with a builtin of it.
+    Builtin {
+        name: &'static str,
+        apply: fn(f64) -> f64,
+    },
+    /// Replaces the top `count` values with a statistic of them.
+    Statistic {
+        name: &'static str,
+        count: usize,
+    },
+}
+
+/// An expression compiled to run over and over with different values for its variables,
+/// without walking the tree or looking names up each time.
+///
+/// The instructions are in the same postfix order that [`crate::rpn::parse`] puts tokens in.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    instructions: Vec<Instruction>,
+    variables: Vec<String>,
+    /// The most values on the stack at once, so that it can be allocated up front.
+    depth: usize,
+}
+
+impl CompiledExpr {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// The variables that values are given for, in the order they are given.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// The instructions as postfix tokens, as [`crate::rpn::to_postfix`] would write them.
+    pub fn postfix(&self) -> Vec<String> {
+        self.instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Constant(value) => value.to_string(),
+                Instruction::Load(slot) => self.variables[*slot].clone(),
+                Instruction::Add => "+".to_string(),
+                Instruction::Subtract => "-".to_string(),
+                Instruction::Multiply => "*".to_string(),
+                Instruction::Divide => "/".to_string(),
+                Instruction::Power => "^".to_string(),
+                Instruction::Negate => NEGATION.to_string(),
+                Instruction::Builtin { name, .. } => name.to_string(),
+                Instruction::Statistic { name, count } => call_token(name, *count),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for CompiledExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.postfix().join(" "))
+    }
+}
+
+/// Parses and compiles `input` with `variables` left to be given when it is run.
+pub fn compile_expression(
+    input: &str,
+    variables: &[&str],
+    env: &Environment,
+) -> Result<CompiledExpr, CalcError> {
+    compile::compile(&parse_expression(input)?, variables, env)
+}
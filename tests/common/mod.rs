@@ -0,0 +1,18 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// writes `contents` to a file of its own so tests running at once do not share it
+pub fn temp_file(extension: &str, contents: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "calculator-integration-test-{}-{}.{}",
+        std::process::id(),
+        count,
+        extension
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
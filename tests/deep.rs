@@ -0,0 +1,99 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+use common::temp_file;
+use pretty_assertions::assert_eq;
+
+mod common;
+
+const DEPTH: usize = 100_000;
+
+/// `1 + (1 + (... 1 ...))`, nested `DEPTH` times.
+fn deep_expression() -> String {
+    format!("{}1{}\n", "1+(".repeat(DEPTH), ")".repeat(DEPTH))
+}
+
+fn calculator(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn last_line(output: &Output) -> String {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().last().unwrap_or_default().to_string()
+}
+
+#[test]
+fn test_deep_script() {
+    let path = temp_file("calc", &deep_expression());
+    let script = path.to_str().unwrap();
+    for flags in [
+        &[][..],
+        &["--echo"],
+        &["--output", "latex"],
+        &["--output", "mathml"],
+    ] {
+        let output = calculator(&[flags, &["-f", script]].concat(), "");
+        assert!(output.status.success(), "{:?}: {:?}", flags, output);
+        if flags.is_empty() {
+            assert_eq!(last_line(&output), (DEPTH + 1).to_string());
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_deep_expression() {
+    let input = deep_expression();
+    for flags in [
+        &[][..],
+        &["--echo"],
+        &["--simplify"],
+        &["--trace"],
+        &["--show-tree", "--subtotals"],
+    ] {
+        let output = calculator(flags, &input);
+        assert!(output.status.success(), "{:?}: {:?}", flags, output);
+        assert_eq!(last_line(&output), (DEPTH + 1).to_string(), "{:?}", flags);
+    }
+    let output = calculator(&["--dot"], &input);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn test_deep_unknown() {
+    let sum = format!("{}x{}", "(x+".repeat(DEPTH), ")".repeat(DEPTH));
+    for (input, expected) in [
+        (format!("diff({}, x)\n", sum), (DEPTH + 1).to_string()),
+        (
+            format!("{} = 5\n", sum),
+            format!("x = {}", 5.0 / (DEPTH + 1) as f64),
+        ),
+        (
+            format!("solve({{{} = 5, x - y = 1}}, {{x, y}})\n", sum),
+            format!(
+                "x = {}, y = {}",
+                5.0 / (DEPTH + 1) as f64,
+                5.0 / (DEPTH + 1) as f64 - 1.0
+            ),
+        ),
+    ] {
+        let output = calculator(&[], &input);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(last_line(&output), expected);
+    }
+}